@@ -7,11 +7,13 @@ use bounce::{
     color::Color,
     geometry::{Point, Ray, Vec3},
     image::Image,
+    integrator::PathIntegrator,
     material::{Lambertian, Material},
     object::{
         bvh::{BvhTree, Primitive},
         Tri, Visible, VisibleList,
     },
+    sampler::Sampler,
     scene::Scene,
 };
 use rand::{prelude::StdRng, Rng, SeedableRng};
@@ -29,11 +31,11 @@ fn bench_sphere(c: &mut Criterion) {
 
     let mut image = Image::new(100, 100, Color::black());
     let samples_per_pixel = 100;
-    let max_depth = 50;
+    let integrator = PathIntegrator::new(50);
 
     c.bench_function("simple sphere", |b| {
         b.iter(|| {
-            scene.render(&mut image, samples_per_pixel, max_depth);
+            scene.render(&mut image, samples_per_pixel, &integrator);
         })
     });
 }
@@ -64,21 +66,21 @@ fn bench_bvh_vs_list(c: &mut Criterion) {
         let bvh = BvhTree::build(bvh_tris);
         let list = VisibleList::from_list(list_tris);
 
-        group.bench_with_input(BenchmarkId::new("BVH", num_tris), &num_tris, |b, i| {
-            let mut rng = StdRng::seed_from_u64(seed);
+        group.bench_with_input(BenchmarkId::new("BVH", num_tris), &num_tris, |b, _| {
+            let mut sampler = Sampler::new(seed);
 
             b.iter(|| {
-                let r = gen_ray(&cam, &mut rng);
+                let r = gen_ray(&cam, &mut sampler);
 
                 bvh.bounce(r, &(0.0..f64::INFINITY));
             })
         });
 
-        group.bench_with_input(BenchmarkId::new("list", num_tris), &num_tris, |b, i| {
-            let mut rng = StdRng::seed_from_u64(seed);
+        group.bench_with_input(BenchmarkId::new("list", num_tris), &num_tris, |b, _| {
+            let mut sampler = Sampler::new(seed);
 
             b.iter(|| {
-                let r = gen_ray(&cam, &mut rng);
+                let r = gen_ray(&cam, &mut sampler);
 
                 list.bounce(r, &(0.0..f64::INFINITY));
             })
@@ -88,6 +90,26 @@ fn bench_bvh_vs_list(c: &mut Criterion) {
     group.finish();
 }
 
+fn bench_bvh_build(c: &mut Criterion) {
+    let mut group = c.benchmark_group("bvh build");
+    group.sample_size(10);
+
+    let seed = 12391234;
+
+    for num_tris in [10_000, 100_000, 1_000_000].into_iter() {
+        let tris: Vec<Arc<dyn Primitive>> = populate_tris(num_tris, seed)
+            .into_iter()
+            .map(|p| Arc::new(p) as Arc<dyn Primitive>)
+            .collect();
+
+        group.bench_with_input(BenchmarkId::from_parameter(num_tris), &num_tris, |b, _| {
+            b.iter(|| BvhTree::build(tris.clone()))
+        });
+    }
+
+    group.finish();
+}
+
 fn make_camera() -> Camera {
     let look_from = Point::new(0.0, 0.0, -20.0);
     let look_at = Point::new(0.0, 0.0, 0.0);
@@ -103,11 +125,11 @@ fn make_camera() -> Camera {
     )
 }
 
-fn gen_ray(cam: &Camera, rng: &mut StdRng) -> Ray {
-    let u = rng.gen_range(0.0..1.0);
-    let v = rng.gen_range(0.0..1.0);
+fn gen_ray(cam: &Camera, sampler: &mut Sampler) -> Ray {
+    let u = sampler.next_f64();
+    let v = sampler.next_f64();
 
-    cam.ray_at(u, v)
+    cam.ray_at(u, v, sampler)
 }
 
 fn populate_tris(amount: u32, seed: u64) -> Vec<Tri> {
@@ -150,5 +172,5 @@ fn populate_tris(amount: u32, seed: u64) -> Vec<Tri> {
     prims
 }
 
-criterion_group!(benches, bench_bvh_vs_list, bench_sphere);
+criterion_group!(benches, bench_bvh_vs_list, bench_bvh_build, bench_sphere);
 criterion_main!(benches);
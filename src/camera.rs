@@ -1,6 +1,9 @@
-use rand::{thread_rng, Rng};
+use std::ops::Range;
 
-use crate::geometry::{Point, Ray, Vec3};
+use crate::{
+    geometry::{Point, Ray, Vec3},
+    sampler::Sampler,
+};
 
 pub struct Camera {
     origin: Point<f64>,
@@ -12,6 +15,9 @@ pub struct Camera {
     lens_radius: f64,
     vertical_axis: Vec3<f64>,
     horizontal_axis: Vec3<f64>,
+
+    // used for motion blur
+    shutter: Range<f64>,
 }
 
 impl Camera {
@@ -50,27 +56,45 @@ impl Camera {
             lens_radius: aperture / 2.0,
             vertical_axis: unit_vertical,
             horizontal_axis: unit_horizontal,
+            shutter: 0.0..0.0,
         }
     }
 
-    pub fn ray_at(&self, u: f64, v: f64) -> Ray {
-        let random_on_lens = Camera::random_in_unit_disk() * self.lens_radius;
+    /// Opens the shutter over `open..close`, giving each ray a random time in it. Things that
+    /// move while the shutter is open are blurred along their path.
+    pub fn set_shutter(&mut self, open: f64, close: f64) {
+        assert!(open <= close, "the shutter must open before it closes");
+        self.shutter = open..close;
+    }
+
+    pub fn shutter(&self) -> Range<f64> {
+        self.shutter.clone()
+    }
+
+    pub fn ray_at(&self, u: f64, v: f64, sampler: &mut Sampler) -> Ray {
+        let random_on_lens = Camera::random_in_unit_disk(sampler) * self.lens_radius;
         let offset =
             self.horizontal_axis * random_on_lens.x() + self.vertical_axis * random_on_lens.y();
 
-        Ray::new(
+        // an instant shutter draws no random number, so still renders are unaffected by it
+        let time = if self.shutter.is_empty() {
+            self.shutter.start
+        } else {
+            sampler.range(self.shutter.start, self.shutter.end)
+        };
+
+        Ray::with_time(
             self.origin + offset.into(),
             self.horizontal * u + self.vertical * v + self.lower_left_corner.into()
                 - self.origin.into()
-                - offset.into(),
+                - offset,
+            time,
         )
     }
 
-    fn random_in_unit_disk() -> Vec3<f64> {
-        let mut rng = thread_rng();
-
+    fn random_in_unit_disk(sampler: &mut Sampler) -> Vec3<f64> {
         loop {
-            let cand = Vec3::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.0);
+            let cand = Vec3::new(sampler.range(-1.0, 1.0), sampler.range(-1.0, 1.0), 0.0);
 
             if cand.len_sq() < 1.0 {
                 return cand;
@@ -67,15 +67,18 @@ fn clamp<T: PartialOrd>(x: T, min: T, max: T) -> T {
     x
 }
 
+impl Color {
+    /// Quantizes the color to 8 bits per channel, clamping components to `[0, 1)`
+    pub fn to_rgb8(&self) -> [u8; 3] {
+        let quantize = |c: f64| (clamp(c, 0.0, 0.999) * 256.0) as u8;
+
+        [quantize(self.r()), quantize(self.g()), quantize(self.b())]
+    }
+}
+
 impl Display for Color {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        let r = clamp(self.r(), 0.0, 0.999);
-        let g = clamp(self.g(), 0.0, 0.999);
-        let b = clamp(self.b(), 0.0, 0.999);
-
-        let r = (r * 256.0) as u8;
-        let g = (g * 256.0) as u8;
-        let b = (b * 256.0) as u8;
+        let [r, g, b] = self.to_rgb8();
 
         write!(f, "{} {} {}", r, g, b)
     }
@@ -0,0 +1,442 @@
+use std::ops::Range;
+
+use super::{transform::Matrix, Point, Transform, Vec3};
+
+/// A transform that moves from `start` to `end` over a time range, for motion blur.
+///
+/// Both ends are split into a translation, a rotation and a scale, which are interpolated
+/// separately so that rotating objects turn rather than shear. The rotation takes the shortest
+/// way between the two ends, so a spin of more than half a turn needs more than one segment.
+/// Before and after the time range the transform holds still.
+#[derive(Debug, Clone)]
+pub struct AnimatedTransform {
+    start: Transform,
+    end: Transform,
+    time: Range<f64>,
+    parts: Option<[Parts; 2]>,
+}
+
+/// An affine transform as a translation applied after a rotation applied after a scale
+#[derive(Debug, Clone)]
+struct Parts {
+    translation: Vec3<f64>,
+    rotation: Quaternion,
+    /// Symmetric, so it may also stretch along directions other than the axes
+    scale: [[f64; 3]; 3],
+}
+
+impl AnimatedTransform {
+    /// Either both or neither of `start` and `end` may mirror, as one can't turn into the other
+    /// without flattening everything on the way.
+    pub fn new(start: Transform, end: Transform, time: Range<f64>) -> Self {
+        let parts = if start == end {
+            None
+        } else {
+            assert!(
+                start.determinant() * end.determinant() > 0.0,
+                "only one end of the motion mirrors"
+            );
+            Some([Parts::of(&start), Parts::of(&end)])
+        };
+
+        Self {
+            start,
+            end,
+            time,
+            parts,
+        }
+    }
+
+    /// A transform that doesn't move
+    pub fn fixed(transform: Transform) -> Self {
+        Self::new(transform.clone(), transform, 0.0..0.0)
+    }
+
+    pub fn is_moving(&self) -> bool {
+        self.parts.is_some()
+    }
+
+    pub fn start(&self) -> &Transform {
+        &self.start
+    }
+
+    pub fn end(&self) -> &Transform {
+        &self.end
+    }
+
+    pub fn at(&self, time: f64) -> Transform {
+        let [start, end] = match &self.parts {
+            None => return self.start.clone(),
+            Some(parts) => parts,
+        };
+
+        let frac = if self.time.end > self.time.start {
+            ((time - self.time.start) / (self.time.end - self.time.start)).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        if frac == 0.0 {
+            return self.start.clone();
+        } else if frac == 1.0 {
+            return self.end.clone();
+        }
+
+        let translation = start.translation * (1.0 - frac) + end.translation * frac;
+        let rotation = start.rotation.slerp(&end.rotation, frac).to_matrix();
+        let mut scale = [[0.0; 3]; 3];
+        for (i, row) in scale.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = start.scale[i][j] * (1.0 - frac) + end.scale[i][j] * frac;
+            }
+        }
+
+        let linear = mul3(&rotation, &scale);
+        let t = [translation.x(), translation.y(), translation.z()];
+        let mut m = [[0.0, 0.0, 0.0, 1.0]; 4];
+        for i in 0..3 {
+            m[i] = [linear[i][0], linear[i][1], linear[i][2], t[i]];
+        }
+
+        Transform::from_affine(m)
+    }
+
+    /// Bounds `points` over the whole motion
+    pub fn bound(&self, points: &[Point<f64>]) -> (Point<f64>, Point<f64>) {
+        const STEPS: usize = 64;
+
+        let steps = if self.is_moving() { STEPS } else { 0 };
+        let mut min = Vec3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+        let mut max = -min;
+        let mut previous: Option<Vec<Point<f64>>> = None;
+        let mut max_step = 0.0;
+
+        for step in 0..=steps {
+            let time = if steps == 0 {
+                self.time.start
+            } else {
+                self.time.start + (self.time.end - self.time.start) * step as f64 / steps as f64
+            };
+            let transform = self.at(time);
+            let moved: Vec<_> = points.iter().map(|&p| transform.point(p)).collect();
+
+            for p in &moved {
+                let p = Vec3::from(*p);
+                min = Vec3::new(min.x().min(p.x()), min.y().min(p.y()), min.z().min(p.z()));
+                max = Vec3::new(max.x().max(p.x()), max.y().max(p.y()), max.z().max(p.z()));
+            }
+
+            if let Some(previous) = previous {
+                for (a, b) in previous.iter().zip(&moved) {
+                    max_step = f64::max(max_step, Vec3::from(*b - *a).len());
+                }
+            }
+            previous = Some(moved);
+        }
+
+        // between two steps the points travel along curves that stray from a straight line by
+        // far less than the length of the step
+        let pad = Vec3::new(max_step, max_step, max_step) / 2.0;
+
+        ((min - pad).into(), (max + pad).into())
+    }
+}
+
+impl Parts {
+    fn of(transform: &Transform) -> Self {
+        let m = transform.matrix();
+        let translation = Vec3::new(m[0][3], m[1][3], m[2][3]);
+        let linear = [
+            [m[0][0], m[0][1], m[0][2]],
+            [m[1][0], m[1][1], m[1][2]],
+            [m[2][0], m[2][1], m[2][2]],
+        ];
+
+        // polar decomposition: averaging a matrix with its inverse transpose converges to the
+        // closest rotation (or reflection)
+        let mut rotation = linear;
+        for _ in 0..100 {
+            let inv_t = transpose3(&inverse3(&rotation));
+            let mut next = rotation;
+            let mut change: f64 = 0.0;
+
+            for i in 0..3 {
+                for j in 0..3 {
+                    next[i][j] = 0.5 * (rotation[i][j] + inv_t[i][j]);
+                    change = change.max((next[i][j] - rotation[i][j]).abs());
+                }
+            }
+
+            rotation = next;
+            if change < 1e-12 {
+                break;
+            }
+        }
+
+        // a mirrored transform keeps its mirroring in the scale, so the rotation stays proper.
+        // The scale is then negative definite, and stays so when interpolated towards another
+        // mirrored one, just like unmirrored scales stay positive definite.
+        if det3(&rotation) < 0.0 {
+            for row in &mut rotation {
+                for value in row.iter_mut() {
+                    *value = -*value;
+                }
+            }
+        }
+
+        let scale = mul3(&transpose3(&rotation), &linear);
+
+        Self {
+            translation,
+            rotation: Quaternion::from_matrix(&rotation),
+            scale,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Quaternion {
+    w: f64,
+    x: f64,
+    y: f64,
+    z: f64,
+}
+
+impl Quaternion {
+    fn from_matrix(m: &[[f64; 3]; 3]) -> Self {
+        let trace = m[0][0] + m[1][1] + m[2][2];
+
+        // pick the largest component to divide by, for precision
+        let q = if trace > 0.0 {
+            let s = (trace + 1.0).sqrt() * 2.0;
+            Quaternion {
+                w: s / 4.0,
+                x: (m[2][1] - m[1][2]) / s,
+                y: (m[0][2] - m[2][0]) / s,
+                z: (m[1][0] - m[0][1]) / s,
+            }
+        } else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+            let s = (1.0 + m[0][0] - m[1][1] - m[2][2]).sqrt() * 2.0;
+            Quaternion {
+                w: (m[2][1] - m[1][2]) / s,
+                x: s / 4.0,
+                y: (m[0][1] + m[1][0]) / s,
+                z: (m[0][2] + m[2][0]) / s,
+            }
+        } else if m[1][1] > m[2][2] {
+            let s = (1.0 + m[1][1] - m[0][0] - m[2][2]).sqrt() * 2.0;
+            Quaternion {
+                w: (m[0][2] - m[2][0]) / s,
+                x: (m[0][1] + m[1][0]) / s,
+                y: s / 4.0,
+                z: (m[1][2] + m[2][1]) / s,
+            }
+        } else {
+            let s = (1.0 + m[2][2] - m[0][0] - m[1][1]).sqrt() * 2.0;
+            Quaternion {
+                w: (m[1][0] - m[0][1]) / s,
+                x: (m[0][2] + m[2][0]) / s,
+                y: (m[1][2] + m[2][1]) / s,
+                z: s / 4.0,
+            }
+        };
+
+        q.normalized()
+    }
+
+    fn dot(&self, other: &Quaternion) -> f64 {
+        self.w * other.w + self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    fn scaled(&self, s: f64) -> Quaternion {
+        Quaternion {
+            w: self.w * s,
+            x: self.x * s,
+            y: self.y * s,
+            z: self.z * s,
+        }
+    }
+
+    fn plus(&self, other: &Quaternion) -> Quaternion {
+        Quaternion {
+            w: self.w + other.w,
+            x: self.x + other.x,
+            y: self.y + other.y,
+            z: self.z + other.z,
+        }
+    }
+
+    fn normalized(&self) -> Quaternion {
+        self.scaled(1.0 / self.dot(self).sqrt())
+    }
+
+    /// Spherical interpolation along the shorter arc
+    fn slerp(&self, other: &Quaternion, frac: f64) -> Quaternion {
+        let mut cos = self.dot(other);
+        let mut other = *other;
+        if cos < 0.0 {
+            cos = -cos;
+            other = other.scaled(-1.0);
+        }
+
+        if cos > 0.9995 {
+            // nearly parallel, where plain interpolation is accurate and slerp unstable
+            return self
+                .scaled(1.0 - frac)
+                .plus(&other.scaled(frac))
+                .normalized();
+        }
+
+        let theta = cos.acos();
+        let sin = theta.sin();
+
+        self.scaled(((1.0 - frac) * theta).sin() / sin)
+            .plus(&other.scaled((frac * theta).sin() / sin))
+    }
+
+    fn to_matrix(self) -> [[f64; 3]; 3] {
+        let Quaternion { w, x, y, z } = self;
+
+        [
+            [
+                1.0 - 2.0 * (y * y + z * z),
+                2.0 * (x * y - w * z),
+                2.0 * (x * z + w * y),
+            ],
+            [
+                2.0 * (x * y + w * z),
+                1.0 - 2.0 * (x * x + z * z),
+                2.0 * (y * z - w * x),
+            ],
+            [
+                2.0 * (x * z - w * y),
+                2.0 * (y * z + w * x),
+                1.0 - 2.0 * (x * x + y * y),
+            ],
+        ]
+    }
+}
+
+fn mul3(a: &[[f64; 3]; 3], b: &[[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    let mut out = [[0.0; 3]; 3];
+
+    for (i, row) in out.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+
+    out
+}
+
+fn transpose3(m: &[[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    let mut out = [[0.0; 3]; 3];
+
+    for (i, row) in out.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = m[j][i];
+        }
+    }
+
+    out
+}
+
+fn det3(m: &[[f64; 3]; 3]) -> f64 {
+    m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+        - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+        + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+}
+
+fn inverse3(m: &[[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    let mut affine: Matrix = [[0.0, 0.0, 0.0, 1.0]; 4];
+    for i in 0..3 {
+        affine[i] = [m[i][0], m[i][1], m[i][2], 0.0];
+    }
+
+    let inv = Transform::from_affine(affine).inverse();
+    let inv = inv.matrix();
+
+    [
+        [inv[0][0], inv[0][1], inv[0][2]],
+        [inv[1][0], inv[1][1], inv[1][2]],
+        [inv[2][0], inv[2][1], inv[2][2]],
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(a: Point<f64>, b: Point<f64>) {
+        assert!(Vec3::from(a - b).len() < 1e-9, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn ends_match_keys() {
+        let start = Transform::scale(Vec3::new(-2.0, 1.0, 1.0))
+            .then(&Transform::rotate(Vec3::new(1.0, 2.0, 0.5), 20.0))
+            .then(&Transform::translate(Vec3::new(1.0, 0.0, -3.0)));
+        let end = Transform::scale(Vec3::new(-1.0, 1.0, 3.0))
+            .then(&Transform::rotate(Vec3::new(0.0, 1.0, 0.0), 150.0));
+        let animated = AnimatedTransform::new(start.clone(), end.clone(), 2.0..4.0);
+        let p = Point::new(0.3, -0.7, 1.1);
+
+        for (time, key) in [(1.0, &start), (2.0, &start), (4.0, &end), (9.0, &end)] {
+            assert_near(animated.at(time).point(p), key.point(p));
+        }
+
+        // the decomposed parts rebuild the key when interpolated right next to it
+        assert_near(animated.at(2.0 + 1e-12).point(p), start.point(p));
+        assert_near(animated.at(4.0 - 1e-12).point(p), end.point(p));
+    }
+
+    #[test]
+    fn mirrored_motion_stays_invertible() {
+        // flipping one axis while growing along another
+        let animated = AnimatedTransform::new(
+            Transform::scale(Vec3::new(2.0, -2.0, 2.0)),
+            Transform::scale(Vec3::new(-1.0, 1.0, 1.0))
+                .then(&Transform::rotate(Vec3::new(0.0, 0.0, 1.0), 90.0)),
+            0.0..1.0,
+        );
+        let p = Point::new(0.3, -0.7, 1.1);
+
+        for time in [0.25, 0.5, 0.75] {
+            let transform = animated.at(time);
+            assert!(transform.determinant() < 0.0);
+            assert_near(transform.inverse().point(transform.point(p)), p);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "mirrors")]
+    fn motion_cant_unmirror() {
+        AnimatedTransform::new(
+            Transform::scale(Vec3::new(2.0, 2.0, 2.0)),
+            Transform::scale(Vec3::new(-1.0, 1.0, 1.0)),
+            0.0..1.0,
+        );
+    }
+
+    #[test]
+    fn spins_about_axis() {
+        let axis = Vec3::new(0.0, 0.0, 1.0);
+        let animated = AnimatedTransform::new(
+            Transform::identity(),
+            Transform::rotate(axis, 90.0),
+            0.0..1.0,
+        );
+        let p = Point::new(1.0, 0.0, 0.0);
+
+        // turning rather than cutting across, so points stay at the same distance
+        let halfway = animated.at(0.5);
+        let expected = Transform::rotate(axis, 45.0).point(p);
+        assert_near(halfway.point(p), expected);
+        assert_near(halfway.inverse().point(expected), p);
+
+        let (min, max) = animated.bound(&[p]);
+        assert!(max.x() >= 1.0 && max.y() >= 1.0);
+        assert!(min.x() <= 0.0 && min.y() <= 0.0);
+        // the arc bulges out to the 45 degree point
+        assert!(max.x() + max.y() >= 2.0_f64.sqrt());
+    }
+}
@@ -1,8 +1,12 @@
+mod animated;
 mod macros;
 mod point;
 mod ray;
+mod transform;
 mod vec3;
 
+pub use animated::*;
 pub use point::*;
 pub use ray::*;
+pub use transform::*;
 pub use vec3::*;
@@ -4,11 +4,21 @@ use super::{Point, Vec3};
 pub struct Ray {
     origin: Point<f64>,
     direction: Vec3<f64>,
+    /// When the ray was cast, which decides where moving objects are
+    time: f64,
 }
 
 impl Ray {
     pub fn new(origin: Point<f64>, direction: Vec3<f64>) -> Ray {
-        Ray { origin, direction }
+        Ray::with_time(origin, direction, 0.0)
+    }
+
+    pub fn with_time(origin: Point<f64>, direction: Vec3<f64>, time: f64) -> Ray {
+        Ray {
+            origin,
+            direction,
+            time,
+        }
     }
 
     pub fn origin(self) -> Point<f64> {
@@ -19,6 +29,10 @@ impl Ray {
         self.direction
     }
 
+    pub fn time(self) -> f64 {
+        self.time
+    }
+
     pub fn at(self, t: f64) -> Point<f64> {
         self.origin + (self.direction * t).into()
     }
@@ -0,0 +1,350 @@
+use super::{Point, Ray, Vec3};
+
+pub(super) type Matrix = [[f64; 4]; 4];
+
+const IDENTITY: Matrix = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+/// An affine transform, stored as a 4x4 matrix along with its inverse so both directions are
+/// equally cheap to apply.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transform {
+    m: Matrix,
+    inv: Matrix,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl Transform {
+    pub fn identity() -> Self {
+        Self {
+            m: IDENTITY,
+            inv: IDENTITY,
+        }
+    }
+
+    pub fn translate(offset: Vec3<f64>) -> Self {
+        let (x, y, z) = (offset.x(), offset.y(), offset.z());
+
+        Self {
+            m: [
+                [1.0, 0.0, 0.0, x],
+                [0.0, 1.0, 0.0, y],
+                [0.0, 0.0, 1.0, z],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+            inv: [
+                [1.0, 0.0, 0.0, -x],
+                [0.0, 1.0, 0.0, -y],
+                [0.0, 0.0, 1.0, -z],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        }
+    }
+
+    /// Scales each axis by the matching component of `factors`, none of which may be zero
+    pub fn scale(factors: Vec3<f64>) -> Self {
+        let (x, y, z) = (factors.x(), factors.y(), factors.z());
+        assert!(
+            x != 0.0 && y != 0.0 && z != 0.0,
+            "scale factors must be non-zero"
+        );
+
+        Self {
+            m: [
+                [x, 0.0, 0.0, 0.0],
+                [0.0, y, 0.0, 0.0],
+                [0.0, 0.0, z, 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+            inv: [
+                [1.0 / x, 0.0, 0.0, 0.0],
+                [0.0, 1.0 / y, 0.0, 0.0],
+                [0.0, 0.0, 1.0 / z, 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        }
+    }
+
+    /// Rotates counter-clockwise by `degrees` about `axis` (looking down the axis towards the
+    /// origin)
+    pub fn rotate(axis: Vec3<f64>, degrees: f64) -> Self {
+        let a = axis.unit();
+        let (x, y, z) = (a.x(), a.y(), a.z());
+        let (sin, cos) = degrees.to_radians().sin_cos();
+
+        // Rodrigues' rotation formula
+        let m = [
+            [
+                cos + x * x * (1.0 - cos),
+                x * y * (1.0 - cos) - z * sin,
+                x * z * (1.0 - cos) + y * sin,
+                0.0,
+            ],
+            [
+                y * x * (1.0 - cos) + z * sin,
+                cos + y * y * (1.0 - cos),
+                y * z * (1.0 - cos) - x * sin,
+                0.0,
+            ],
+            [
+                z * x * (1.0 - cos) - y * sin,
+                z * y * (1.0 - cos) + x * sin,
+                cos + z * z * (1.0 - cos),
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ];
+
+        // rotations are orthogonal, so the inverse is the transpose
+        Self {
+            m,
+            inv: transpose(&m),
+        }
+    }
+
+    /// Moves the origin to `from` and turns the -z axis to face `at`, with the y axis as close to
+    /// `up` as possible. This is the same orientation `Camera` uses for its view.
+    pub fn look_at(from: Point<f64>, at: Point<f64>, up: Vec3<f64>) -> Self {
+        let z = Vec3::from(from - at).unit();
+        let x = up.cross(z).unit();
+        let y = z.cross(x);
+
+        let rotation = [
+            [x.x(), y.x(), z.x(), 0.0],
+            [x.y(), y.y(), z.y(), 0.0],
+            [x.z(), y.z(), z.z(), 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ];
+        let orient = Self {
+            m: rotation,
+            inv: transpose(&rotation),
+        };
+
+        orient.then(&Self::translate(from.into()))
+    }
+
+    /// The transform that applies `self` first and then `next`
+    pub fn then(&self, next: &Transform) -> Self {
+        Self {
+            m: mul(&next.m, &self.m),
+            inv: mul(&self.inv, &next.inv),
+        }
+    }
+
+    /// Wraps an affine matrix, whose bottom row must be `[0, 0, 0, 1]` and whose top-left 3x3
+    /// part must be invertible
+    pub(super) fn from_affine(m: Matrix) -> Self {
+        let a = |i: usize, j: usize| m[i][j];
+        let det = a(0, 0) * (a(1, 1) * a(2, 2) - a(1, 2) * a(2, 1))
+            - a(0, 1) * (a(1, 0) * a(2, 2) - a(1, 2) * a(2, 0))
+            + a(0, 2) * (a(1, 0) * a(2, 1) - a(1, 1) * a(2, 0));
+        let inv_det = 1.0 / det;
+
+        // inverse of the linear part from its adjugate, then undo the translation with it
+        let mut inv = IDENTITY;
+        for (i, row) in inv.iter_mut().take(3).enumerate() {
+            for (j, value) in row.iter_mut().take(3).enumerate() {
+                let (r0, r1) = ((j + 1) % 3, (j + 2) % 3);
+                let (c0, c1) = ((i + 1) % 3, (i + 2) % 3);
+                *value = (a(r0, c0) * a(r1, c1) - a(r0, c1) * a(r1, c0)) * inv_det;
+            }
+            row[3] = -(0..3).map(|k| row[k] * m[k][3]).sum::<f64>();
+        }
+
+        Self { m, inv }
+    }
+
+    pub(super) fn matrix(&self) -> &Matrix {
+        &self.m
+    }
+
+    pub fn inverse(&self) -> Self {
+        Self {
+            m: self.inv,
+            inv: self.m,
+        }
+    }
+
+    pub fn point(&self, p: Point<f64>) -> Point<f64> {
+        let m = &self.m;
+        let (x, y, z) = (p.x(), p.y(), p.z());
+
+        Point::new(
+            m[0][0] * x + m[0][1] * y + m[0][2] * z + m[0][3],
+            m[1][0] * x + m[1][1] * y + m[1][2] * z + m[1][3],
+            m[2][0] * x + m[2][1] * y + m[2][2] * z + m[2][3],
+        )
+    }
+
+    /// Transforms a direction, which unlike a point is not affected by translation
+    pub fn vector(&self, v: Vec3<f64>) -> Vec3<f64> {
+        let m = &self.m;
+        let (x, y, z) = (v.x(), v.y(), v.z());
+
+        Vec3::new(
+            m[0][0] * x + m[0][1] * y + m[0][2] * z,
+            m[1][0] * x + m[1][1] * y + m[1][2] * z,
+            m[2][0] * x + m[2][1] * y + m[2][2] * z,
+        )
+    }
+
+    /// Transforms a surface normal so it stays perpendicular to the transformed surface. The
+    /// result is not normalized.
+    pub fn normal(&self, n: Vec3<f64>) -> Vec3<f64> {
+        // normals transform by the inverse transpose
+        let inv = &self.inv;
+        let (x, y, z) = (n.x(), n.y(), n.z());
+
+        Vec3::new(
+            inv[0][0] * x + inv[1][0] * y + inv[2][0] * z,
+            inv[0][1] * x + inv[1][1] * y + inv[2][1] * z,
+            inv[0][2] * x + inv[1][2] * y + inv[2][2] * z,
+        )
+    }
+
+    /// Transforms the origin and direction of `r`, keeping its time. The direction is not
+    /// normalized, so a point at distance `t` along `r` ends up at distance `t` along the
+    /// transformed ray.
+    pub fn ray(&self, r: Ray) -> Ray {
+        Ray::with_time(self.point(r.origin()), self.vector(r.direction()), r.time())
+    }
+
+    /// The factor by which surface areas are scaled, when it's the same for every surface. That's
+    /// the case for rotations, reflections, translations and uniform scales, but not for other
+    /// scales, which stretch differently oriented surfaces by different amounts.
+    pub fn area_scale(&self) -> Option<f64> {
+        // the linear part is a uniform scale times a rotation or a reflection exactly when its
+        // columns are orthogonal and equally long
+        let m = &self.m;
+        let [x, y, z] = [0, 1, 2].map(|j| Vec3::new(m[0][j], m[1][j], m[2][j]));
+        let len_sq = x.len_sq();
+        let near = |value: f64| value.abs() <= 1e-9 * len_sq;
+
+        let uniform = near(y.len_sq() - len_sq)
+            && near(z.len_sq() - len_sq)
+            && near(x.dot(y))
+            && near(y.dot(z))
+            && near(z.dot(x));
+
+        uniform.then_some(len_sq)
+    }
+
+    /// Determinant of the linear part, the factor by which volumes are scaled
+    pub fn determinant(&self) -> f64 {
+        let m = &self.m;
+
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+}
+
+fn mul(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut out = [[0.0; 4]; 4];
+
+    for (i, row) in out.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+
+    out
+}
+
+fn transpose(m: &Matrix) -> Matrix {
+    let mut out = [[0.0; 4]; 4];
+
+    for (i, row) in out.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = m[j][i];
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(a: Vec3<f64>, b: Vec3<f64>) {
+        assert!((a - b).len() < 1e-9, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn rotate_quarter_turn() {
+        let t = Transform::rotate(Vec3::new(0.0, 0.0, 1.0), 90.0);
+
+        assert_near(t.vector(Vec3::new(1.0, 0.0, 0.0)), Vec3::new(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn compose_and_invert() {
+        let t = Transform::scale(Vec3::new(2.0, 1.0, 0.5))
+            .then(&Transform::rotate(Vec3::new(1.0, 1.0, 0.0), 30.0))
+            .then(&Transform::translate(Vec3::new(1.0, -2.0, 3.0)));
+        let p = Point::new(0.3, -1.5, 2.0);
+
+        let there = t.point(p);
+        assert_near(
+            there.into(),
+            Vec3::new(1.0, -2.0, 3.0)
+                + Transform::rotate(Vec3::new(1.0, 1.0, 0.0), 30.0)
+                    .vector(Vec3::new(0.6, -1.5, 1.0)),
+        );
+        assert_near(t.inverse().point(there).into(), p.into());
+    }
+
+    #[test]
+    fn normals_stay_perpendicular() {
+        let t = Transform::scale(Vec3::new(4.0, 1.0, 1.0))
+            .then(&Transform::rotate(Vec3::new(0.0, 1.0, 0.0), 45.0));
+
+        // a plane tilted in x and y, with a tangent along it
+        let normal = Vec3::new(1.0, 1.0, 0.0);
+        let tangent = Vec3::new(1.0, -1.0, 0.0);
+
+        assert!(t.normal(normal).dot(t.vector(tangent)).abs() < 1e-9);
+        assert!((t.determinant() - 4.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn area_scale_of_uniform_scales() {
+        let turned = Transform::rotate(Vec3::new(1.0, 2.0, 3.0), 40.0)
+            .then(&Transform::translate(Vec3::new(5.0, 0.0, 0.0)));
+
+        let uniform = Transform::scale(Vec3::new(2.0, -2.0, 2.0)).then(&turned);
+        assert!((uniform.area_scale().unwrap() - 4.0).abs() < 1e-9);
+        assert_eq!(Transform::identity().area_scale(), Some(1.0));
+
+        let stretched = Transform::scale(Vec3::new(2.0, 1.0, 1.0)).then(&turned);
+        assert_eq!(stretched.area_scale(), None);
+        // a uniform scale after a rotation still shears
+        let sheared = turned.then(&Transform::scale(Vec3::new(1.0, 3.0, 1.0)));
+        assert_eq!(sheared.area_scale(), None);
+    }
+
+    #[test]
+    fn look_at_faces_target() {
+        let from = Point::new(1.0, 2.0, 3.0);
+        let at = Point::new(-2.0, 0.0, 1.0);
+        let t = Transform::look_at(from, at, Vec3::new(0.0, 1.0, 0.0));
+
+        assert_near(t.point(Point::new(0.0, 0.0, 0.0)).into(), from.into());
+        assert_near(
+            t.vector(Vec3::new(0.0, 0.0, -1.0)),
+            Vec3::from(at - from).unit(),
+        );
+        assert!(t.vector(Vec3::new(0.0, 1.0, 0.0)).y() > 0.0);
+        assert_near(t.inverse().point(from).into(), Vec3::new(0.0, 0.0, 0.0));
+    }
+}
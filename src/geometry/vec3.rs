@@ -1,6 +1,6 @@
 use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
 
-use rand::{thread_rng, Rng};
+use crate::sampler::Sampler;
 
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Vec3<T> {
@@ -64,12 +64,12 @@ impl Vec3<f64> {
 
     /// Reflects the vector by a surface given by its normal vector
     pub fn reflect(&self, normal: Self) -> Self {
-        self.clone() - normal * 2.0 * self.dot(normal)
+        *self - normal * 2.0 * self.dot(normal)
     }
 
     /// Refracts the vector (should be unit) by the IOR ratio eta_ratio on the normal
     pub fn refract(&self, normal: Vec3<f64>, eta_ratio: f64) -> Vec3<f64> {
-        let v = self.clone();
+        let v = *self;
         let cos_theta = normal.dot(-v).min(1.0);
         let perpendicular = eta_ratio * (v + cos_theta * normal);
         let parallel = -((1.0 - perpendicular.len_sq()).abs().sqrt()) * normal;
@@ -78,13 +78,11 @@ impl Vec3<f64> {
     }
 
     /// Generates a random vector where all components are in the half-open range [min, max)
-    pub fn random(min: f64, max: f64) -> Self {
-        let mut rng = thread_rng();
-
+    pub fn random(sampler: &mut Sampler, min: f64, max: f64) -> Self {
         Vec3::new(
-            rng.gen_range(min..max),
-            rng.gen_range(min..max),
-            rng.gen_range(min..max),
+            sampler.range(min, max),
+            sampler.range(min, max),
+            sampler.range(min, max),
         )
     }
 
@@ -95,10 +93,10 @@ impl Vec3<f64> {
     }
 
     /// Generate a random vector that lies in the unit-radius sphere
-    pub fn random_in_unit_sphere() -> Self {
+    pub fn random_in_unit_sphere(sampler: &mut Sampler) -> Self {
         // keep generating vectors until they lie in the unit radius sphere (and not the surrounding cube)
         loop {
-            let cand = Vec3::random(-1.0, 1.0);
+            let cand = Vec3::random(sampler, -1.0, 1.0);
 
             if cand.len_sq() <= 1.0 {
                 return cand;
@@ -106,8 +104,8 @@ impl Vec3<f64> {
         }
     }
 
-    pub fn random_unit() -> Self {
-        Self::random_in_unit_sphere().unit()
+    pub fn random_unit(sampler: &mut Sampler) -> Self {
+        Self::random_in_unit_sphere(sampler).unit()
     }
 }
 
@@ -0,0 +1,184 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::color::Color;
+
+use super::{Image, ImageFormat};
+
+/// A named buffer produced alongside the rendered image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layer {
+    /// The rendered image, as encoded by the integrator
+    Beauty,
+    /// Material albedo at the first surface hit
+    Albedo,
+    /// Surface normal at the first surface hit, facing the camera
+    Normal,
+    /// Distance to the first surface hit, averaged over the samples that hit something
+    Depth,
+    /// Number of samples taken
+    SampleCount,
+    /// Per-channel sample variance of the radiance
+    Variance,
+}
+
+impl Layer {
+    pub const ALL: [Layer; 6] = [
+        Layer::Beauty,
+        Layer::Albedo,
+        Layer::Normal,
+        Layer::Depth,
+        Layer::SampleCount,
+        Layer::Variance,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Layer::Beauty => "beauty",
+            Layer::Albedo => "albedo",
+            Layer::Normal => "normal",
+            Layer::Depth => "depth",
+            Layer::SampleCount => "samples",
+            Layer::Variance => "variance",
+        }
+    }
+
+    /// Maps raw layer values into `[0, 1]` so they survive 8-bit formats
+    fn to_displayable(self, image: &Image) -> Image {
+        match self {
+            Layer::Beauty | Layer::Albedo => image.map(|c| c),
+            Layer::Normal => image.map(|n| (n + Color::white()) * 0.5),
+            Layer::Depth | Layer::SampleCount | Layer::Variance => {
+                let max = image
+                    .pixels
+                    .iter()
+                    .map(|c| c.r().max(c.g()).max(c.b()))
+                    .fold(0.0, f64::max);
+                let scale = if max > 0.0 { 1.0 / max } else { 0.0 };
+
+                image.map(|c| c * scale)
+            }
+        }
+    }
+}
+
+/// Image buffers for every `Layer`, filled together in a single render pass
+pub struct Framebuffer {
+    width: usize,
+    height: usize,
+    layers: Vec<Image>,
+}
+
+impl Framebuffer {
+    pub fn new(width: usize, height: usize) -> Self {
+        let layers = Layer::ALL
+            .iter()
+            .map(|_| Image::new(width, height, Color::black()))
+            .collect();
+
+        Self {
+            width,
+            height,
+            layers,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn layer(&self, layer: Layer) -> &Image {
+        &self.layers[layer as usize]
+    }
+
+    pub fn layer_mut(&mut self, layer: Layer) -> &mut Image {
+        &mut self.layers[layer as usize]
+    }
+
+    /// Saves the beauty layer to `path` and every other layer next to it as
+    /// `<name>.<layer>.<extension>`, returning the paths written.
+    ///
+    /// 8-bit formats get auxiliary layers rescaled for viewing, use `.pfm` to keep raw values.
+    pub fn save(&self, path: impl Into<PathBuf>) -> io::Result<Vec<PathBuf>> {
+        let path = path.into();
+        let format = ImageFormat::from_path(&path).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "unsupported image extension for {} (expected .png, .ppm or .pfm)",
+                    path.display()
+                ),
+            )
+        })?;
+
+        let mut written = Vec::new();
+
+        for layer in Layer::ALL {
+            let layer_path = layer_path(&path, layer);
+            let image = self.layer(layer);
+
+            if format.is_low_dynamic_range() {
+                layer.to_displayable(image).save_as(&layer_path, format)?;
+            } else {
+                image.save_as(&layer_path, format)?;
+            }
+
+            written.push(layer_path);
+        }
+
+        Ok(written)
+    }
+}
+
+fn layer_path(path: &Path, layer: Layer) -> PathBuf {
+    if layer == Layer::Beauty {
+        return path.to_path_buf();
+    }
+
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{}.{}.{}", stem, layer.name(), ext.to_string_lossy()),
+        None => format!("{}.{}", stem, layer.name()),
+    };
+
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layer_paths() {
+        let path = Path::new("out/render.png");
+
+        assert_eq!(layer_path(path, Layer::Beauty), path);
+        assert_eq!(
+            layer_path(path, Layer::Albedo),
+            Path::new("out/render.albedo.png")
+        );
+        assert_eq!(
+            layer_path(Path::new("render"), Layer::Variance),
+            Path::new("render.variance")
+        );
+    }
+
+    #[test]
+    fn displayable_depth_is_normalized() {
+        let depth = Image::from_pixels(
+            2,
+            1,
+            vec![Color::new(2.0, 2.0, 2.0), Color::new(8.0, 8.0, 8.0)],
+        );
+        let shown = Layer::Depth.to_displayable(&depth);
+
+        assert_eq!(shown.get(0, 0), Color::new(0.25, 0.25, 0.25));
+        assert_eq!(shown.get(1, 0), Color::white());
+    }
+}
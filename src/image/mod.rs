@@ -0,0 +1,224 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    slice::IterMut,
+};
+
+use rayon::{
+    iter::{IndexedParallelIterator, ParallelIterator},
+    slice::ParallelSliceMut,
+};
+
+use crate::color::Color;
+
+mod framebuffer;
+mod pfm;
+mod png;
+
+pub use framebuffer::*;
+
+pub struct Image {
+    width: usize,
+    height: usize,
+
+    /// Pixels are stored contiguously for better caching.
+    /// Read left to right, row by row.
+    /// Improves data locality by making data contiguous for caching.
+    pixels: Vec<Color>,
+}
+
+pub struct PixelIterator<'img> {
+    idx: usize,
+    width: usize,
+    height: usize,
+    image_iter: IterMut<'img, Color>,
+}
+
+impl<'img> PixelIterator<'img> {
+    pub fn new(image: &'img mut Image) -> Self {
+        PixelIterator {
+            idx: 0,
+            width: image.width,
+            height: image.height,
+            image_iter: image.pixels.iter_mut(),
+        }
+    }
+}
+
+impl<'img> Iterator for PixelIterator<'img> {
+    type Item = (usize, usize, &'img mut Color);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(item) = self.image_iter.next() {
+            let x = self.idx % self.width;
+            let y = (self.height - 1) - self.idx / self.width;
+            self.idx += 1;
+
+            return Some((x, y, item));
+        }
+
+        None
+    }
+}
+
+impl ExactSizeIterator for PixelIterator<'_> {
+    fn len(&self) -> usize {
+        self.height * self.width
+    }
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize, default: Color) -> Self {
+        let pixels = vec![default; width * height];
+
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// Creates an image from pixels in storage order: left to right, with the top row first
+    pub fn from_pixels(width: usize, height: usize, pixels: Vec<Color>) -> Self {
+        assert_eq!(pixels.len(), width * height);
+
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// Returns the pixel at `(x, y)`, where `y = 0` is the bottom row
+    pub fn get(&self, x: usize, y: usize) -> Color {
+        self.pixels[(self.height - 1 - y) * self.width + x]
+    }
+
+    /// Returns a new image with `f` applied to every pixel
+    pub fn map(&self, f: impl Fn(Color) -> Color) -> Image {
+        Image::from_pixels(
+            self.width,
+            self.height,
+            self.pixels.iter().map(|&p| f(p)).collect(),
+        )
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Returns an iterator giving `(x, y, pixel)` where `pixel` is a mutable reference to a pixel.
+    /// Also includes `x` and `y` for the current pixel, where pixels are provided top to bottom, left to right.
+    ///
+    /// To apply a function in parallel on all pixels, use the `apply_parallel` method.
+    pub fn pixels(&mut self) -> PixelIterator<'_> {
+        PixelIterator::new(self)
+    }
+
+    pub fn apply_parallel(&mut self, f: impl Fn(usize, usize, &mut Color) + Sync) {
+        self.pixels
+            .par_chunks_mut(self.width)
+            .enumerate()
+            .for_each(|(y, row)| {
+                let y = (self.height - 1) - y;
+                row.iter_mut().enumerate().for_each(|(x, pixel)| {
+                    f(x, y, pixel);
+                });
+            });
+    }
+
+    /// Loads an image saved as `.pfm`, the only format that keeps values exact
+    pub fn load(path: impl Into<PathBuf>) -> io::Result<Image> {
+        let path = path.into();
+
+        if ImageFormat::from_path(&path) != Some(ImageFormat::Pfm) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("only .pfm images can be loaded, got {}", path.display()),
+            ));
+        }
+
+        let mut file = BufReader::new(File::open(path)?);
+        let (width, height, pixels) = pfm::read_pfm(&mut file)?;
+
+        Ok(Image::from_pixels(width, height, pixels))
+    }
+
+    /// Save image to a file, choosing the format from the file extension (`.png`, `.ppm` or `.pfm`)
+    pub fn save(&self, path: impl Into<PathBuf>) -> io::Result<()> {
+        let path = path.into();
+
+        let format = ImageFormat::from_path(&path).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "unsupported image extension for {} (expected .png, .ppm or .pfm)",
+                    path.display()
+                ),
+            )
+        })?;
+
+        self.save_as(path, format)
+    }
+
+    /// Save image to a file in the given format, regardless of the file extension
+    pub fn save_as(&self, path: impl Into<PathBuf>, format: ImageFormat) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path.into())?);
+
+        match format {
+            ImageFormat::Png => {
+                let rgb: Vec<u8> = self.pixels.iter().flat_map(|p| p.to_rgb8()).collect();
+                png::write_png(&mut file, self.width, self.height, &rgb)?;
+            }
+            ImageFormat::Ppm => self.write_ppm(&mut file)?,
+            ImageFormat::Pfm => pfm::write_pfm(&mut file, self.width, self.height, &self.pixels)?,
+        }
+
+        file.flush()
+    }
+
+    fn write_ppm(&self, out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "P3")?;
+        writeln!(out, "{} {}", self.width, self.height)?;
+
+        writeln!(out, "255")?;
+
+        for pixel in self.pixels.iter() {
+            writeln!(out, "{}", pixel)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    /// ASCII (P3) portable pixmap
+    Ppm,
+    /// Portable float map, storing unclamped 32-bit floats
+    Pfm,
+}
+
+impl ImageFormat {
+    /// Whether the format quantizes to 8 bits per channel, clamping values outside `[0, 1]`
+    pub fn is_low_dynamic_range(&self) -> bool {
+        matches!(self, ImageFormat::Png | ImageFormat::Ppm)
+    }
+
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+
+        match extension.as_str() {
+            "png" => Some(ImageFormat::Png),
+            "ppm" => Some(ImageFormat::Ppm),
+            "pfm" => Some(ImageFormat::Pfm),
+            _ => None,
+        }
+    }
+}
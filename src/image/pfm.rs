@@ -0,0 +1,163 @@
+//! Portable float map reader and writer, see http://www.pauldebevec.com/Research/HDR/PFM/
+
+use std::io::{self, Read, Write};
+
+use crate::color::Color;
+
+/// Writes `pixels` (row-major, top row first) as a little-endian RGB PFM image
+pub fn write_pfm(
+    out: &mut impl Write,
+    width: usize,
+    height: usize,
+    pixels: &[Color],
+) -> io::Result<()> {
+    assert_eq!(pixels.len(), width * height);
+
+    // a negative scale marks the data as little-endian
+    write!(out, "PF\n{} {}\n-1.0\n", width, height)?;
+
+    // PFM stores rows from the bottom of the image up
+    for row in pixels.chunks(width).rev() {
+        for pixel in row {
+            for channel in [pixel.r(), pixel.g(), pixel.b()] {
+                out.write_all(&(channel as f32).to_le_bytes())?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Largest width or height accepted when reading, anything bigger is taken as a corrupt header
+const MAX_SIZE: usize = 1 << 16;
+
+/// Reads an RGB PFM image, returning its size and pixels (row-major, top row first)
+pub fn read_pfm(input: &mut impl Read) -> io::Result<(usize, usize, Vec<Color>)> {
+    let header = read_header(input, 4)?;
+    if header[0] != "PF" {
+        return Err(invalid("only RGB PFM images (PF) are supported"));
+    }
+
+    let parse_size = |token: &str| match token.parse::<usize>() {
+        Ok(size) if (1..=MAX_SIZE).contains(&size) => Ok(size),
+        _ => Err(invalid("invalid size")),
+    };
+    let width = parse_size(&header[1])?;
+    let height = parse_size(&header[2])?;
+    let scale: f32 = header[3].parse().map_err(|_| invalid("invalid scale"))?;
+    let little_endian = scale < 0.0;
+
+    let len = width
+        .checked_mul(height)
+        .and_then(|n| n.checked_mul(3 * 4))
+        .ok_or_else(|| invalid("invalid size"))?;
+
+    // read through `take` rather than into a buffer of the claimed size, so a corrupt header
+    // fails on the missing data instead of allocating for it
+    let mut data = Vec::new();
+    input.take(len as u64).read_to_end(&mut data)?;
+    if data.len() != len {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "fewer pixels than the header claims",
+        ));
+    }
+
+    let channels: Vec<f64> = data
+        .chunks_exact(4)
+        .map(|bytes| {
+            let bytes = bytes.try_into().unwrap();
+            let value = if little_endian {
+                f32::from_le_bytes(bytes)
+            } else {
+                f32::from_be_bytes(bytes)
+            };
+
+            value as f64
+        })
+        .collect();
+
+    let mut pixels = Vec::with_capacity(width * height);
+    for row in channels.chunks(width * 3).rev() {
+        for rgb in row.chunks_exact(3) {
+            pixels.push(Color::new(rgb[0], rgb[1], rgb[2]));
+        }
+    }
+
+    Ok((width, height, pixels))
+}
+
+/// Reads `count` whitespace-separated header tokens, consuming the single whitespace byte that
+/// ends the last one
+fn read_header(input: &mut impl Read, count: usize) -> io::Result<Vec<String>> {
+    let mut tokens = Vec::new();
+    let mut token = String::new();
+    let mut byte = [0];
+
+    while tokens.len() < count {
+        input.read_exact(&mut byte)?;
+
+        if byte[0].is_ascii_whitespace() {
+            if !token.is_empty() {
+                tokens.push(std::mem::take(&mut token));
+            }
+        } else if byte[0].is_ascii_graphic() {
+            token.push(byte[0] as char);
+        } else {
+            return Err(invalid("invalid header"));
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let pixels = vec![
+            Color::new(0.0, 0.5, 1.0),
+            Color::new(2.0, 3.0, 4.0),
+            Color::new(-1.0, 0.25, 8.0),
+            Color::new(0.125, 0.0, 100.0),
+            Color::new(1.0, 1.0, 1.0),
+            Color::new(0.0, 0.0, 0.0),
+        ];
+
+        let mut data = Vec::new();
+        write_pfm(&mut data, 3, 2, &pixels).unwrap();
+
+        let (width, height, read) = read_pfm(&mut data.as_slice()).unwrap();
+        assert_eq!((width, height), (3, 2));
+        assert_eq!(read, pixels);
+    }
+
+    #[test]
+    fn corrupt_header() {
+        let read = |data: &[u8]| read_pfm(&mut &data[..]).map(|_| ()).unwrap_err().kind();
+
+        assert_eq!(read(b"PF\n0 2\n-1.0\n"), io::ErrorKind::InvalidData);
+        assert_eq!(read(b"PF\n2 0\n-1.0\n"), io::ErrorKind::InvalidData);
+        assert_eq!(read(b"PF\n-2 2\n-1.0\n"), io::ErrorKind::InvalidData);
+        assert_eq!(read(b"PF\n99999999 2\n-1.0\n"), io::ErrorKind::InvalidData);
+        assert_eq!(
+            read(b"PF\n18446744073709551615 2\n-1.0\n"),
+            io::ErrorKind::InvalidData
+        );
+        assert_eq!(read(b"PF\n2 x\n-1.0\n"), io::ErrorKind::InvalidData);
+        assert_eq!(read(b"Pf\n2 2\n-1.0\n"), io::ErrorKind::InvalidData);
+
+        // a plausible header with too little data behind it
+        assert_eq!(
+            read(b"PF\n60000 60000\n-1.0\n"),
+            io::ErrorKind::UnexpectedEof
+        );
+        assert_eq!(read(b"PF\n2 2\n-1.0\n"), io::ErrorKind::UnexpectedEof);
+    }
+}
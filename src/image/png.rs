@@ -0,0 +1,605 @@
+//! Minimal PNG encoder: 8-bit RGB, adaptive scanline filtering and a zlib stream compressed with
+//! fixed-Huffman DEFLATE.
+//!
+//! Spec references: https://www.w3.org/TR/png/ and https://www.rfc-editor.org/rfc/rfc1951
+
+use std::io::{self, Write};
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+/// Writes `rgb` (row-major, top row first, three bytes per pixel) as a PNG image
+pub fn write_png(out: &mut impl Write, width: usize, height: usize, rgb: &[u8]) -> io::Result<()> {
+    assert_eq!(rgb.len(), width * height * 3);
+
+    let too_large = |_| io::Error::new(io::ErrorKind::InvalidInput, "image too large for PNG");
+    let width_u32 = u32::try_from(width).map_err(too_large)?;
+    let height_u32 = u32::try_from(height).map_err(too_large)?;
+
+    out.write_all(&SIGNATURE)?;
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width_u32.to_be_bytes());
+    header.extend_from_slice(&height_u32.to_be_bytes());
+    // bit depth 8, color type 2 (RGB), deflate compression, adaptive filtering, no interlace
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+    write_chunk(out, b"IHDR", &header)?;
+
+    let filtered = filter_scanlines(width, height, rgb);
+    write_chunk(out, b"IDAT", &zlib_compress(&filtered))?;
+
+    write_chunk(out, b"IEND", &[])
+}
+
+fn write_chunk(out: &mut impl Write, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    out.write_all(kind)?;
+    out.write_all(data)?;
+
+    let mut crc = Crc32::new();
+    crc.update(kind);
+    crc.update(data);
+    out.write_all(&crc.finish().to_be_bytes())
+}
+
+/// Prefixes every scanline with the filter type that gives the smallest sum of absolute residuals
+fn filter_scanlines(width: usize, height: usize, rgb: &[u8]) -> Vec<u8> {
+    const BPP: usize = 3;
+
+    let stride = width * BPP;
+    let mut filtered = Vec::with_capacity((stride + 1) * height);
+    let mut candidate = vec![0u8; stride];
+    let mut best = vec![0u8; stride];
+    let zeros = vec![0u8; stride];
+
+    for y in 0..height {
+        let row = &rgb[y * stride..(y + 1) * stride];
+        let prev = if y == 0 {
+            &zeros[..]
+        } else {
+            &rgb[(y - 1) * stride..y * stride]
+        };
+
+        let mut best_type = 0;
+        let mut best_score = u64::MAX;
+
+        for filter_type in 0..5u8 {
+            for i in 0..stride {
+                let a = if i >= BPP { row[i - BPP] } else { 0 };
+                let b = prev[i];
+                let c = if i >= BPP { prev[i - BPP] } else { 0 };
+
+                let predicted = match filter_type {
+                    0 => 0,
+                    1 => a,
+                    2 => b,
+                    3 => ((a as u16 + b as u16) / 2) as u8,
+                    _ => paeth(a, b, c),
+                };
+                candidate[i] = row[i].wrapping_sub(predicted);
+            }
+
+            let score: u64 = candidate
+                .iter()
+                .map(|&x| (x as i8).unsigned_abs() as u64)
+                .sum();
+            if score < best_score {
+                best_score = score;
+                best_type = filter_type;
+                best.copy_from_slice(&candidate);
+            }
+        }
+
+        filtered.push(best_type);
+        filtered.extend_from_slice(&best);
+    }
+
+    filtered
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+struct Crc32 {
+    table: [u32; 256],
+    value: u32,
+}
+
+impl Crc32 {
+    fn new() -> Self {
+        let mut table = [0u32; 256];
+
+        for (n, entry) in table.iter_mut().enumerate() {
+            let mut c = n as u32;
+            for _ in 0..8 {
+                c = if c & 1 != 0 {
+                    0xedb8_8320 ^ (c >> 1)
+                } else {
+                    c >> 1
+                };
+            }
+            *entry = c;
+        }
+
+        Self {
+            table,
+            value: 0xffff_ffff,
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.value =
+                self.table[((self.value ^ byte as u32) & 0xff) as usize] ^ (self.value >> 8);
+        }
+    }
+
+    fn finish(&self) -> u32 {
+        self.value ^ 0xffff_ffff
+    }
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    // largest number of bytes that can be summed before `b` may overflow a u32
+    const CHUNK: usize = 5552;
+
+    let (mut a, mut b) = (1u32, 0u32);
+
+    for chunk in data.chunks(CHUNK) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= MOD;
+        b %= MOD;
+    }
+
+    (b << 16) | a
+}
+
+fn zlib_compress(data: &[u8]) -> Vec<u8> {
+    // CMF: deflate with a 32K window, FLG: default compression level with a valid check value
+    let mut out = vec![0x78, 0x9c];
+    out.extend(deflate(data));
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+
+    out
+}
+
+struct BitWriter {
+    bytes: Vec<u8>,
+    acc: u64,
+    len: u32,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        Self {
+            bytes: Vec::new(),
+            acc: 0,
+            len: 0,
+        }
+    }
+
+    /// Writes the low `count` bits of `bits`, least significant bit first
+    fn write(&mut self, bits: u32, count: u32) {
+        self.acc |= (bits as u64) << self.len;
+        self.len += count;
+
+        while self.len >= 8 {
+            self.bytes.push(self.acc as u8);
+            self.acc >>= 8;
+            self.len -= 8;
+        }
+    }
+
+    /// Writes a Huffman code, which DEFLATE packs most significant bit first
+    fn write_code(&mut self, code: u32, count: u32) {
+        let reversed = code.reverse_bits() >> (32 - count);
+        self.write(reversed, count);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.len > 0 {
+            self.bytes.push(self.acc as u8);
+        }
+
+        self.bytes
+    }
+}
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+const WINDOW_SIZE: usize = 32 * 1024;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const MAX_CHAIN: usize = 64;
+const HASH_BITS: u32 = 15;
+
+fn write_literal(out: &mut BitWriter, symbol: u16) {
+    let symbol = symbol as u32;
+
+    match symbol {
+        0..=143 => out.write_code(0x30 + symbol, 8),
+        144..=255 => out.write_code(0x190 + symbol - 144, 9),
+        256..=279 => out.write_code(symbol - 256, 7),
+        _ => out.write_code(0xc0 + symbol - 280, 8),
+    }
+}
+
+fn write_match(out: &mut BitWriter, length: usize, distance: usize) {
+    let len_idx = LENGTH_BASE
+        .iter()
+        .rposition(|&base| base as usize <= length)
+        .unwrap();
+    write_literal(out, 257 + len_idx as u16);
+    out.write(
+        (length - LENGTH_BASE[len_idx] as usize) as u32,
+        LENGTH_EXTRA[len_idx] as u32,
+    );
+
+    let dist_idx = DIST_BASE
+        .iter()
+        .rposition(|&base| base as usize <= distance)
+        .unwrap();
+    out.write_code(dist_idx as u32, 5);
+    out.write(
+        (distance - DIST_BASE[dist_idx] as usize) as u32,
+        DIST_EXTRA[dist_idx] as u32,
+    );
+}
+
+/// Hash chains over every position of the input, used to find earlier occurrences of a sequence
+struct Matcher<'a> {
+    data: &'a [u8],
+    head: Vec<usize>,
+    prev: Vec<usize>,
+}
+
+impl<'a> Matcher<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            head: vec![usize::MAX; 1 << HASH_BITS],
+            prev: vec![usize::MAX; data.len()],
+        }
+    }
+
+    fn hash(&self, pos: usize) -> usize {
+        let d = &self.data[pos..];
+        let v = (d[0] as u32) << 16 | (d[1] as u32) << 8 | d[2] as u32;
+
+        (v.wrapping_mul(0x9e37_79b1) >> (32 - HASH_BITS)) as usize
+    }
+
+    fn insert(&mut self, pos: usize) {
+        if pos + MIN_MATCH <= self.data.len() {
+            let h = self.hash(pos);
+            self.prev[pos] = self.head[h];
+            self.head[h] = pos;
+        }
+    }
+
+    /// Returns the `(length, distance)` of the longest earlier match for the bytes at `pos`
+    fn longest_match(&self, pos: usize) -> Option<(usize, usize)> {
+        if pos + MIN_MATCH > self.data.len() {
+            return None;
+        }
+
+        let max_len = MAX_MATCH.min(self.data.len() - pos);
+        let mut best: Option<(usize, usize)> = None;
+        let mut candidate = self.head[self.hash(pos)];
+
+        for _ in 0..MAX_CHAIN {
+            if candidate == usize::MAX || pos - candidate > WINDOW_SIZE {
+                break;
+            }
+
+            let len = self.data[candidate..]
+                .iter()
+                .zip(&self.data[pos..pos + max_len])
+                .take_while(|(a, b)| a == b)
+                .count();
+
+            if len >= MIN_MATCH && best.is_none_or(|(best_len, _)| len > best_len) {
+                best = Some((len, pos - candidate));
+
+                if len == max_len {
+                    break;
+                }
+            }
+
+            candidate = self.prev[candidate];
+        }
+
+        best
+    }
+}
+
+/// Compresses `data` into a single fixed-Huffman DEFLATE block using hash-chained LZ77 matching
+fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut out = BitWriter::new();
+
+    // BFINAL = 1, BTYPE = 01 (fixed Huffman codes)
+    out.write(1, 1);
+    out.write(1, 2);
+
+    let mut matcher = Matcher::new(data);
+
+    let mut pos = 0;
+    while pos < data.len() {
+        match matcher.longest_match(pos) {
+            Some((length, distance)) => {
+                write_match(&mut out, length, distance);
+                for p in pos..pos + length {
+                    matcher.insert(p);
+                }
+                pos += length;
+            }
+            None => {
+                write_literal(&mut out, data[pos] as u16);
+                matcher.insert(pos);
+                pos += 1;
+            }
+        }
+    }
+
+    write_literal(&mut out, 256);
+
+    out.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc_of_iend() {
+        let mut crc = Crc32::new();
+        crc.update(b"IEND");
+
+        assert_eq!(crc.finish(), 0xae42_6082);
+    }
+
+    #[test]
+    fn adler_known_value() {
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+        assert_eq!(adler32(&[]), 1);
+    }
+
+    #[test]
+    fn fixed_huffman_empty_block() {
+        // final fixed block containing only the end-of-block symbol
+        assert_eq!(deflate(&[]), vec![0x03, 0x00]);
+    }
+
+    #[test]
+    fn repeated_data_compresses() {
+        let data = vec![7u8; 10_000];
+
+        assert!(deflate(&data).len() < 200);
+    }
+
+    #[test]
+    fn paeth_predictor() {
+        assert_eq!(paeth(10, 20, 10), 20);
+        assert_eq!(paeth(20, 10, 10), 20);
+        assert_eq!(paeth(10, 10, 20), 10);
+    }
+
+    /// Reads DEFLATE's bit stream, just enough of it to decode what `deflate` writes
+    struct BitReader<'a> {
+        bytes: &'a [u8],
+        pos: usize,
+    }
+
+    impl BitReader<'_> {
+        fn bits(&mut self, count: u32) -> u32 {
+            let mut value = 0;
+            for i in 0..count {
+                let bit = (self.bytes[self.pos / 8] >> (self.pos % 8)) & 1;
+                value |= (bit as u32) << i;
+                self.pos += 1;
+            }
+            value
+        }
+
+        /// Reads a Huffman code of `count` bits, most significant bit first
+        fn code(&mut self, count: u32) -> u32 {
+            (0..count).fold(0, |code, _| code << 1 | self.bits(1))
+        }
+
+        fn literal(&mut self) -> u16 {
+            let code = self.code(7);
+            if code <= 0x17 {
+                return 256 + code as u16;
+            }
+            let code = code << 1 | self.bits(1);
+            match code {
+                0x30..=0xbf => (code - 0x30) as u16,
+                0xc0..=0xc7 => (280 + code - 0xc0) as u16,
+                _ => (144 + (code << 1 | self.bits(1)) - 0x190) as u16,
+            }
+        }
+    }
+
+    /// Decompresses a zlib stream made of stored and fixed-Huffman blocks
+    fn zlib_decompress(data: &[u8]) -> Vec<u8> {
+        assert_eq!(data[0] & 0x0f, 8, "not deflate");
+        assert_eq!(u16::from_be_bytes([data[0], data[1]]) % 31, 0);
+
+        let mut reader = BitReader {
+            bytes: &data[2..data.len() - 4],
+            pos: 0,
+        };
+        let mut out: Vec<u8> = Vec::new();
+
+        loop {
+            let last = reader.bits(1) == 1;
+            match reader.bits(2) {
+                0 => {
+                    let start = reader.pos.div_ceil(8);
+                    let len = u16::from_le_bytes([reader.bytes[start], reader.bytes[start + 1]]);
+                    let data = &reader.bytes[start + 4..start + 4 + len as usize];
+                    out.extend_from_slice(data);
+                    reader.pos = (start + 4 + len as usize) * 8;
+                }
+                1 => loop {
+                    let symbol = reader.literal();
+                    match symbol {
+                        0..=255 => out.push(symbol as u8),
+                        256 => break,
+                        _ => {
+                            let idx = (symbol - 257) as usize;
+                            let length = LENGTH_BASE[idx] as usize
+                                + reader.bits(LENGTH_EXTRA[idx] as u32) as usize;
+                            let idx = reader.code(5) as usize;
+                            let distance = DIST_BASE[idx] as usize
+                                + reader.bits(DIST_EXTRA[idx] as u32) as usize;
+                            for _ in 0..length {
+                                out.push(out[out.len() - distance]);
+                            }
+                        }
+                    }
+                },
+                btype => panic!("unexpected block type {}", btype),
+            }
+
+            if last {
+                break;
+            }
+        }
+
+        let checksum = u32::from_be_bytes(data[data.len() - 4..].try_into().unwrap());
+        assert_eq!(checksum, adler32(&out));
+
+        out
+    }
+
+    /// Decodes a PNG as written by `write_png`, returning its size and RGB bytes
+    fn read_png(png: &[u8]) -> (usize, usize, Vec<u8>) {
+        assert_eq!(&png[..8], &SIGNATURE);
+
+        let (mut width, mut height) = (0, 0);
+        let mut compressed = Vec::new();
+        let mut pos = 8;
+        while pos < png.len() {
+            let len = u32::from_be_bytes(png[pos..pos + 4].try_into().unwrap()) as usize;
+            let kind = &png[pos + 4..pos + 8];
+            let data = &png[pos + 8..pos + 8 + len];
+
+            let mut crc = Crc32::new();
+            crc.update(kind);
+            crc.update(data);
+            let stored = u32::from_be_bytes(png[pos + 8 + len..pos + 12 + len].try_into().unwrap());
+            assert_eq!(stored, crc.finish());
+
+            match kind {
+                b"IHDR" => {
+                    width = u32::from_be_bytes(data[0..4].try_into().unwrap()) as usize;
+                    height = u32::from_be_bytes(data[4..8].try_into().unwrap()) as usize;
+                    assert_eq!(&data[8..], &[8, 2, 0, 0, 0]);
+                }
+                b"IDAT" => compressed.extend_from_slice(data),
+                _ => {}
+            }
+            pos += len + 12;
+        }
+
+        let filtered = zlib_decompress(&compressed);
+        let stride = width * 3;
+        assert_eq!(filtered.len(), (stride + 1) * height);
+
+        let mut rgb = vec![0u8; stride * height];
+        for y in 0..height {
+            let filter_type = filtered[y * (stride + 1)];
+            let line = &filtered[y * (stride + 1) + 1..(y + 1) * (stride + 1)];
+            for i in 0..stride {
+                let a = if i >= 3 { rgb[y * stride + i - 3] } else { 0 };
+                let b = if y > 0 { rgb[(y - 1) * stride + i] } else { 0 };
+                let c = if i >= 3 && y > 0 {
+                    rgb[(y - 1) * stride + i - 3]
+                } else {
+                    0
+                };
+
+                let predicted = match filter_type {
+                    0 => 0,
+                    1 => a,
+                    2 => b,
+                    3 => ((a as u16 + b as u16) / 2) as u8,
+                    4 => paeth(a, b, c),
+                    _ => panic!("unknown filter type {}", filter_type),
+                };
+                rgb[y * stride + i] = line[i].wrapping_add(predicted);
+            }
+        }
+
+        (width, height, rgb)
+    }
+
+    #[test]
+    fn round_trip() {
+        let (width, height) = (37, 23);
+        // gradients favor the predicting filters, the noise keeps some rows unfiltered and the
+        // repeated band gives the matcher long back references
+        let mut state = 12345u32;
+        let rgb: Vec<u8> = (0..width * height * 3)
+            .map(|i| {
+                let (x, y) = ((i / 3) % width, (i / 3) / width);
+                if y < 8 {
+                    (x * 7 + y * 3 + i % 3 * 50) as u8
+                } else if y < 16 {
+                    state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                    (state >> 16) as u8
+                } else {
+                    (x % 4 * 60) as u8
+                }
+            })
+            .collect();
+
+        let mut out = Vec::new();
+        write_png(&mut out, width, height, &rgb).unwrap();
+
+        assert_eq!(read_png(&out), (width, height, rgb));
+    }
+
+    #[test]
+    fn png_header() {
+        let mut out = Vec::new();
+        write_png(&mut out, 2, 1, &[255, 0, 0, 0, 255, 0]).unwrap();
+
+        assert_eq!(&out[..8], &SIGNATURE);
+        assert_eq!(&out[12..16], b"IHDR");
+        assert_eq!(&out[16..20], &2u32.to_be_bytes());
+        assert_eq!(&out[20..24], &1u32.to_be_bytes());
+        assert_eq!(&out[out.len() - 12..out.len() - 8], &0u32.to_be_bytes());
+        assert_eq!(&out[out.len() - 8..out.len() - 4], b"IEND");
+    }
+}
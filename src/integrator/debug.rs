@@ -0,0 +1,156 @@
+use crate::{
+    color::Color,
+    geometry::{Point, Ray, Vec3},
+    sampler::Sampler,
+    scene::{SceneView, HIT_TOLERANCE},
+};
+
+use super::Integrator;
+
+/// Surface attribute visualized by a `DebugIntegrator`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugMode {
+    /// Outward shading normal, mapped from [-1, 1] to [0, 1] per component
+    Normal,
+    /// Outward normal of the surface itself, ignoring interpolated vertex normals
+    GeometricNormal,
+    /// Hit distance relative to the farthest corner of the scene bounds, near is white and far is black
+    Depth,
+    /// Base color of the material
+    Albedo,
+    /// Green where the ray hit the front of a surface, red where it hit the back
+    FrontFace,
+    /// A distinct color for each primitive in the BVH, grey for primitives outside of it
+    PrimitiveId,
+    /// Heatmap of the bounding boxes tested to find the first hit, from blue (few) to red (many)
+    BoxTests,
+    /// Heatmap of the primitives tested to find the first hit, from blue (few) to red (many)
+    PrimitiveTests,
+}
+
+/// Number of tests shown as the hottest color in the traversal heatmaps, the scale is logarithmic
+/// below it
+const HEATMAP_MAX_TESTS: u32 = 1024;
+
+/// Renders a false-color image of the first surface hit by each camera ray. Misses are black,
+/// except in the heatmap modes, which show the work done for every ray.
+pub struct DebugIntegrator {
+    mode: DebugMode,
+}
+
+impl DebugIntegrator {
+    pub fn new(mode: DebugMode) -> Self {
+        Self { mode }
+    }
+}
+
+impl Integrator for DebugIntegrator {
+    fn radiance(&self, scene: &SceneView, r: Ray, _sampler: &mut Sampler) -> Color {
+        if let DebugMode::BoxTests | DebugMode::PrimitiveTests = self.mode {
+            let (_, counts) = scene.hit_counted(r, &(HIT_TOLERANCE..f64::INFINITY));
+            let tests = if self.mode == DebugMode::BoxTests {
+                counts.box_tests
+            } else {
+                counts.primitive_tests
+            };
+
+            return heat_color(tests);
+        }
+
+        let hit = match scene.hit(r, &(HIT_TOLERANCE..f64::INFINITY)) {
+            Some(found) => found.hit,
+            None => return Color::black(),
+        };
+
+        match self.mode {
+            DebugMode::Normal | DebugMode::GeometricNormal => {
+                let normal = if self.mode == DebugMode::Normal {
+                    hit.normal
+                } else {
+                    hit.geometric_normal
+                };
+                // both normals face the ray, and `front_face` says whether that flipped them
+                let outward = if hit.front_face { normal } else { -normal };
+                let n = outward.unit();
+
+                Color::new(n.x() + 1.0, n.y() + 1.0, n.z() + 1.0) * 0.5
+            }
+            DebugMode::Depth => {
+                // normalize by the farthest corner of the scene bounds, or the hit itself for
+                // surfaces outside the BVH such as planes
+                let bbox = scene.bvh().bbox();
+                let (x, y, z) = (bbox.x(), bbox.y(), bbox.z());
+                let far = [x.start, x.end]
+                    .into_iter()
+                    .flat_map(|x| [y.start, y.end].into_iter().map(move |y| (x, y)))
+                    .flat_map(|(x, y)| [z.start, z.end].into_iter().map(move |z| (x, y, z)))
+                    .map(|(x, y, z)| Vec3::from(Point::new(x, y, z) - r.origin()).len())
+                    .fold(0.0, f64::max);
+
+                let distance = hit.t * r.direction().len();
+                let shade = (1.0 - distance / far.max(distance)).clamp(0.0, 1.0);
+
+                Color::new(shade, shade, shade)
+            }
+            DebugMode::Albedo => hit.material.albedo(&hit),
+            DebugMode::FrontFace => {
+                if hit.front_face {
+                    Color::new(0.0, 1.0, 0.0)
+                } else {
+                    Color::new(1.0, 0.0, 0.0)
+                }
+            }
+            DebugMode::PrimitiveId => match hit.primitive_id {
+                Some(id) => id_color(id),
+                None => Color::new(0.5, 0.5, 0.5),
+            },
+            DebugMode::BoxTests | DebugMode::PrimitiveTests => unreachable!(),
+        }
+    }
+
+    fn encode(&self, average: Color) -> Color {
+        average
+    }
+}
+
+/// Maps a number of tests onto a blue, cyan, green, yellow, red ramp. Zero tests is black.
+fn heat_color(tests: u32) -> Color {
+    if tests == 0 {
+        return Color::black();
+    }
+
+    const RAMP: [(f64, f64, f64); 5] = [
+        (0.0, 0.0, 1.0),
+        (0.0, 1.0, 1.0),
+        (0.0, 1.0, 0.0),
+        (1.0, 1.0, 0.0),
+        (1.0, 0.0, 0.0),
+    ];
+
+    let heat = ((tests as f64).log2() / (HEATMAP_MAX_TESTS as f64).log2()).clamp(0.0, 1.0);
+    let pos = heat * (RAMP.len() - 1) as f64;
+    let idx = (pos as usize).min(RAMP.len() - 2);
+    let frac = pos - idx as f64;
+
+    let (a, b) = (RAMP[idx], RAMP[idx + 1]);
+    Color::new(
+        a.0 + (b.0 - a.0) * frac,
+        a.1 + (b.1 - a.1) * frac,
+        a.2 + (b.2 - a.2) * frac,
+    )
+}
+
+/// Spreads consecutive ids over distinct, bright colors
+fn id_color(id: usize) -> Color {
+    // integer hash (lowbias32) so neighbouring primitives don't get similar colors
+    let mut h = id as u32;
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x7feb_352d);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x846c_a68b);
+    h ^= h >> 16;
+
+    let [r, g, b, _] = h.to_le_bytes();
+
+    Color::from_rgb(r / 2 + 64, g / 2 + 64, b / 2 + 64)
+}
@@ -0,0 +1,60 @@
+use crate::{
+    color::Color,
+    geometry::{Ray, Vec3},
+    object::VisibleHit,
+    sampler::Sampler,
+    scene::{SceneView, HIT_TOLERANCE},
+};
+
+mod debug;
+mod path;
+
+pub use debug::*;
+pub use path::*;
+
+/// Computes the value a camera ray contributes to its pixel
+pub trait Integrator: Sync {
+    fn radiance(&self, scene: &SceneView, r: Ray, sampler: &mut Sampler) -> Color;
+
+    /// Like `radiance`, but also reports the first surface the ray hit for auxiliary layers.
+    /// The default casts a separate ray to find it, integrators that trace the camera ray
+    /// themselves should override this to reuse their hit.
+    fn radiance_with_surface(
+        &self,
+        scene: &SceneView,
+        r: Ray,
+        sampler: &mut Sampler,
+    ) -> (Color, Option<SurfaceSample>) {
+        let surface = scene
+            .hit(r, &(HIT_TOLERANCE..f64::INFINITY))
+            .map(|found| SurfaceSample::new(r, &found.hit));
+
+        (self.radiance(scene, r, sampler), surface)
+    }
+
+    /// Converts the average of a pixel's samples into the value stored in the image.
+    /// Defaults to gamma 2 encoding of the radiance.
+    fn encode(&self, average: Color) -> Color {
+        Color::new(average.r().sqrt(), average.g().sqrt(), average.b().sqrt())
+    }
+}
+
+/// Attributes of the first surface hit by a camera ray
+#[derive(Debug, Clone, Copy)]
+pub struct SurfaceSample {
+    pub albedo: Color,
+    /// Unit normal facing against the ray
+    pub normal: Vec3<f64>,
+    /// Distance from the ray origin to the hit
+    pub depth: f64,
+}
+
+impl SurfaceSample {
+    pub fn new(r: Ray, hit: &VisibleHit) -> Self {
+        Self {
+            albedo: hit.material.albedo(hit),
+            normal: hit.normal.unit(),
+            depth: hit.t * r.direction().len(),
+        }
+    }
+}
@@ -0,0 +1,219 @@
+use crate::{
+    color::Color,
+    geometry::{Ray, Vec3},
+    object::VisibleHit,
+    sampler::Sampler,
+    scene::{SceneHit, SceneView, HIT_TOLERANCE},
+};
+
+use super::{Integrator, SurfaceSample};
+
+/// Fraction of a shadow ray's length around the sampled light point that still counts as the light
+const SHADOW_TOLERANCE: f64 = 0.0001;
+
+/// Unidirectional path tracer that samples lights directly at each bounce, combining light and
+/// material sampling with multiple importance sampling
+pub struct PathIntegrator {
+    max_depth: u32,
+}
+
+impl PathIntegrator {
+    pub fn new(max_depth: u32) -> Self {
+        Self { max_depth }
+    }
+
+    /// Path traces `r`, where `scatter_pdf` is the density with which the previous bounce sampled
+    /// `r` (`None` for camera rays and specular bounces, which can't be light-sampled)
+    fn ray_color(
+        &self,
+        scene: &SceneView,
+        r: Ray,
+        depth: u32,
+        scatter_pdf: Option<f64>,
+        sampler: &mut Sampler,
+    ) -> Color {
+        if depth == 0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+
+        match scene.hit(r, &(HIT_TOLERANCE..f64::INFINITY)) {
+            Some(found) => self.shade(scene, r, found, depth, scatter_pdf, sampler),
+            None => scene.sky(r.direction().unit()),
+        }
+    }
+
+    /// Computes the light leaving the surface hit by `r` back along the ray
+    fn shade(
+        &self,
+        scene: &SceneView,
+        r: Ray,
+        found: SceneHit,
+        depth: u32,
+        scatter_pdf: Option<f64>,
+        sampler: &mut Sampler,
+    ) -> Color {
+        let hit = found.hit;
+
+        let mut color = hit.material.emitted(&hit);
+
+        // this light could also have been reached by light sampling at the previous bounce,
+        // so weigh the two strategies against each other
+        if let Some(scatter_pdf) = scatter_pdf {
+            if found.on_light {
+                let light_pdf = scene
+                    .lights()
+                    .pdf(r.origin(), hit.point, hit.geometric_normal);
+                color = color * power_heuristic(scatter_pdf, light_pdf);
+            }
+        }
+
+        // lights hit by the scattered ray below are only counted if it can bounce again, so
+        // without that partner strategy light sampling takes the whole weight
+        let combine = depth > 1;
+        color += self.sample_light(scene, r, &hit, combine, sampler);
+
+        if let Some((scattered, attenuation)) = hit.material.scatter(r, &hit, sampler) {
+            let pdf = hit.material.pdf(r, &hit, scattered.direction());
+            color += attenuation * self.ray_color(scene, scattered, depth - 1, pdf, sampler);
+        }
+
+        color
+    }
+
+    /// Estimates direct lighting at `hit` by casting a shadow ray towards a random point on a light,
+    /// weighted against material sampling when `combine` is set
+    fn sample_light(
+        &self,
+        scene: &SceneView,
+        r: Ray,
+        hit: &VisibleHit,
+        combine: bool,
+        sampler: &mut Sampler,
+    ) -> Color {
+        let sample = match scene.lights().sample(r.time(), sampler) {
+            Some(sample) => sample,
+            None => return Color::black(),
+        };
+
+        let to_light = Vec3::from(sample.point - hit.point);
+        let scatter_pdf = match hit.material.pdf(r, hit, to_light) {
+            Some(pdf) if pdf > 0.0 => pdf,
+            _ => return Color::black(),
+        };
+
+        let light_pdf = scene.lights().pdf(hit.point, sample.point, sample.normal);
+        if light_pdf == 0.0 {
+            return Color::black();
+        }
+
+        // the shadow ray reaches the light at t = 1, anything hit before that is an occluder
+        let shadow = Ray::with_time(hit.point, to_light, r.time());
+        if scene.occluded(shadow, &(HIT_TOLERANCE..(1.0 - SHADOW_TOLERANCE))) {
+            return Color::black();
+        }
+
+        let light_range = (1.0 - SHADOW_TOLERANCE)..(1.0 + SHADOW_TOLERANCE);
+        let light_hit = match sample.light.bounce(shadow, &light_range) {
+            Some(light_hit) => light_hit,
+            None => return Color::black(),
+        };
+
+        let weight = if combine {
+            power_heuristic(light_pdf, scatter_pdf) / light_pdf
+        } else {
+            1.0 / light_pdf
+        };
+
+        hit.material.eval(r, hit, to_light) * light_hit.material.emitted(&light_hit) * weight
+    }
+}
+
+impl Integrator for PathIntegrator {
+    fn radiance(&self, scene: &SceneView, r: Ray, sampler: &mut Sampler) -> Color {
+        self.ray_color(scene, r, self.max_depth, None, sampler)
+    }
+
+    fn radiance_with_surface(
+        &self,
+        scene: &SceneView,
+        r: Ray,
+        sampler: &mut Sampler,
+    ) -> (Color, Option<SurfaceSample>) {
+        if self.max_depth == 0 {
+            return (Color::black(), None);
+        }
+
+        match scene.hit(r, &(HIT_TOLERANCE..f64::INFINITY)) {
+            Some(found) => {
+                let surface = SurfaceSample::new(r, &found.hit);
+                let color = self.shade(scene, r, found, self.max_depth, None, sampler);
+
+                (color, Some(surface))
+            }
+            None => (scene.sky(r.direction().unit()), None),
+        }
+    }
+}
+
+/// Multiple importance sampling weight for a sample drawn from the strategy with density `pdf`
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+
+    if a + b == 0.0 {
+        0.0
+    } else {
+        a / (a + b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{geometry::Point, image::Image, scene::Scene, sky::Uniform};
+
+    use super::*;
+
+    #[test]
+    fn last_bounce_keeps_all_direct_light() {
+        // a light large and close enough over a diffuse floor that material sampling would
+        // often have found it too, and takes a good share of the weight when the two are combined
+        let (radius, height, albedo, emission) = (0.5, 1.0, 0.5, 4.0);
+
+        let mut scene = Scene::new();
+        scene.progress(false);
+        scene.sky(Uniform::new(Color::black()));
+        let floor = scene.diffuse_material(Color::new(albedo, albedo, albedo));
+        let light = scene.light_material(Color::new(emission, emission, emission));
+        scene.plane(Point::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), &floor);
+        scene.sphere(Point::new(0.0, height, 0.0), radius, &light);
+
+        // every camera ray lands right under the light
+        let (from, at) = (Point::new(3.0, 0.5, 0.0), Point::new(0.0, 0.0, 0.0));
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        scene.camera(from, at, up, 0.01, 1.0, 0.0, Vec3::from(at - from).len());
+
+        // a sphere lights a point below it like a disk filling the same cone, that is with
+        // irradiance pi * emission * sin^2 of the cone's half angle, which is what light
+        // sampling alone converges to
+        let expected = albedo * emission * (radius / height).powi(2);
+
+        // one bounce, where only light sampling can find the light, and more, where the two
+        // strategies are combined
+        for max_depth in [1, 2] {
+            let mut image = Image::new(2, 2, Color::black());
+            scene.render(&mut image, 2000, &PathIntegrator::new(max_depth));
+
+            let found = [(0, 0), (0, 1), (1, 0), (1, 1)]
+                .into_iter()
+                .map(|(x, y)| image.get(x, y).r().powi(2))
+                .sum::<f64>()
+                / 4.0;
+            assert!(
+                (found - expected).abs() < 0.05 * expected,
+                "{} != {} at depth {}",
+                found,
+                expected,
+                max_depth
+            );
+        }
+    }
+}
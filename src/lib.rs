@@ -2,7 +2,12 @@ pub mod camera;
 pub mod color;
 pub mod geometry;
 pub mod image;
+pub mod integrator;
 pub mod material;
 pub mod object;
+pub mod sampler;
 pub mod scene;
 pub mod sky;
+
+#[cfg(test)]
+mod test_util;
@@ -1,125 +1,149 @@
-use std::{io, path::PathBuf, sync::Arc};
+use std::{path::PathBuf, process};
 
 use bounce::{
     color::Color,
-    geometry::{Point, Vec3},
-    image::Image,
-    material::{Lambertian, Material},
-    object::{
-        bvh::{BvhTree, Primitive},
-        Tri,
-    },
+    image::{Framebuffer, Image, ImageFormat},
+    integrator::{DebugIntegrator, DebugMode, Integrator, PathIntegrator},
     scene::Scene,
-    sky::Day,
 };
-use clap::Parser;
-use rand::{thread_rng, Rng};
+use clap::{ArgEnum, Parser};
 
 #[derive(Parser)]
 #[clap(about)]
 struct Args {
+    /// Scene description to render
+    #[clap(parse(from_os_str))]
+    scene: PathBuf,
+
     /// Where to save the output image
     #[clap(parse(from_os_str))]
     output: PathBuf,
 
-    #[clap(long, short, default_value_t = 100)]
-    samples_per_pixel: u32,
+    /// Overrides the samples per pixel given in the scene file
+    #[clap(long, short, parse(try_from_str = parse_samples))]
+    samples_per_pixel: Option<u32>,
 
-    #[clap(long, short, default_value_t = 50)]
-    max_depth: u32,
+    /// Overrides the maximum bounce depth given in the scene file
+    #[clap(long, short)]
+    max_depth: Option<u32>,
 
-    #[clap(long, default_value_t = 400)]
-    width: usize,
+    /// What to render: the lit image, or a false-color view of the first surface hit
+    #[clap(long, arg_enum, default_value = "beauty")]
+    mode: Mode,
 
-    #[clap(long, default_value_t = 225)]
-    height: usize,
-}
+    /// Seed for the random sampling, renders with the same seed and settings are identical
+    #[clap(long, default_value = "0")]
+    seed: u64,
 
-fn main() -> io::Result<()> {
-    let args = Args::parse();
+    /// Print statistics about the BVH built over the scene
+    #[clap(long)]
+    bvh_stats: bool,
 
-    let samples_per_pixel = args.samples_per_pixel;
-    let max_depth = args.max_depth;
-    let image_width = args.width;
-    let image_height = args.height;
+    /// Also save albedo, normal, depth, sample count and variance layers next to the output
+    #[clap(long)]
+    layers: bool,
 
-    let aspect_ratio = (image_width as f64) / (image_height as f64);
+    /// Directory to cache loaded OBJ files and their BVHs in, to skip rebuilding them next time
+    #[clap(long, parse(from_os_str))]
+    mesh_cache: Option<PathBuf>,
+}
 
-    let mut scene = Scene::new();
+#[derive(Clone, Copy, ArgEnum)]
+enum Mode {
+    Beauty,
+    Normal,
+    GeometricNormal,
+    Depth,
+    Albedo,
+    FrontFace,
+    PrimitiveId,
+    BoxTests,
+    PrimitiveTests,
+}
 
-    // make_triangles(120_000, &mut scene);
-
-    let metal = scene.metal_material(Color::new(1.0, 1.0, 1.0), 0.3);
-    let diffuse = scene.diffuse_material(Color::new(1.0, 0.0, 1.0));
-    let glass = scene.dielectric_material(1.5);
-    scene.object("files/teapot.obj", &diffuse);
-
-    let plane_mat = scene.diffuse_material(Color::new(0.2, 0.2, 0.01));
-    // scene.plane(
-    //     Point::new(0.0, 0.0, 0.0),
-    //     Vec3::new(0.0, 1.0, 0.0),
-    //     &plane_mat,
-    // );
-
-    // scene.sphere(Point::new(3.0, 0.0, 2.0), 0.5, &tri_mat);
-    // scene.triangle(
-    //     Point::new(5.0, 0.0, 0.0),
-    //     Point::new(0.0, 0.0, 0.0),
-    //     Point::new(0.0, 3.0, 0.0),
-    //     &teapot_mat,
-    // );
-
-    let look_from = Point::new(3.0, 3.0, 10.0);
-    let look_at = Point::new(0.0, 1.0, 0.0);
-    let focus_dist = Vec3::from(look_at - look_from).len();
-
-    scene.camera(
-        look_from,
-        look_at,
-        Vec3::new(0.0, 1.0, 0.0),
-        30.0,
-        aspect_ratio,
-        0.0,
-        focus_dist,
-    );
-    scene.sky(Day::new());
-
-    let mut image = Image::new(image_width, image_height, Color::black());
-
-    scene.render(&mut image, samples_per_pixel, max_depth);
-
-    image.save(args.output)?;
-
-    Ok(())
+impl Mode {
+    fn integrator(self, max_depth: u32) -> Box<dyn Integrator> {
+        let debug = match self {
+            Mode::Beauty => return Box::new(PathIntegrator::new(max_depth)),
+            Mode::Normal => DebugMode::Normal,
+            Mode::GeometricNormal => DebugMode::GeometricNormal,
+            Mode::Depth => DebugMode::Depth,
+            Mode::Albedo => DebugMode::Albedo,
+            Mode::FrontFace => DebugMode::FrontFace,
+            Mode::PrimitiveId => DebugMode::PrimitiveId,
+            Mode::BoxTests => DebugMode::BoxTests,
+            Mode::PrimitiveTests => DebugMode::PrimitiveTests,
+        };
+
+        Box::new(DebugIntegrator::new(debug))
+    }
 }
 
-fn make_triangles(num: usize, scene: &mut Scene) {
-    let diffuse = scene.diffuse_material(Color::new(0.0, 0.3, 0.8));
-    let metal = scene.metal_material(Color::new(0.3, 0.2, 0.0), 0.5);
-    let glass = scene.dielectric_material(1.5);
-
-    let mut rng = thread_rng();
-    let a = Point::new(0.0, 0.0, 0.0);
-    let b = Point::new(1.0, 0.0, 0.0);
-    let c = Point::new(0.0, 1.0, 0.0);
-    let coord_range = -10.0..10.0;
-    for _ in 0..num {
-        let offset = Point::new(
-            rng.gen_range(coord_range.clone()),
-            0.0,
-            rng.gen_range(coord_range.clone()),
+/// Samples per pixel, which like in scene files must be at least 1
+fn parse_samples(value: &str) -> Result<u32, String> {
+    match value.parse() {
+        Ok(0) => Err("must be at least 1".to_string()),
+        Ok(samples) => Ok(samples),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+
+    // checked up front so a typo in the output path doesn't throw away a finished render
+    if ImageFormat::from_path(&args.output).is_none() {
+        eprintln!(
+            "error: {}: unsupported image extension (expected .png, .ppm or .pfm)",
+            args.output.display()
         );
+        process::exit(1);
+    }
 
-        let mat = {
-            let num: f64 = rng.gen();
+    let mut scene = Scene::new();
+    if let Some(dir) = &args.mesh_cache {
+        scene.mesh_cache(dir);
+    }
 
-            match num {
-                num if num < 0.33 => &diffuse,
-                num if num < 0.66 => &metal,
-                _ => &glass,
-            }
-        };
-        // scene.triangle(a + offset, b + offset, c + offset, &mat);
-        scene.sphere(offset, 0.2, mat);
+    let (mut scene, mut settings) = match scene.load_into(&args.scene) {
+        Ok(loaded) => loaded,
+        Err(err) => {
+            eprintln!("error: {}: {}", args.scene.display(), err);
+            process::exit(1);
+        }
+    };
+    for warning in scene.warnings() {
+        eprintln!("warning: {}: {}", args.scene.display(), warning);
+    }
+
+    if let Some(samples_per_pixel) = args.samples_per_pixel {
+        settings.samples_per_pixel = samples_per_pixel;
+    }
+    if let Some(max_depth) = args.max_depth {
+        settings.max_depth = max_depth;
+    }
+
+    scene.seed(args.seed);
+    scene.bvh_stats(args.bvh_stats);
+
+    let integrator = args.mode.integrator(settings.max_depth);
+
+    let saved = if args.layers {
+        let mut framebuffer = Framebuffer::new(settings.width, settings.height);
+        scene.render_layers(
+            &mut framebuffer,
+            settings.samples_per_pixel,
+            integrator.as_ref(),
+        );
+        framebuffer.save(&args.output).map(|_| ())
+    } else {
+        let mut image = Image::new(settings.width, settings.height, Color::black());
+        scene.render(&mut image, settings.samples_per_pixel, integrator.as_ref());
+        image.save(&args.output)
+    };
+
+    if let Err(err) = saved {
+        eprintln!("error: {}: {}", args.output.display(), err);
+        process::exit(1);
     }
 }
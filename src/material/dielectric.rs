@@ -1,5 +1,5 @@
 use super::Material;
-use crate::{color::Color, geometry::Ray, object::VisibleHit};
+use crate::{color::Color, geometry::Ray, object::VisibleHit, sampler::Sampler};
 
 pub struct Dielectric {
     ior: f64,
@@ -22,7 +22,7 @@ impl Dielectric {
 
 impl Material for Dielectric {
     // TODO: Verify that dielectric scatter was correctly implemented
-    fn scatter(&self, r: Ray, hit: &VisibleHit) -> Option<(Ray, Color)> {
+    fn scatter(&self, r: Ray, hit: &VisibleHit, sampler: &mut Sampler) -> Option<(Ray, Color)> {
         let attenuation = Color::new(1.0, 1.0, 1.0);
         let eta_ratio = if hit.front_face {
             1.0 / self.ior
@@ -37,14 +37,15 @@ impl Material for Dielectric {
 
         let cannot_refract = eta_ratio * sin_theta > 1.0;
 
-        let scatter_dir =
-            if cannot_refract || Dielectric::reflectance(cos_theta, eta_ratio) > rand::random() {
-                unit_dir.reflect(hit.normal)
-            } else {
-                unit_dir.refract(hit.normal, eta_ratio)
-            };
+        let scatter_dir = if cannot_refract
+            || Dielectric::reflectance(cos_theta, eta_ratio) > sampler.next_f64()
+        {
+            unit_dir.reflect(hit.normal)
+        } else {
+            unit_dir.refract(hit.normal, eta_ratio)
+        };
 
-        let scattered = Ray::new(hit.point, scatter_dir);
+        let scattered = Ray::with_time(hit.point, scatter_dir, r.time());
 
         Some((scattered, attenuation))
     }
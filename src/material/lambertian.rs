@@ -1,7 +1,10 @@
+use std::f64::consts::PI;
+
 use crate::{
     color::Color,
     geometry::{Ray, Vec3},
     object::VisibleHit,
+    sampler::Sampler,
 };
 
 use super::Material;
@@ -17,17 +20,32 @@ impl Lambertian {
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, _r: Ray, hit: &VisibleHit) -> Option<(Ray, Color)> {
-        let scatter_dir = hit.normal + Vec3::random_unit();
+    fn albedo(&self, _hit: &VisibleHit) -> Color {
+        self.albedo
+    }
+
+    fn scatter(&self, r: Ray, hit: &VisibleHit, sampler: &mut Sampler) -> Option<(Ray, Color)> {
+        let scatter_dir = hit.normal + Vec3::random_unit(sampler);
         let scattered = if scatter_dir.near_zero() {
             // Prevent cases where the ray bounce is 0, leading to NaN/infinites
-            Ray::new(hit.point, hit.normal)
+            Ray::with_time(hit.point, hit.normal, r.time())
         } else {
-            Ray::new(hit.point, scatter_dir)
+            Ray::with_time(hit.point, scatter_dir, r.time())
         };
 
         let attenuation = self.albedo;
 
         Some((scattered, attenuation))
     }
+
+    fn pdf(&self, _r: Ray, hit: &VisibleHit, direction: Vec3<f64>) -> Option<f64> {
+        // scattering towards normal + random unit vector is cosine-weighted
+        let cosine = hit.normal.dot(direction.unit());
+
+        Some(cosine.max(0.0) / PI)
+    }
+
+    fn eval(&self, r: Ray, hit: &VisibleHit, direction: Vec3<f64>) -> Color {
+        self.albedo * self.pdf(r, hit, direction).unwrap_or(0.0)
+    }
 }
@@ -0,0 +1,32 @@
+use crate::{color::Color, geometry::Ray, object::VisibleHit, sampler::Sampler};
+
+use super::Material;
+
+/// Emits light of a fixed color from both sides of a surface and absorbs all incoming light
+pub struct DiffuseLight {
+    emit: Color,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Color) -> Self {
+        Self { emit }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn albedo(&self, _hit: &VisibleHit) -> Color {
+        self.emit
+    }
+
+    fn scatter(&self, _r: Ray, _hit: &VisibleHit, _sampler: &mut Sampler) -> Option<(Ray, Color)> {
+        None
+    }
+
+    fn emitted(&self, _hit: &VisibleHit) -> Color {
+        self.emit
+    }
+
+    fn is_emissive(&self) -> bool {
+        true
+    }
+}
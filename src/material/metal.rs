@@ -1,7 +1,10 @@
+use std::f64::consts::PI;
+
 use crate::{
     color::Color,
     geometry::{Ray, Vec3},
     object::VisibleHit,
+    sampler::Sampler,
 };
 
 use super::Material;
@@ -21,13 +24,18 @@ impl Metal {
 }
 
 impl Material for Metal {
-    fn scatter(&self, r: Ray, hit: &VisibleHit) -> Option<(Ray, Color)> {
+    fn albedo(&self, _hit: &VisibleHit) -> Color {
+        self.albedo
+    }
+
+    fn scatter(&self, r: Ray, hit: &VisibleHit, sampler: &mut Sampler) -> Option<(Ray, Color)> {
         let reflected = r.direction().reflect(hit.normal);
         let attenuation = self.albedo;
 
-        let scattered = Ray::new(
+        let scattered = Ray::with_time(
             hit.point,
-            reflected + self.fuzz * Vec3::random_in_unit_sphere(),
+            reflected + self.fuzz * Vec3::random_in_unit_sphere(sampler),
+            r.time(),
         );
 
         if reflected.dot(hit.normal) > 0.0 {
@@ -36,4 +44,84 @@ impl Material for Metal {
             None
         }
     }
+
+    fn pdf(&self, r: Ray, hit: &VisibleHit, direction: Vec3<f64>) -> Option<f64> {
+        if self.fuzz == 0.0 {
+            return None;
+        }
+
+        let reflected = r.direction().reflect(hit.normal);
+        if reflected.dot(hit.normal) <= 0.0 {
+            return Some(0.0);
+        }
+
+        // Scattered directions point from the origin to a uniform point x in the ball of radius
+        // `fuzz` around `reflected`. Integrating the ball's density (3 / 4πf³) along the ray
+        // s * direction with the s² ds solid angle measure gives (s1³ - s0³) / (4πf³), where
+        // [s0, s1] is the ray's chord through the ball.
+        let dir = direction.unit();
+        let b = dir.dot(reflected);
+        let discriminant = b * b - reflected.len_sq() + self.fuzz * self.fuzz;
+        if discriminant <= 0.0 {
+            return Some(0.0);
+        }
+
+        let s1 = b + discriminant.sqrt();
+        let s0 = (b - discriminant.sqrt()).max(0.0);
+        if s1 <= 0.0 {
+            return Some(0.0);
+        }
+
+        Some((s1.powi(3) - s0.powi(3)) / (4.0 * PI * self.fuzz.powi(3)))
+    }
+
+    fn eval(&self, r: Ray, hit: &VisibleHit, direction: Vec3<f64>) -> Color {
+        self.albedo * self.pdf(r, hit, direction).unwrap_or(0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::geometry::Point;
+
+    use super::*;
+
+    #[test]
+    fn fuzzy_pdf_integrates_to_one() {
+        let metal = Arc::new(Metal::new(Color::white(), 0.5));
+        let r = Ray::new(Point::new(0.0, 1.0, 0.0), Vec3::new(1.0, -1.0, 0.0).unit());
+        let hit = VisibleHit::new(
+            r,
+            Point::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            2.0_f64.sqrt(),
+            metal.clone(),
+        );
+
+        // Monte Carlo estimate of the pdf's integral over the sphere of directions
+        let samples = 200_000;
+        let mut sampler = Sampler::new(0);
+        let total: f64 = (0..samples)
+            .map(|_| metal.pdf(r, &hit, Vec3::random_unit(&mut sampler)).unwrap() * 4.0 * PI)
+            .sum();
+
+        assert!((total / samples as f64 - 1.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn mirror_is_specular() {
+        let metal = Arc::new(Metal::new(Color::white(), 0.0));
+        let r = Ray::new(Point::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let hit = VisibleHit::new(
+            r,
+            Point::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            1.0,
+            metal.clone(),
+        );
+
+        assert!(metal.pdf(r, &hit, Vec3::new(0.0, 1.0, 0.0)).is_none());
+    }
 }
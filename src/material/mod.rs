@@ -1,13 +1,46 @@
-use crate::{color::Color, geometry::Ray, object::VisibleHit};
+use crate::{
+    color::Color,
+    geometry::{Ray, Vec3},
+    object::VisibleHit,
+    sampler::Sampler,
+};
 
 mod dielectric;
 mod lambertian;
+mod light;
 mod metal;
 
 pub use dielectric::*;
 pub use lambertian::*;
+pub use light::*;
 pub use metal::*;
 
 pub trait Material: Sync + Send {
-    fn scatter(&self, r: Ray, hit: &VisibleHit) -> Option<(Ray, Color)>;
+    fn scatter(&self, r: Ray, hit: &VisibleHit, sampler: &mut Sampler) -> Option<(Ray, Color)>;
+
+    /// Radiance given off by the surface at the hit point, black for non-emissive materials
+    fn emitted(&self, _hit: &VisibleHit) -> Color {
+        Color::black()
+    }
+
+    /// Base surface color at the hit, used by debug renders
+    fn albedo(&self, _hit: &VisibleHit) -> Color {
+        Color::white()
+    }
+
+    fn is_emissive(&self) -> bool {
+        false
+    }
+
+    /// Solid-angle density with which `scatter` picks `direction`.
+    /// `None` for specular materials, whose scattering can only be sampled and never evaluated.
+    fn pdf(&self, _r: Ray, _hit: &VisibleHit, _direction: Vec3<f64>) -> Option<f64> {
+        None
+    }
+
+    /// Scattering towards `direction` (BSDF times cosine), consistent with `scatter` in that
+    /// `eval(d) / pdf(d)` equals the attenuation returned when `d` is sampled
+    fn eval(&self, _r: Ray, _hit: &VisibleHit, _direction: Vec3<f64>) -> Color {
+        Color::black()
+    }
 }
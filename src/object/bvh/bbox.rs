@@ -2,7 +2,7 @@
 
 use std::ops::{Add, AddAssign, Range};
 
-use crate::geometry::{Point, Ray};
+use crate::geometry::{AnimatedTransform, Point, Ray, Transform};
 
 use super::Intersect;
 
@@ -40,6 +40,35 @@ impl BoundingBox {
     pub fn z(&self) -> Range<f64> {
         self.z.clone()
     }
+
+    /// The smallest box containing this one after it has been transformed
+    pub fn transform(&self, t: &Transform) -> Self {
+        let corners: Vec<_> = self.corners().into_iter().map(|p| t.point(p)).collect();
+
+        Self::from_points(&corners)
+    }
+
+    /// A box containing this one at every point in time along `motion`
+    pub fn animate(&self, motion: &AnimatedTransform) -> Self {
+        let (min, max) = motion.bound(&self.corners());
+
+        Self::from_points(&[min, max])
+    }
+
+    fn corners(&self) -> [Point<f64>; 8] {
+        let mut corners = [Point::new(0.0, 0.0, 0.0); 8];
+        let mut idx = 0;
+        for x in [self.x.start, self.x.end] {
+            for y in [self.y.start, self.y.end] {
+                for z in [self.z.start, self.z.end] {
+                    corners[idx] = Point::new(x, y, z);
+                    idx += 1;
+                }
+            }
+        }
+
+        corners
+    }
 }
 
 impl AddAssign for BoundingBox {
@@ -109,9 +138,9 @@ impl Intersect for BoundingBox {
         // combine all possible range of t to find the t's that satify all
         let combined = intersection(&t_range_x, &t_range_y)
             .and_then(|combined| intersection(&combined, &t_range_z))
-            .and_then(|combined| intersection(&combined, &t_range));
+            .and_then(|combined| intersection(&combined, t_range));
 
-        combined.and_then(|range| Some(range.start))
+        combined.map(|range| range.start)
     }
 }
 
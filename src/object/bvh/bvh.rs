@@ -1,11 +1,23 @@
-use std::{fmt::Display, ops::Range, sync::Arc};
+use std::{
+    fmt::Display,
+    io, mem,
+    ops::Range,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
 
 use crate::{
-    geometry::{Point, Ray},
+    geometry::{Point, Ray, Vec3},
     object::{Visible, VisibleHit},
+    sampler::Sampler,
 };
 
-use super::BoundingBox;
+use super::{
+    cache::{too_large, Decoder, Encoder},
+    BoundingBox, BvhStats, TraversalCounts,
+};
 
 /*
 
@@ -37,10 +49,37 @@ assign this.right to build_bvh of right
 
 */
 
-// BVH Parameters
-const NUM_BUCKETS: usize = 10;
-const COST_TRAVERSAL: f64 = 1.0 / 4.0;
-const MAX_PRIMS_PER_NODE: usize = 4;
+/// Parameters for building a `BvhTree` with the surface area heuristic (SAH).
+///
+/// The SAH estimates the cost of a node as the cost of testing its children, weighted by the
+/// probability that a ray through the node also passes through each child (the ratio of their
+/// surface areas). Only the ratio of the two costs affects the tree's shape.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BvhConfig {
+    /// Cost of testing a ray against the bounding box of an inner node
+    pub traversal_cost: f64,
+    /// Cost of testing a ray against a single primitive
+    pub intersection_cost: f64,
+    /// Number of equal-width buckets along each axis whose boundaries are the candidate splits
+    pub num_buckets: usize,
+    /// Nodes with more primitives than this are split even if the SAH would rather make a leaf
+    pub max_prims_per_node: usize,
+    /// `BvhTree::update` rebuilds a refit tree once its SAH cost has grown by more than this
+    /// factor since it was built
+    pub max_refit_cost_ratio: f64,
+}
+
+impl Default for BvhConfig {
+    fn default() -> Self {
+        Self {
+            traversal_cost: 0.25,
+            intersection_cost: 1.0,
+            num_buckets: 10,
+            max_prims_per_node: 4,
+            max_refit_cost_ratio: 1.5,
+        }
+    }
+}
 
 pub trait Bounded {
     fn bbox(&self) -> BoundingBox;
@@ -53,19 +92,30 @@ pub trait Intersect {
     fn intersect(&self, r: Ray, t_range: &Range<f64>) -> Option<f64>;
 }
 
-pub trait Primitive: Bounded + Visible + Sync + Send {}
+pub trait Primitive: Bounded + Visible + Sync + Send {
+    /// Picks a point uniformly over the surface as it is at `time`, returning it along with the
+    /// surface normal there
+    fn sample_surface(&self, time: f64, sampler: &mut Sampler) -> (Point<f64>, Vec3<f64>);
+
+    /// Whether `surface_area` and `sample_surface` are exact, as sampling the primitive as a
+    /// light requires. They may only be estimates for e.g. non-uniformly scaled geometry.
+    fn samples_uniformly(&self) -> bool {
+        true
+    }
+}
 
 impl Bounded for BoundingBox {
     fn bbox(&self) -> BoundingBox {
-        return self.clone();
+        self.clone()
     }
 
     fn surface_area(&self) -> f64 {
         let x = self.x();
         let y = self.y();
         let z = self.z();
+        let (dx, dy, dz) = (x.end - x.start, y.end - y.start, z.end - z.start);
 
-        (x.end - x.start) * (y.end - y.start) * (z.end - z.start)
+        2.0 * (dx * dy + dy * dz + dz * dx)
     }
 
     fn centroid(&self) -> Point<f64> {
@@ -81,33 +131,634 @@ impl Bounded for BoundingBox {
     }
 }
 
+/// Tag of leaves in the layout written by `BvhTree::write_layout`, inner nodes are tagged with
+/// their split axis
+const LEAF_TAG: u8 = 3;
+
+/// Maximum depth of the tree, which bounds the traversal stack
+const MAX_DEPTH: usize = 64;
+/// Subtrees over at least this many primitives build their children in parallel
+const PARALLEL_BUILD_THRESHOLD: usize = 1024;
+
+/// Bounding volume hierarchy stored as a flat array of nodes in depth-first order, so the first
+/// child of an inner node directly follows it. Leaves refer to a contiguous range of
+/// `primitive_indices`, which index into `primitives`.
 pub struct BvhTree {
-    // TODO: convert to a linear representation to improve data locality and speed
-    root: BvhNode,
+    nodes: Vec<LinearNode>,
+    primitives: Vec<Arc<dyn Primitive>>,
+    primitive_indices: Vec<usize>,
+    config: BvhConfig,
+    build_time: Duration,
+    /// SAH cost right after the last full build, to tell how much refitting has degraded the tree
+    built_sah_cost: f64,
+}
+
+struct LinearNode {
+    bbox: BoundingBox,
+    kind: LinearNodeKind,
+}
+
+enum LinearNodeKind {
+    /// `axis` is the axis the children were split on, with the first child on the lower side
+    Inner {
+        second_child: u32,
+        axis: Axis,
+    },
+    Leaf {
+        first: u32,
+        count: u32,
+    },
 }
 
 impl BvhTree {
+    /// Builds the tree over `primitives` with the default `BvhConfig`. Hits report the index of
+    /// the primitive in this list as their `primitive_id`.
     pub fn build(primitives: Vec<Arc<dyn Primitive>>) -> Self {
-        let root = BvhNode::build(primitives);
+        Self::build_with(primitives, BvhConfig::default())
+    }
+
+    pub fn build_with(primitives: Vec<Arc<dyn Primitive>>, config: BvhConfig) -> Self {
+        assert!(config.num_buckets >= 2, "BVH needs at least two buckets");
+
+        let start = Instant::now();
+
+        let mut tree = Self {
+            nodes: Vec::new(),
+            primitives,
+            primitive_indices: Vec::new(),
+            config,
+            build_time: Duration::ZERO,
+            built_sah_cost: 0.0,
+        };
+
+        if !tree.primitives.is_empty() {
+            // computed once up front, the recursive build only moves these around
+            let primitive_info = tree
+                .primitives
+                .par_iter()
+                .enumerate()
+                .map(|(index, prim)| PrimitiveInfo {
+                    index,
+                    bbox: prim.bbox(),
+                    centroid: prim.centroid(),
+                })
+                .collect();
+
+            let root = BvhNode::build(primitive_info, 0, &tree.config);
+            tree.flatten(root);
+        }
+
+        tree.built_sah_cost = tree.sah_cost();
+        tree.build_time = start.elapsed();
+
+        tree
+    }
+
+    /// Replaces the primitives with moved versions of them, given in the same order, and
+    /// recomputes the bounds of every node. The shape of the tree is kept, so this is much
+    /// cheaper than building a new one, but the tree gets slower to traverse as primitives move
+    /// relative to each other. `needs_rebuild` tells when that has gone too far.
+    pub fn refit(&mut self, primitives: Vec<Arc<dyn Primitive>>) {
+        assert_eq!(
+            primitives.len(),
+            self.primitives.len(),
+            "refitting needs the same number of primitives"
+        );
+
+        let start = Instant::now();
+
+        self.primitives = primitives;
+        let bboxes: Vec<_> = self.primitives.par_iter().map(|prim| prim.bbox()).collect();
+
+        // children are stored after their parent, so walking backwards updates them first
+        for idx in (0..self.nodes.len()).rev() {
+            let bbox = match self.nodes[idx].kind {
+                LinearNodeKind::Inner { second_child, .. } => {
+                    self.nodes[idx + 1].bbox.clone()
+                        + self.nodes[second_child as usize].bbox.clone()
+                }
+                LinearNodeKind::Leaf { first, count } => {
+                    let (first, count) = (first as usize, count as usize);
+
+                    self.primitive_indices[first..(first + count)]
+                        .iter()
+                        .map(|&prim| bboxes[prim].clone())
+                        .reduce(|acc, bbox| acc + bbox)
+                        .unwrap()
+                }
+            };
+
+            self.nodes[idx].bbox = bbox;
+        }
+
+        self.build_time = start.elapsed();
+    }
+
+    /// Whether refitting has made the tree enough slower than a freshly built one, going by their
+    /// SAH costs, that it is worth rebuilding
+    pub fn needs_rebuild(&self) -> bool {
+        self.sah_cost() > self.built_sah_cost * self.config.max_refit_cost_ratio
+    }
+
+    /// Moves the primitives like `refit`, but builds the tree anew if refitting would leave it
+    /// too slow (see `needs_rebuild`). Returns whether the tree was rebuilt.
+    pub fn update(&mut self, primitives: Vec<Arc<dyn Primitive>>) -> bool {
+        self.refit(primitives);
+
+        if !self.needs_rebuild() {
+            return false;
+        }
+
+        let primitives = mem::take(&mut self.primitives);
+        *self = Self::build_with(primitives, self.config.clone());
+
+        true
+    }
+
+    /// Writes the shape of the tree, but not the primitives, for `read_layout` to restore.
+    /// Fails for trees too large for the `u32` indices of the layout, rather than truncating them.
+    pub(super) fn write_layout(&self, out: &mut Encoder) -> io::Result<()> {
+        // child and leaf indices are only exact when every node and primitive index fits
+        u32::try_from(self.nodes.len()).map_err(|_| too_large("BVH nodes"))?;
+        u32::try_from(self.primitive_indices.len()).map_err(|_| too_large("primitives"))?;
+
+        out.u64(self.nodes.len() as u64);
+        for node in &self.nodes {
+            for range in [node.bbox.x(), node.bbox.y(), node.bbox.z()] {
+                out.f64(range.start);
+                out.f64(range.end);
+            }
+
+            match node.kind {
+                LinearNodeKind::Inner { second_child, axis } => {
+                    out.u8(axis as u8);
+                    out.u32(second_child);
+                    out.u32(0);
+                }
+                LinearNodeKind::Leaf { first, count } => {
+                    out.u8(LEAF_TAG);
+                    out.u32(first);
+                    out.u32(count);
+                }
+            }
+        }
+
+        out.u64(self.primitive_indices.len() as u64);
+        for &idx in &self.primitive_indices {
+            out.u32(u32::try_from(idx).map_err(|_| too_large("primitives"))?);
+        }
+
+        out.f64(self.built_sah_cost);
+
+        Ok(())
+    }
+
+    /// Restores a tree over `primitives` from the shape written by `write_layout`, which must
+    /// have been built over the same primitives in the same order
+    pub(super) fn read_layout(
+        input: &mut Decoder,
+        primitives: Vec<Arc<dyn Primitive>>,
+        config: BvhConfig,
+    ) -> io::Result<Self> {
+        let start = Instant::now();
+        let invalid = |what: &str| io::Error::new(io::ErrorKind::InvalidData, what.to_string());
+
+        let node_count = input.len()?;
+        let mut nodes = Vec::with_capacity(node_count);
+        for _ in 0..node_count {
+            let mut ranges = [0.0..0.0, 0.0..0.0, 0.0..0.0];
+            for range in &mut ranges {
+                *range = input.f64()?..input.f64()?;
+            }
+            let [x, y, z] = ranges;
+
+            let (tag, a, b) = (input.u8()?, input.u32()?, input.u32()?);
+            let kind = match tag {
+                LEAF_TAG => LinearNodeKind::Leaf { first: a, count: b },
+                tag => LinearNodeKind::Inner {
+                    second_child: a,
+                    axis: match tag {
+                        0 => Axis::X,
+                        1 => Axis::Y,
+                        2 => Axis::Z,
+                        _ => return Err(invalid("unknown BVH node kind")),
+                    },
+                },
+            };
+
+            nodes.push(LinearNode {
+                bbox: BoundingBox::new(x, y, z),
+                kind,
+            });
+        }
+
+        let index_count = input.len()?;
+        let mut primitive_indices = Vec::with_capacity(index_count);
+        for _ in 0..index_count {
+            primitive_indices.push(input.u32()? as usize);
+        }
+
+        let built_sah_cost = input.f64()?;
+
+        let tree = Self {
+            nodes,
+            primitives,
+            primitive_indices,
+            config,
+            build_time: start.elapsed(),
+            built_sah_cost,
+        };
+
+        // checked so that a mismatched layout fails here rather than while traversing
+        tree.check_layout().map_err(invalid)?;
+
+        Ok(tree)
+    }
+
+    /// Checks that the nodes form a tree laid out the way `flatten` does it, no deeper than the
+    /// traversal stack allows, and that its leaves cover every primitive exactly once
+    fn check_layout(&self) -> Result<(), &'static str> {
+        // the next leaf has to start where the previous one ended
+        let mut covered = 0;
+        // (node, depth) of the second children still to visit
+        let mut pending = Vec::new();
+
+        if !self.nodes.is_empty() {
+            pending.push((0, 1));
+        }
+
+        // depth-first order, so every node has to come right after the one visited before it
+        for idx in 0..self.nodes.len() {
+            let (node, depth) = pending.pop().ok_or("BVH has unreachable nodes")?;
+            if node != idx {
+                return Err("BVH nodes out of order");
+            }
+            if depth > MAX_DEPTH {
+                return Err("BVH is too deep");
+            }
+
+            match self.nodes[idx].kind {
+                LinearNodeKind::Inner { second_child, .. } => {
+                    let second_child = second_child as usize;
+                    if second_child <= idx + 1 || second_child >= self.nodes.len() {
+                        return Err("BVH node out of bounds");
+                    }
+
+                    pending.push((second_child, depth + 1));
+                    pending.push((idx + 1, depth + 1));
+                }
+                LinearNodeKind::Leaf { first, count } => {
+                    let (first, count) = (first as usize, count as usize);
+                    if first != covered || first + count > self.primitive_indices.len() {
+                        return Err("BVH leaf out of bounds");
+                    }
+
+                    covered += count;
+                }
+            }
+        }
+
+        if !pending.is_empty() {
+            return Err("BVH nodes out of order");
+        }
+
+        let mut seen = vec![false; self.primitives.len()];
+        for &idx in &self.primitive_indices {
+            match seen.get_mut(idx) {
+                Some(seen) if !*seen => *seen = true,
+                _ => return Err("BVH refers to missing or repeated primitives"),
+            }
+        }
+        if covered != self.primitive_indices.len() || seen.contains(&false) {
+            return Err("BVH leaves don't cover every primitive");
+        }
 
-        Self { root }
+        Ok(())
+    }
+
+    /// Appends `node` and its subtree in depth-first order, returning the index of `node`
+    fn flatten(&mut self, node: BvhNode) -> usize {
+        let idx = self.nodes.len();
+
+        match node {
+            BvhNode::Inner(inner) => {
+                self.nodes.push(LinearNode {
+                    bbox: inner.bbox,
+                    kind: LinearNodeKind::Inner {
+                        second_child: 0,
+                        axis: inner.split.axis,
+                    },
+                });
+
+                self.flatten(*inner.left);
+                let second = self.flatten(*inner.right);
+
+                if let LinearNodeKind::Inner { second_child, .. } = &mut self.nodes[idx].kind {
+                    *second_child = second as u32;
+                }
+            }
+            BvhNode::Leaf(leaf) => {
+                let first = self.primitive_indices.len() as u32;
+                let count = leaf.primitives.len() as u32;
+
+                self.primitive_indices.extend(leaf.primitives);
+                self.nodes.push(LinearNode {
+                    bbox: leaf.bbox,
+                    kind: LinearNodeKind::Leaf { first, count },
+                });
+            }
+        }
+
+        idx
+    }
+
+    pub fn bbox(&self) -> BoundingBox {
+        self.nodes
+            .first()
+            .map(|root| root.bbox.clone())
+            .unwrap_or(BoundingBox::empty())
+    }
+
+    pub fn len(&self) -> usize {
+        self.primitives.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.primitives.is_empty()
+    }
+
+    /// The primitives in the order they were given to `build`
+    pub fn primitives(&self) -> &[Arc<dyn Primitive>] {
+        &self.primitives
+    }
+
+    /// Wall-clock time it took to build the tree, or to refit it if it has been since
+    pub fn build_time(&self) -> Duration {
+        self.build_time
+    }
+
+    pub fn config(&self) -> &BvhConfig {
+        &self.config
+    }
+
+    /// Expected cost of tracing a ray through the root under the surface area heuristic
+    pub fn sah_cost(&self) -> f64 {
+        let root_area = match self.nodes.first() {
+            Some(root) => root.bbox.surface_area(),
+            None => return 0.0,
+        };
+
+        self.nodes
+            .iter()
+            .map(|node| {
+                let cost = match node.kind {
+                    LinearNodeKind::Inner { .. } => self.config.traversal_cost,
+                    LinearNodeKind::Leaf { count, .. } => {
+                        self.config.intersection_cost * count as f64
+                    }
+                };
+
+                hit_probability(&node.bbox, root_area) * cost
+            })
+            .sum()
+    }
+
+    pub fn stats(&self) -> BvhStats {
+        let memory_bytes = self.nodes.len() * mem::size_of::<LinearNode>()
+            + self.primitive_indices.len() * mem::size_of::<usize>()
+            + self.primitives.len() * mem::size_of::<Arc<dyn Primitive>>();
+
+        let mut stats = BvhStats {
+            primitives: self.primitives.len(),
+            inner_nodes: 0,
+            leaves: 0,
+            max_depth: 0,
+            mean_depth: 0.0,
+            min_leaf_size: 0,
+            max_leaf_size: 0,
+            mean_leaf_size: 0.0,
+            leaf_size_histogram: Vec::new(),
+            sah_cost: self.sah_cost(),
+            memory_bytes,
+        };
+
+        if self.nodes.is_empty() {
+            return stats;
+        }
+
+        stats.min_leaf_size = usize::MAX;
+        let mut depth_sum = 0;
+
+        // (node, depth) pairs still to visit
+        let mut stack = vec![(0, 0)];
+        while let Some((idx, depth)) = stack.pop() {
+            match self.nodes[idx].kind {
+                LinearNodeKind::Inner { second_child, .. } => {
+                    stats.inner_nodes += 1;
+
+                    stack.push((idx + 1, depth + 1));
+                    stack.push((second_child as usize, depth + 1));
+                }
+                LinearNodeKind::Leaf { count, .. } => {
+                    let count = count as usize;
+
+                    stats.leaves += 1;
+                    stats.max_depth = stats.max_depth.max(depth);
+                    depth_sum += depth;
+
+                    if stats.leaf_size_histogram.len() <= count {
+                        stats.leaf_size_histogram.resize(count + 1, 0);
+                    }
+                    stats.leaf_size_histogram[count] += 1;
+
+                    stats.min_leaf_size = stats.min_leaf_size.min(count);
+                    stats.max_leaf_size = stats.max_leaf_size.max(count);
+                }
+            }
+        }
+
+        stats.mean_depth = depth_sum as f64 / stats.leaves as f64;
+        stats.mean_leaf_size = stats.primitives as f64 / stats.leaves as f64;
+
+        stats
     }
 
     pub fn print(&self) {
-        self.root.print(0);
+        if !self.nodes.is_empty() {
+            self.print_node(0, 0);
+        }
+    }
+
+    fn print_node(&self, idx: usize, offset: usize) {
+        let gap = "\t".repeat(offset);
+        print!("{}", gap);
+
+        match &self.nodes[idx].kind {
+            LinearNodeKind::Inner { second_child, axis } => {
+                println!("Inner (split on {})", axis);
+
+                self.print_node(idx + 1, offset + 1);
+                self.print_node(*second_child as usize, offset + 1);
+            }
+            LinearNodeKind::Leaf { count, .. } => println!("Leaf ({})", count),
+        }
     }
 }
 
 impl Visible for BvhTree {
     fn bounce(&self, r: Ray, t_range: &Range<f64>) -> Option<VisibleHit> {
-        self.root.bounce(r, t_range)
+        self.traverse::<false>(r, t_range, &mut TraversalCounts::default())
+    }
+
+    fn occluded(&self, r: Ray, t_range: &Range<f64>) -> bool {
+        if self.nodes.is_empty() {
+            return false;
+        }
+
+        // any hit will do, so children are visited in storage order and the range never shrinks
+        let mut stack = [0; MAX_DEPTH];
+        let mut stack_len = 0;
+        let mut current = 0;
+
+        loop {
+            let node = &self.nodes[current];
+
+            if node.bbox.intersect(r, t_range).is_some() {
+                match &node.kind {
+                    LinearNodeKind::Inner { second_child, .. } => {
+                        stack[stack_len] = *second_child as usize;
+                        stack_len += 1;
+                        current += 1;
+
+                        continue;
+                    }
+                    LinearNodeKind::Leaf { first, count } => {
+                        let (first, count) = (*first as usize, *count as usize);
+
+                        if self.primitive_indices[first..(first + count)]
+                            .iter()
+                            .any(|&idx| self.primitives[idx].occluded(r, t_range))
+                        {
+                            return true;
+                        }
+                    }
+                }
+            }
+
+            if stack_len == 0 {
+                return false;
+            }
+
+            stack_len -= 1;
+            current = stack[stack_len];
+        }
+    }
+}
+
+impl BvhTree {
+    /// Like `bounce`, but also counts the bounding box and primitive tests made along the way
+    pub fn bounce_counted(
+        &self,
+        r: Ray,
+        t_range: &Range<f64>,
+    ) -> (Option<VisibleHit>, TraversalCounts) {
+        let mut counts = TraversalCounts::default();
+        let hit = self.traverse::<true>(r, t_range, &mut counts);
+
+        (hit, counts)
+    }
+
+    /// Finds the closest hit, only updating `counts` when `COUNT` is set so that the counting
+    /// compiles away for regular queries
+    fn traverse<const COUNT: bool>(
+        &self,
+        r: Ray,
+        t_range: &Range<f64>,
+        counts: &mut TraversalCounts,
+    ) -> Option<VisibleHit> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let dir = r.direction();
+        let dir_is_neg = |axis: &Axis| match axis {
+            Axis::X => dir.x() < 0.0,
+            Axis::Y => dir.y() < 0.0,
+            Axis::Z => dir.z() < 0.0,
+        };
+
+        let mut closest_hit: Option<VisibleHit> = None;
+        let mut closest_t = t_range.end;
+
+        // nodes still to visit, at most one pending sibling per level of the tree
+        let mut stack = [0; MAX_DEPTH];
+        let mut stack_len = 0;
+        let mut current = 0;
+
+        loop {
+            let node = &self.nodes[current];
+            if COUNT {
+                counts.box_tests += 1;
+            }
+
+            if node
+                .bbox
+                .intersect(r, &(t_range.start..closest_t))
+                .is_some()
+            {
+                match &node.kind {
+                    LinearNodeKind::Inner { second_child, axis } => {
+                        // visit the child on the side the ray comes from first, so closer hits
+                        // can prune the other
+                        let second_child = *second_child as usize;
+                        let (near, far) = if dir_is_neg(axis) {
+                            (second_child, current + 1)
+                        } else {
+                            (current + 1, second_child)
+                        };
+
+                        stack[stack_len] = far;
+                        stack_len += 1;
+                        current = near;
+
+                        continue;
+                    }
+                    LinearNodeKind::Leaf { first, count } => {
+                        let (first, count) = (*first as usize, *count as usize);
+
+                        if COUNT {
+                            counts.primitive_tests += count as u32;
+                        }
+
+                        for &idx in &self.primitive_indices[first..(first + count)] {
+                            let hit = self.primitives[idx].bounce(r, &(t_range.start..closest_t));
+
+                            if let Some(mut hit) = hit {
+                                closest_t = hit.t;
+                                hit.primitive_id = Some(idx);
+                                closest_hit = Some(hit);
+                            }
+                        }
+                    }
+                }
+            }
+
+            if stack_len == 0 {
+                break;
+            }
+
+            stack_len -= 1;
+            current = stack[stack_len];
+        }
+
+        closest_hit
     }
 }
 
-fn containing_bbox(items: &[Arc<dyn Primitive>]) -> BoundingBox {
+fn containing_bbox(items: &[PrimitiveInfo]) -> BoundingBox {
     items
         .iter()
-        .map(|p| p.bbox())
+        .map(|p| p.bbox.clone())
         .reduce(|acc, item| acc + item)
         .unwrap_or(BoundingBox::empty())
 }
@@ -118,11 +769,13 @@ struct Bucket {
 }
 
 struct PrimitiveInfo {
-    primitive: Arc<dyn Primitive>,
+    /// Position of the primitive in the list the tree was built from
+    index: usize,
     centroid: Point<f64>,
     bbox: BoundingBox,
 }
 
+#[derive(Debug, Clone, Copy)]
 enum Axis {
     X,
     Y,
@@ -150,144 +803,104 @@ impl Display for Axis {
     }
 }
 
-struct Split {
-    axis: Axis,
-    threshold: f64,
-    cost: f64,
+/// Probability that a ray passing through a node with surface area `parent_area` also passes
+/// through `bbox`, which lies inside it
+fn hit_probability(bbox: &BoundingBox, parent_area: f64) -> f64 {
+    if parent_area > 0.0 {
+        bbox.surface_area() / parent_area
+    } else {
+        // every box inside a zero-area node is the same point
+        1.0
+    }
 }
 
-impl Split {
-    fn partition(
-        &self,
-        items: Vec<PrimitiveInfo>,
-    ) -> (Vec<Arc<dyn Primitive>>, Vec<Arc<dyn Primitive>>) {
-        let (left, right): (Vec<_>, Vec<_>) = items
-            .into_iter()
-            .partition(|item| self.axis.of(&item.centroid) < self.threshold);
+/// Equal-width buckets spanning the centroids of a node along one axis
+struct BucketGrid {
+    start: f64,
+    width: f64,
+    count: usize,
+}
 
-        let left = left
-            .into_iter()
-            .map(|prim_info| prim_info.primitive)
-            .collect();
-        let right = right
-            .into_iter()
-            .map(|prim_info| prim_info.primitive)
-            .collect();
+impl BucketGrid {
+    fn index(&self, coord: f64) -> usize {
+        // negative or NaN offsets saturate to the first bucket
+        let idx = ((coord - self.start) / self.width) as usize;
 
-        (left, right)
+        idx.min(self.count - 1)
     }
 }
 
-// const COST_TRAVERSAL: f64 = f64::INFINITY;
-// const MAX_PRIMS_PER_NODE: usize = 5;
-
-impl Visible for BvhNode {
-    fn bounce(&self, r: Ray, t_range: &Range<f64>) -> Option<VisibleHit> {
-        self.intersect(r, t_range)?;
-
-        match self {
-            BvhNode::Inner(inner) => {
-                let (left_t, right_t) = (
-                    inner.left.intersect(r, t_range),
-                    inner.right.intersect(r, t_range),
-                );
-
-                match (left_t, right_t) {
-                    (Some(left_t), Some(right_t)) => {
-                        let (first, first_t, second, second_t) = if left_t < right_t {
-                            (&inner.left, left_t, &inner.right, right_t)
-                        } else {
-                            (&inner.right, right_t, &inner.left, left_t)
-                        };
-
-                        let closest_hit = first.bounce(r, t_range);
-
-                        match closest_hit {
-                            Some(hit) => {
-                                let mut best_hit = hit;
-
-                                if best_hit.t >= second_t {
-                                    if let Some(second_hit) = second.bounce(r, t_range) {
-                                        if best_hit.t > second_hit.t {
-                                            best_hit = second_hit;
-                                        }
-                                    }
-                                }
-
-                                Some(best_hit)
-                            }
-                            None => second.bounce(r, t_range),
-                        }
-                    }
-                    (Some(left_t), None) => inner.left.bounce(r, t_range),
-                    (None, Some(right_t)) => inner.right.bounce(r, t_range),
-                    _ => None,
-                }
-            }
-            BvhNode::Leaf(leaf) => {
-                let mut closest_t = t_range.end;
-
-                leaf.primitives
-                    .iter()
-                    .filter_map(|x| {
-                        let hit = x.bounce(r, &(t_range.start..closest_t));
+struct Split {
+    axis: Axis,
+    cost: f64,
+    kind: SplitKind,
+}
 
-                        if let Some(ref record) = hit {
-                            closest_t = f64::min(closest_t, record.t);
-                        }
+enum SplitKind {
+    /// Centroids falling in buckets up to and including `last_left` go to the left child
+    Buckets { grid: BucketGrid, last_left: usize },
+    /// The half of the primitives with the lowest centroids goes to the left child
+    Median,
+}
 
-                        hit
-                    })
-                    .reduce(|acc, hit| if acc.t > hit.t { hit } else { acc })
+impl Split {
+    fn partition(&self, mut items: Vec<PrimitiveInfo>) -> (Vec<PrimitiveInfo>, Vec<PrimitiveInfo>) {
+        match &self.kind {
+            // uses the same bucket assignment the cost was computed from, so both sides are
+            // guaranteed to be non-empty
+            SplitKind::Buckets { grid, last_left } => items
+                .into_iter()
+                .partition(|item| grid.index(self.axis.of(&item.centroid)) <= *last_left),
+            SplitKind::Median => {
+                let mid = items.len() / 2;
+                items.select_nth_unstable_by(mid, |a, b| {
+                    self.axis
+                        .of(&a.centroid)
+                        .total_cmp(&self.axis.of(&b.centroid))
+                });
+                let right = items.split_off(mid);
+
+                (items, right)
             }
         }
     }
 }
 
 impl BvhNode {
-    fn print(&self, offset: usize) {
-        let gap = "\t".repeat(offset);
-        print!("{}", gap);
-
-        match self {
-            BvhNode::Inner(inner) => {
-                println!(
-                    "Inner (split on {} at {})",
-                    inner.split.axis, inner.split.threshold
-                );
-
-                inner.left.print(offset + 1);
-                inner.right.print(offset + 1);
-            }
-            BvhNode::Leaf(leaf) => println!("Leaf ({})", leaf.primitives.len()),
-        }
-    }
-
-    // FIXME: fails on equal centroids
-
-    fn find_best_split(primitives: &Vec<PrimitiveInfo>, axis: Axis) -> Option<Split> {
+    /// Finds the cheapest SAH split along `axis`, or `None` when every centroid lies in the same
+    /// plane along it. `parent_area` is the surface area of the node being split.
+    fn find_best_split(
+        primitives: &[PrimitiveInfo],
+        axis: Axis,
+        parent_area: f64,
+        config: &BvhConfig,
+    ) -> Option<Split> {
         let centroids = primitives.iter().map(|p| axis.of(&p.centroid));
         let centroid_bounds = centroids
             .map(|cent| cent..cent)
             .reduce(|acc, item| acc.start.min(item.start)..acc.end.max(item.end))?;
 
-        let mut buckets: Vec<_> = (0..NUM_BUCKETS)
+        let extent = centroid_bounds.end - centroid_bounds.start;
+        if !(extent > 0.0 && extent.is_finite()) {
+            return None;
+        }
+
+        let mut buckets: Vec<_> = (0..config.num_buckets)
             .map(|_| Bucket {
                 bbox: None,
                 count: 0,
             })
             .collect();
 
-        let bucket_size = (centroid_bounds.end - centroid_bounds.start) / (NUM_BUCKETS as f64);
+        let grid = BucketGrid {
+            start: centroid_bounds.start,
+            width: extent / (config.num_buckets as f64),
+            count: config.num_buckets,
+        };
 
         // assign each primitive to a bucket
         for prim in primitives.iter() {
-            let coord = axis.of(&prim.centroid);
-
-            let mut b = ((coord - centroid_bounds.start) / bucket_size).floor() as usize;
-            if b == buckets.len() {
-                b -= 1;
-            }
+            let b = grid.index(axis.of(&prim.centroid));
 
             buckets[b].bbox = match &buckets[b].bbox {
                 Some(bbox) => Some(bbox.clone() + prim.bbox.clone()),
@@ -297,7 +910,7 @@ impl BvhNode {
         }
 
         // consider all splits
-        let mut best_threshold = None;
+        let mut best_split = None;
         let mut best_cost = f64::INFINITY;
 
         for split_idx in 0..(buckets.len() - 1) {
@@ -318,17 +931,16 @@ impl BvhNode {
 
             match (left_bbox, right_bbox) {
                 (Some(left_bbox), Some(right_bbox)) => {
-                    let left_sa = left_bbox.surface_area();
-                    let right_sa = right_bbox.surface_area();
+                    let left_prob = hit_probability(&left_bbox, parent_area);
+                    let right_prob = hit_probability(&right_bbox, parent_area);
 
-                    let split_cost = COST_TRAVERSAL
-                        + (left_count as f64 * left_sa)
-                        + (right_count as f64 * right_sa);
+                    let split_cost = config.traversal_cost
+                        + config.intersection_cost
+                            * (left_count as f64 * left_prob + right_count as f64 * right_prob);
 
                     if split_cost < best_cost {
                         best_cost = split_cost;
-                        best_threshold =
-                            Some(centroid_bounds.start + ((split_idx + 1) as f64 * bucket_size));
+                        best_split = Some(split_idx);
                     }
                 }
                 _ => continue,
@@ -337,81 +949,543 @@ impl BvhNode {
 
         Some(Split {
             axis,
-            threshold: best_threshold?,
             cost: best_cost,
+            kind: SplitKind::Buckets {
+                grid,
+                last_left: best_split?,
+            },
         })
     }
 
-    fn build(primitives: Vec<Arc<dyn Primitive>>) -> Self {
-        let containing_bbox = containing_bbox(&primitives);
+    /// Splits the primitives in half along the axis with the widest spread of centroids, for when
+    /// the SAH finds no usable split. `None` if all centroids coincide, as no split can separate
+    /// them.
+    fn median_split(primitives: &[PrimitiveInfo]) -> Option<Split> {
+        let centroids: Vec<_> = primitives.iter().map(|p| p.centroid).collect();
+        let extent = |axis: Axis| {
+            let coords = centroids.iter().map(|c| axis.of(c));
+            let min = coords.clone().fold(f64::INFINITY, f64::min);
+            let max = coords.fold(f64::NEG_INFINITY, f64::max);
+
+            max - min
+        };
 
-        // precompute the computations needed for primitives
-        let primitive_info: Vec<_> = primitives
+        let (axis, widest) = [Axis::X, Axis::Y, Axis::Z]
             .into_iter()
-            .map(|prim| PrimitiveInfo {
-                bbox: prim.bbox(),
-                centroid: prim.centroid(),
-                primitive: prim,
+            .map(|axis| (axis, extent(axis)))
+            .reduce(|acc, item| if item.1 > acc.1 { item } else { acc })?;
+
+        if widest > 0.0 {
+            Some(Split {
+                axis,
+                cost: f64::INFINITY,
+                kind: SplitKind::Median,
             })
-            .collect();
+        } else {
+            None
+        }
+    }
 
-        let split_candidates = vec![
-            BvhNode::find_best_split(&primitive_info, Axis::X),
-            BvhNode::find_best_split(&primitive_info, Axis::Y),
-            BvhNode::find_best_split(&primitive_info, Axis::Z),
-        ];
+    /// Builds the subtree over `primitives`, where `depth` is the depth of the subtree's root
+    fn build(primitive_info: Vec<PrimitiveInfo>, depth: usize, config: &BvhConfig) -> Self {
+        let containing_bbox = containing_bbox(&primitive_info);
+        let area = containing_bbox.surface_area();
 
-        // choose the axis with the lowest cost, or all might be none
-        let best_split = split_candidates
+        let split_candidates = [Axis::X, Axis::Y, Axis::Z]
             .into_iter()
-            .filter_map(|s| s)
-            .reduce(|acc, split| if acc.cost > split.cost { split } else { acc });
-
-        let leaf_cost = primitive_info.len() as f64;
-        match best_split {
-            Some(split) if split.cost < leaf_cost || primitive_info.len() > MAX_PRIMS_PER_NODE => {
-                let (left, right) = split.partition(primitive_info);
-
-                let left_child = BvhNode::build(left);
-                let right_child = BvhNode::build(right);
-
-                BvhNode::Inner(BvhInner {
-                    bbox: containing_bbox,
-                    split,
-                    left: Arc::new(left_child),
-                    right: Arc::new(right_child),
-                })
+            .map(|axis| BvhNode::find_best_split(&primitive_info, axis, area, config));
+
+        // choose the axis with the lowest cost, or all might be none
+        let best_split = split_candidates.into_iter().flatten().reduce(|acc, split| {
+            if acc.cost > split.cost {
+                split
+            } else {
+                acc
+            }
+        });
+
+        let count = primitive_info.len();
+        let leaf_cost = config.intersection_cost * count as f64;
+        let max_prims = config.max_prims_per_node;
+        let split = if depth + 1 >= MAX_DEPTH {
+            None
+        } else {
+            match best_split {
+                Some(split) if split.cost < leaf_cost || count > max_prims => Some(split),
+                // the SAH had nothing to offer (e.g. every cost was NaN), but the node is too
+                // big to leave as a leaf
+                None if count > max_prims => BvhNode::median_split(&primitive_info),
+                _ => None,
             }
-            _ => BvhNode::Leaf(BvhLeaf {
-                bbox: containing_bbox,
-                primitives: primitive_info.into_iter().map(|p| p.primitive).collect(),
-            }),
+        };
+
+        let split = match split {
+            Some(split) => split,
+            None => return BvhNode::leaf(containing_bbox, primitive_info),
+        };
+
+        let (left, right) = split.partition(primitive_info);
+        if left.is_empty() || right.is_empty() {
+            // never recurse on a node that didn't get smaller
+            return BvhNode::leaf(containing_bbox, left.into_iter().chain(right).collect());
         }
+
+        let (left_child, right_child) = if count >= PARALLEL_BUILD_THRESHOLD {
+            rayon::join(
+                || BvhNode::build(left, depth + 1, config),
+                || BvhNode::build(right, depth + 1, config),
+            )
+        } else {
+            (
+                BvhNode::build(left, depth + 1, config),
+                BvhNode::build(right, depth + 1, config),
+            )
+        };
+
+        BvhNode::Inner(BvhInner {
+            bbox: containing_bbox,
+            split,
+            left: Box::new(left_child),
+            right: Box::new(right_child),
+        })
+    }
+
+    fn leaf(bbox: BoundingBox, primitive_info: Vec<PrimitiveInfo>) -> Self {
+        BvhNode::Leaf(BvhLeaf {
+            bbox,
+            primitives: primitive_info.into_iter().map(|p| p.index).collect(),
+        })
     }
 }
 
+/// Node of the tree built by the SAH splitter, before it is flattened into a `BvhTree`
 enum BvhNode {
     Inner(BvhInner),
     Leaf(BvhLeaf),
 }
 
-impl Intersect for BvhNode {
-    fn intersect(&self, r: Ray, t_range: &Range<f64>) -> Option<f64> {
-        match self {
-            BvhNode::Inner(inner) => inner.bbox.intersect(r, t_range),
-            BvhNode::Leaf(leaf) => leaf.bbox.intersect(r, t_range),
-        }
-    }
-}
-
 struct BvhInner {
     bbox: BoundingBox,
     split: Split,
-    left: Arc<BvhNode>,
-    right: Arc<BvhNode>,
+    left: Box<BvhNode>,
+    right: Box<BvhNode>,
 }
 
 struct BvhLeaf {
     bbox: BoundingBox,
-    primitives: Vec<Arc<dyn Primitive>>,
+    /// Indices of the primitives in the list the tree was built from
+    primitives: Vec<usize>,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        color::Color,
+        material::{Lambertian, Material},
+        object::{Sphere, Tri},
+    };
+
+    use super::*;
+
+    fn material() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new(Color::white()))
+    }
+
+    fn random_tris(seed: u64) -> Vec<Arc<dyn Primitive>> {
+        let mut sampler = Sampler::new(seed);
+        let material = material();
+
+        (0..500)
+            .map(|_| {
+                let offset = Vec3::random(&mut sampler, -5.0, 5.0);
+                let mut corner = || Point::from(offset + Vec3::random(&mut sampler, 0.0, 1.0));
+
+                Arc::new(Tri::new(
+                    corner(),
+                    corner(),
+                    corner(),
+                    Arc::clone(&material),
+                )) as Arc<dyn Primitive>
+            })
+            .collect()
+    }
+
+    fn depth(tree: &BvhTree, idx: usize) -> usize {
+        match tree.nodes[idx].kind {
+            LinearNodeKind::Inner { second_child, .. } => {
+                1 + depth(tree, idx + 1).max(depth(tree, second_child as usize))
+            }
+            LinearNodeKind::Leaf { .. } => 1,
+        }
+    }
+
+    /// Builds a tree over `primitives` and checks it finds the same closest hits as testing every
+    /// primitive, returning the tree for further checks
+    fn assert_matches_brute_force(primitives: Vec<Arc<dyn Primitive>>) -> BvhTree {
+        let bvh = BvhTree::build(primitives.clone());
+        assert!(depth(&bvh, 0) < MAX_DEPTH, "tree hit the depth limit");
+        assert_queries_match(&bvh, &primitives);
+
+        bvh
+    }
+
+    fn assert_queries_match(bvh: &BvhTree, primitives: &[Arc<dyn Primitive>]) {
+        let mut sampler = Sampler::new(8);
+        for _ in 0..1000 {
+            let origin = Point::from(Vec3::random(&mut sampler, -8.0, 8.0));
+            let r = Ray::new(origin, Vec3::random_unit(&mut sampler));
+            let t_range = 0.0..f64::INFINITY;
+
+            let expected = primitives
+                .iter()
+                .filter_map(|p| p.bounce(r, &t_range))
+                .map(|hit| hit.t)
+                .reduce(f64::min);
+            assert_eq!(bvh.bounce(r, &t_range).map(|hit| hit.t), expected);
+
+            let (hit, counts) = bvh.bounce_counted(r, &t_range);
+            assert_eq!(hit.map(|hit| hit.t), expected);
+            assert!(counts.box_tests >= 1 && counts.box_tests as usize <= bvh.nodes.len());
+            assert!(counts.primitive_tests as usize <= primitives.len());
+
+            assert_eq!(bvh.occluded(r, &t_range), expected.is_some());
+            let short_range = 0.0..4.0;
+            assert_eq!(
+                bvh.occluded(r, &short_range),
+                primitives
+                    .iter()
+                    .any(|p| p.bounce(r, &short_range).is_some())
+            );
+        }
+    }
+
+    #[test]
+    fn matches_linear_search() {
+        assert_matches_brute_force(random_tris(7));
+    }
+
+    #[test]
+    fn stacked_spheres() {
+        let material = material();
+        let spheres = (1..=50)
+            .map(|i| {
+                Arc::new(Sphere::new(
+                    Point::new(1.0, 2.0, 3.0),
+                    0.1 * i as f64,
+                    Arc::clone(&material),
+                )) as Arc<dyn Primitive>
+            })
+            .collect();
+
+        // no split can separate identical centroids
+        let bvh = assert_matches_brute_force(spheres);
+        assert_eq!(bvh.nodes.len(), 1);
+    }
+
+    #[test]
+    fn stacked_spheres_in_groups() {
+        let material = material();
+        let spheres = (0..60)
+            .map(|i| {
+                let center = Point::new((i % 3) as f64, 0.0, 0.0);
+                Arc::new(Sphere::new(center, 0.5, Arc::clone(&material))) as Arc<dyn Primitive>
+            })
+            .collect();
+
+        assert_matches_brute_force(spheres);
+    }
+
+    #[test]
+    fn duplicate_triangles() {
+        let material = material();
+        let mut tris = random_tris(3);
+        tris.extend((0..100).map(|_| {
+            Arc::new(Tri::new(
+                Point::new(0.0, 0.0, 0.0),
+                Point::new(1.0, 0.0, 0.0),
+                Point::new(0.0, 1.0, 0.0),
+                Arc::clone(&material),
+            )) as Arc<dyn Primitive>
+        }));
+
+        assert_matches_brute_force(tris);
+    }
+
+    #[test]
+    fn flat_mesh() {
+        let material = material();
+        let mut tris: Vec<Arc<dyn Primitive>> = Vec::new();
+
+        for i in 0..20 {
+            for j in 0..20 {
+                let (x, z) = (i as f64 * 0.5 - 5.0, j as f64 * 0.5 - 5.0);
+                let corner = |dx, dz| Point::new(x + dx, 0.0, z + dz);
+
+                tris.push(Arc::new(Tri::new(
+                    corner(0.0, 0.0),
+                    corner(0.5, 0.0),
+                    corner(0.5, 0.5),
+                    Arc::clone(&material),
+                )));
+                tris.push(Arc::new(Tri::new(
+                    corner(0.0, 0.0),
+                    corner(0.5, 0.5),
+                    corner(0.0, 0.5),
+                    Arc::clone(&material),
+                )));
+            }
+        }
+
+        assert_matches_brute_force(tris);
+    }
+
+    #[test]
+    fn symmetric_mesh() {
+        // an icosahedron, whose centroids fall exactly on bucket boundaries
+        let vertices = [
+            (-0.315439, 1.010390, 0.0),
+            (0.315439, 1.010390, 0.0),
+            (-0.315439, -0.010390, 0.0),
+            (0.315439, -0.010390, 0.0),
+            (0.0, 0.184561, 0.510390),
+            (0.0, 0.815439, 0.510390),
+            (0.0, 0.184561, -0.510390),
+            (0.0, 0.815439, -0.510390),
+            (0.510390, 0.5, -0.315439),
+            (0.510390, 0.5, 0.315439),
+            (-0.510390, 0.5, -0.315439),
+            (-0.510390, 0.5, 0.315439),
+        ];
+        let faces = [
+            [1, 12, 6],
+            [1, 6, 2],
+            [1, 2, 8],
+            [1, 8, 11],
+            [1, 11, 12],
+            [2, 6, 10],
+            [6, 12, 5],
+            [12, 11, 3],
+            [11, 8, 7],
+            [8, 2, 9],
+            [4, 10, 5],
+            [4, 5, 3],
+            [4, 3, 7],
+            [4, 7, 9],
+            [4, 9, 10],
+            [5, 10, 6],
+            [3, 5, 12],
+            [7, 3, 11],
+            [9, 7, 8],
+            [10, 9, 2],
+        ];
+
+        let material = material();
+        let vertex = |i: usize| {
+            let (x, y, z) = vertices[i - 1];
+            Point::new(x, y, z)
+        };
+        let tris = faces
+            .iter()
+            .map(|&[a, b, c]| {
+                Arc::new(Tri::new(
+                    vertex(a),
+                    vertex(b),
+                    vertex(c),
+                    Arc::clone(&material),
+                )) as Arc<dyn Primitive>
+            })
+            .collect();
+
+        let bvh = assert_matches_brute_force(tris);
+        assert!(bvh.nodes.len() < 2 * bvh.len());
+    }
+
+    #[test]
+    fn box_surface_area() {
+        let cube = BoundingBox::new(0.0..1.0, 0.0..2.0, 0.0..3.0);
+        assert_eq!(cube.surface_area(), 22.0);
+
+        // flat boxes still have area, so flat meshes don't look free to the SAH
+        let flat = BoundingBox::new(0.0..1.0, 5.0..5.0, 0.0..1.0);
+        assert_eq!(flat.surface_area(), 2.0);
+    }
+
+    #[test]
+    fn stats_of_two_spheres() {
+        let material = material();
+        let sphere = |x| {
+            Arc::new(Sphere::new(
+                Point::new(x, 0.0, 0.0),
+                1.0,
+                Arc::clone(&material),
+            )) as Arc<dyn Primitive>
+        };
+        let bvh = BvhTree::build(vec![sphere(-10.0), sphere(10.0)]);
+        let stats = bvh.stats();
+
+        assert_eq!((stats.inner_nodes, stats.leaves), (1, 2));
+        assert_eq!(stats.max_depth, 1);
+        assert_eq!((stats.min_leaf_size, stats.max_leaf_size), (1, 1));
+
+        // each sphere's box is 2x2x2 inside a 22x2x2 root
+        let expected = 0.25 + 2.0 * (24.0 / 184.0);
+        assert!((stats.sah_cost - expected).abs() < 1e-12);
+    }
+
+    #[test]
+    fn refit_follows_moved_primitives() {
+        let material = material();
+        let mut sampler = Sampler::new(11);
+        let corners: Vec<[Vec3<f64>; 3]> = (0..500)
+            .map(|_| {
+                let offset = Vec3::random(&mut sampler, -5.0, 5.0);
+                [(); 3].map(|_| offset + Vec3::random(&mut sampler, 0.0, 1.0))
+            })
+            .collect();
+
+        let tris = |moved: &dyn Fn(usize, Vec3<f64>) -> Vec3<f64>| -> Vec<Arc<dyn Primitive>> {
+            corners
+                .iter()
+                .enumerate()
+                .map(|(i, tri)| {
+                    let [a, b, c] = tri.map(|corner| Point::from(moved(i, corner)));
+                    Arc::new(Tri::new(a, b, c, Arc::clone(&material))) as Arc<dyn Primitive>
+                })
+                .collect()
+        };
+
+        let mut bvh = BvhTree::build(tris(&|_, corner| corner));
+        let node_count = bvh.nodes.len();
+
+        // moving everything together keeps the tree as good as new
+        let shifted = tris(&|_, corner| corner + Vec3::new(1.0, -0.5, 0.25));
+        bvh.refit(shifted.clone());
+        assert_eq!(bvh.nodes.len(), node_count);
+        assert!(!bvh.needs_rebuild());
+        assert_queries_match(&bvh, &shifted);
+
+        // swapping halves of the scene makes every subtree span the whole of it
+        let swapped = tris(&|i, corner| {
+            if i % 2 == 0 {
+                corner * -1.0
+            } else {
+                corner
+            }
+        });
+        bvh.refit(swapped.clone());
+        assert!(bvh.needs_rebuild());
+        assert_queries_match(&bvh, &swapped);
+
+        assert!(bvh.update(swapped.clone()));
+        assert!(!bvh.needs_rebuild());
+        assert_queries_match(&bvh, &swapped);
+    }
+
+    #[test]
+    fn corrupt_layouts_are_rejected() {
+        let config = BvhConfig {
+            max_prims_per_node: 2,
+            ..BvhConfig::default()
+        };
+        let check = |corrupt: &dyn Fn(&mut BvhTree)| {
+            let mut bvh = BvhTree::build_with(random_tris(3), config.clone());
+            corrupt(&mut bvh);
+            bvh.check_layout()
+        };
+        let set_second_child = |bvh: &mut BvhTree, child: usize| {
+            if let LinearNodeKind::Inner { second_child, .. } = &mut bvh.nodes[0].kind {
+                *second_child = child as u32;
+            }
+        };
+        let first_leaf = |bvh: &mut BvhTree| -> usize {
+            bvh.nodes
+                .iter()
+                .position(|node| matches!(node.kind, LinearNodeKind::Leaf { .. }))
+                .unwrap()
+        };
+
+        assert_eq!(check(&|_| {}), Ok(()));
+
+        assert!(check(&|bvh| set_second_child(bvh, 0)).is_err());
+        assert!(check(&|bvh| set_second_child(bvh, 1)).is_err());
+        assert!(check(&|bvh| set_second_child(bvh, 2)).is_err());
+        assert!(check(&|bvh| set_second_child(bvh, bvh.nodes.len())).is_err());
+        assert!(check(&|bvh| {
+            let leaf = first_leaf(bvh);
+            if let LinearNodeKind::Leaf { count, .. } = &mut bvh.nodes[leaf].kind {
+                *count += 1;
+            }
+        })
+        .is_err());
+        assert!(check(&|bvh| {
+            let leaf = first_leaf(bvh);
+            if let LinearNodeKind::Leaf { first, .. } = &mut bvh.nodes[leaf].kind {
+                *first = u32::MAX;
+            }
+        })
+        .is_err());
+        assert!(check(&|bvh| bvh.primitive_indices[0] = bvh.primitive_indices[1]).is_err());
+        assert!(check(&|bvh| bvh.primitive_indices[0] = bvh.primitives.len()).is_err());
+        assert!(check(&|bvh| {
+            bvh.primitives.pop();
+        })
+        .is_err());
+        assert!(check(&|bvh| {
+            bvh.nodes.pop();
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn layouts_deeper_than_the_stack_are_rejected() {
+        // each inner node has a leaf as its first child and the rest of the chain as its second
+        let chain = |inner_count: usize| {
+            let bbox = BoundingBox::new(0.0..1.0, 0.0..1.0, 0.0..1.0);
+            let mut nodes = Vec::new();
+            for i in 0..inner_count {
+                nodes.push(LinearNode {
+                    bbox: bbox.clone(),
+                    kind: LinearNodeKind::Inner {
+                        second_child: 2 * i as u32 + 2,
+                        axis: Axis::X,
+                    },
+                });
+                nodes.push(LinearNode {
+                    bbox: bbox.clone(),
+                    kind: LinearNodeKind::Leaf {
+                        first: i as u32,
+                        count: 1,
+                    },
+                });
+            }
+            nodes.push(LinearNode {
+                bbox,
+                kind: LinearNodeKind::Leaf {
+                    first: inner_count as u32,
+                    count: 1,
+                },
+            });
+
+            let mut primitives = random_tris(1);
+            primitives.truncate(inner_count + 1);
+
+            BvhTree {
+                nodes,
+                primitives,
+                primitive_indices: (0..=inner_count).collect(),
+                config: BvhConfig::default(),
+                build_time: Duration::ZERO,
+                built_sah_cost: 0.0,
+            }
+        };
+
+        // the deepest accepted layout still fits the traversal stack
+        let deepest = chain(MAX_DEPTH - 1);
+        assert_eq!(deepest.check_layout(), Ok(()));
+        let r = Ray::new(Point::new(0.5, 0.5, -1.0), Vec3::new(0.0, 0.0, 1.0));
+        deepest.bounce(r, &(0.0..f64::INFINITY));
+        deepest.occluded(r, &(0.0..f64::INFINITY));
+
+        assert!(chain(MAX_DEPTH).check_layout().is_err());
+    }
 }
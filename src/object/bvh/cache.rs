@@ -0,0 +1,619 @@
+//! On-disk cache of meshes loaded from OBJ files, along with the BVH built over them.
+//!
+//! Each OBJ file gets one cache file, named after its path. The cache file records the format
+//! version and a hash of the OBJ's contents and the `BvhConfig` the tree was built with, and ends
+//! with a checksum of everything before it. An entry that is missing, from another version, for
+//! different contents or parameters, or damaged, is rebuilt from the OBJ and overwritten.
+//!
+//! Materials are stored by name and looked up in the OBJ's material libraries on every load, so
+//! editing a library takes effect without rebuilding the entry.
+//!
+//! All numbers are stored little-endian.
+
+use std::{
+    ffi::OsStr,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    geometry::{Point, Vec3},
+    material::Material,
+    object::{
+        obj::{MaterialNames, Materials},
+        LoadError, Mesh, Object, Tri,
+    },
+};
+
+use super::{BvhConfig, BvhTree, Primitive};
+
+const MAGIC: &[u8; 8] = b"BNCMESH\0";
+/// Bumped whenever the layout of cache files changes, so older files are rebuilt
+const VERSION: u32 = 5;
+
+const HAS_NORMALS: u8 = 1;
+const HAS_UVS: u8 = 2;
+
+/// Material id of triangles without a `usemtl` material
+const NO_MATERIAL: u32 = u32::MAX;
+
+/// How a load from a `MeshCache` used it. Problems with the cache never fail a load, since the
+/// mesh can always be built from the OBJ instead, so they are returned for the caller to warn
+/// about or ignore.
+#[derive(Debug)]
+pub enum CacheStatus {
+    /// Read from the entry
+    Hit,
+    /// Built from the OBJ, and saved as a new entry
+    Miss,
+    /// Built from the OBJ because the entry couldn't be read, was damaged or was out of date,
+    /// and saved in its place
+    Stale(io::Error),
+    /// Built from the OBJ, but the entry couldn't be saved
+    Unsaved { entry: PathBuf, error: io::Error },
+}
+
+/// A directory of cached meshes, so that repeated renders of the same OBJ files skip parsing
+/// them and building their BVH.
+pub struct MeshCache {
+    dir: PathBuf,
+}
+
+impl MeshCache {
+    /// Caches meshes in `dir`, which is created when the first mesh is saved
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Loads the OBJ file at `path` as a `Mesh`, whose BVH is built with `config`. It is drawn
+    /// with `material` when given, and otherwise with the materials from its MTL libraries.
+    pub fn mesh(
+        &self,
+        path: impl AsRef<Path>,
+        material: Option<&Arc<dyn Material>>,
+        config: &BvhConfig,
+    ) -> Result<(Mesh, CacheStatus), LoadError> {
+        let (object, bvh, status) = self.load(path.as_ref(), material, config)?;
+        let mesh = Mesh::from_bvh(bvh, object.dropped_faces(), object.warnings().to_vec());
+
+        Ok((mesh, status))
+    }
+
+    /// Loads the OBJ file at `path` as loose triangles. These share their cache entry with
+    /// `mesh`, so a BVH is built for them the first time even though it goes unused.
+    pub fn object(
+        &self,
+        path: impl AsRef<Path>,
+        material: Option<&Arc<dyn Material>>,
+        config: &BvhConfig,
+    ) -> Result<(Object, CacheStatus), LoadError> {
+        let (object, _, status) = self.load(path.as_ref(), material, config)?;
+
+        Ok((object, status))
+    }
+
+    /// Where the entry for the OBJ file at `path` is stored
+    pub fn entry_path(&self, path: &Path) -> PathBuf {
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        let stem = path.file_stem().and_then(OsStr::to_str).unwrap_or("mesh");
+        let path_hash = fnv1a(FNV_OFFSET, path.to_string_lossy().as_bytes());
+
+        self.dir.join(format!("{}-{:016x}.mesh", stem, path_hash))
+    }
+
+    fn load(
+        &self,
+        path: &Path,
+        material: Option<&Arc<dyn Material>>,
+        config: &BvhConfig,
+    ) -> Result<(Object, BvhTree, CacheStatus), LoadError> {
+        let source = fs::read(path)?;
+        let key = cache_key(&source, config);
+        let entry_path = self.entry_path(path);
+        let base_dir = path.parent().unwrap_or(Path::new(""));
+
+        // anything wrong with the entry just means it has to be rebuilt, while a material
+        // library that can't be read fails the load as it would without the cache
+        let stale = match fs::read(&entry_path) {
+            Ok(entry) => match decode_header(&entry, key) {
+                Ok((input, names)) => {
+                    let materials = names.resolve(base_dir, material)?;
+
+                    match decode_body(input, names, &materials, config) {
+                        Ok((object, bvh)) => return Ok((object, bvh, CacheStatus::Hit)),
+                        Err(err) => Some(err),
+                    }
+                }
+                Err(err) => Some(err),
+            },
+            Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+            Err(err) => Some(err),
+        };
+
+        let object = Object::from_reader(&source[..], base_dir, material.map(Arc::clone))?;
+        let bvh = BvhTree::build_with(primitives(object.triangles()), config.clone());
+
+        let saved = encode(key, &object, &bvh).and_then(|bytes| self.save(&entry_path, &bytes));
+        let status = match (saved, stale) {
+            (Ok(()), None) => CacheStatus::Miss,
+            (Ok(()), Some(reason)) => CacheStatus::Stale(reason),
+            (Err(error), _) => CacheStatus::Unsaved {
+                entry: entry_path,
+                error,
+            },
+        };
+
+        Ok((object, bvh, status))
+    }
+
+    /// Writes the entry under a temporary name first, so a render that is interrupted or runs
+    /// alongside another never leaves a partial entry behind
+    fn save(&self, entry_path: &Path, bytes: &[u8]) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+
+        let partial = entry_path.with_extension(format!("partial-{}", std::process::id()));
+        fs::write(&partial, bytes)?;
+        fs::rename(&partial, entry_path)
+    }
+}
+
+/// Identifies the OBJ contents and the build parameters that affect the shape of the tree
+fn cache_key(source: &[u8], config: &BvhConfig) -> u64 {
+    let mut hash = fnv1a(FNV_OFFSET, source);
+    hash = fnv1a(hash, &config.traversal_cost.to_le_bytes());
+    hash = fnv1a(hash, &config.intersection_cost.to_le_bytes());
+    hash = fnv1a(hash, &(config.num_buckets as u64).to_le_bytes());
+    fnv1a(hash, &(config.max_prims_per_node as u64).to_le_bytes())
+}
+
+/// Fails for objects too large for the `u32` indices of the layout
+fn encode(key: u64, object: &Object, bvh: &BvhTree) -> io::Result<Vec<u8>> {
+    let mut out = Encoder::default();
+    out.bytes.extend_from_slice(MAGIC);
+    out.u32(VERSION);
+    out.u64(key);
+
+    let names = object.material_names();
+    for strings in [&names.libraries, &names.names] {
+        out.u64(strings.len() as u64);
+        for string in strings {
+            out.u64(string.len() as u64);
+            out.bytes.extend_from_slice(string.as_bytes());
+        }
+    }
+
+    out.u64(object.dropped_faces() as u64);
+    out.u64(object.triangles().len() as u64);
+    for (tri, id) in object.triangles().iter().zip(object.material_ids()) {
+        let id = match *id {
+            Some(id) => u32::try_from(id)
+                .ok()
+                .filter(|&id| id != NO_MATERIAL)
+                .ok_or_else(|| too_large("materials"))?,
+            None => NO_MATERIAL,
+        };
+        out.u32(id);
+        for vertex in tri.vertices() {
+            out.f64(vertex.x());
+            out.f64(vertex.y());
+            out.f64(vertex.z());
+        }
+
+        let normals = tri.vertex_normals();
+        let uvs = tri.uvs();
+        let flags = if normals.is_some() { HAS_NORMALS } else { 0 }
+            | if uvs.is_some() { HAS_UVS } else { 0 };
+        out.u8(flags);
+
+        for normal in normals.iter().flatten() {
+            out.f64(normal.x());
+            out.f64(normal.y());
+            out.f64(normal.z());
+        }
+        for [u, v] in uvs.iter().flatten() {
+            out.f64(*u);
+            out.f64(*v);
+        }
+    }
+
+    bvh.write_layout(&mut out)?;
+
+    let checksum = fnv1a(FNV_OFFSET, &out.bytes);
+    out.u64(checksum);
+
+    Ok(out.bytes)
+}
+
+fn stale(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, what.to_string())
+}
+
+/// For meshes with more of `what` than the layout can index
+pub(super) fn too_large(what: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("too many {} to cache", what),
+    )
+}
+
+/// Checks that the entry is intact and matches `key`, and reads the material names, which have
+/// to be resolved before the triangles can be read
+fn decode_header(bytes: &[u8], key: u64) -> io::Result<(Decoder<'_>, MaterialNames)> {
+    if bytes.len() < 8 {
+        return Err(stale("truncated"));
+    }
+    let (body, checksum) = bytes.split_at(bytes.len() - 8);
+    if fnv1a(FNV_OFFSET, body) != Decoder::new(checksum).u64()? {
+        return Err(stale("checksum mismatch"));
+    }
+
+    let mut input = Decoder::new(body);
+    if input.take(MAGIC.len())? != MAGIC {
+        return Err(stale("not a mesh cache"));
+    }
+    if input.u32()? != VERSION {
+        return Err(stale("written by another version"));
+    }
+    if input.u64()? != key {
+        return Err(stale("built from other contents or parameters"));
+    }
+
+    let mut names = MaterialNames::default();
+    for strings in [&mut names.libraries, &mut names.names] {
+        for _ in 0..input.len()? {
+            let len = input.len()?;
+            let string = std::str::from_utf8(input.take(len)?).map_err(|_| stale("not UTF-8"))?;
+            strings.push(string.to_string());
+        }
+    }
+
+    Ok((input, names))
+}
+
+fn decode_body(
+    mut input: Decoder<'_>,
+    names: MaterialNames,
+    materials: &Materials,
+    config: &BvhConfig,
+) -> io::Result<(Object, BvhTree)> {
+    let dropped_faces = input.u64()? as usize;
+    let count = input.len()?;
+    let mut triangles = Vec::with_capacity(count);
+    let mut material_ids = Vec::with_capacity(count);
+    for _ in 0..count {
+        let material_id = match input.u32()? {
+            NO_MATERIAL => None,
+            id if (id as usize) < names.names.len() => Some(id as usize),
+            _ => return Err(stale("material out of range")),
+        };
+        material_ids.push(material_id);
+
+        let mut vertices = [Point::new(0.0, 0.0, 0.0); 3];
+        for vertex in &mut vertices {
+            *vertex = Point::new(input.f64()?, input.f64()?, input.f64()?);
+        }
+        let [a, b, c] = vertices;
+        let mut tri = Tri::new(a, b, c, Arc::clone(materials.get(material_id)));
+
+        let flags = input.u8()?;
+        if flags & HAS_NORMALS != 0 {
+            let mut normals = [Vec3::new(0.0, 0.0, 0.0); 3];
+            for normal in &mut normals {
+                *normal = Vec3::new(input.f64()?, input.f64()?, input.f64()?);
+            }
+            tri = tri.with_vertex_normals(normals);
+        }
+        if flags & HAS_UVS != 0 {
+            let mut uvs = [[0.0; 2]; 3];
+            for uv in &mut uvs {
+                *uv = [input.f64()?, input.f64()?];
+            }
+            tri = tri.with_uvs(uvs);
+        }
+
+        triangles.push(tri);
+    }
+
+    let bvh = BvhTree::read_layout(&mut input, primitives(&triangles), config.clone())?;
+
+    Ok((
+        Object::from_triangles(
+            triangles,
+            dropped_faces,
+            names,
+            material_ids,
+            materials.warnings().to_vec(),
+        ),
+        bvh,
+    ))
+}
+
+fn primitives(triangles: &[Tri]) -> Vec<Arc<dyn Primitive>> {
+    triangles
+        .iter()
+        .map(|tri| {
+            let tri: Arc<dyn Primitive> = Arc::new(tri.clone());
+            tri
+        })
+        .collect()
+}
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+
+/// 64-bit FNV-1a hash of `bytes`, continuing from `hash`
+fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for &byte in bytes {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+
+    hash
+}
+
+#[derive(Default)]
+pub(super) struct Encoder {
+    bytes: Vec<u8>,
+}
+
+impl Encoder {
+    pub(super) fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub(super) fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(super) fn u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(super) fn f64(&mut self, value: f64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+pub(super) struct Decoder<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    fn take(&mut self, count: usize) -> io::Result<&'a [u8]> {
+        if count > self.bytes.len() {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+
+        Ok(taken)
+    }
+
+    pub(super) fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(super) fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub(super) fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub(super) fn f64(&mut self) -> io::Result<f64> {
+        Ok(f64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    /// A count of items that follow, which can't be more than there are bytes left
+    pub(super) fn len(&mut self) -> io::Result<usize> {
+        let len = self.u64()?;
+        if len > self.bytes.len() as u64 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
+        Ok(len as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        color::Color, geometry::Ray, material::Lambertian, object::Visible, sampler::Sampler,
+        test_util::ScratchDir,
+    };
+
+    use super::*;
+
+    fn icosahedron() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/icosahedron.obj")
+    }
+
+    fn decode(
+        bytes: &[u8],
+        key: u64,
+        material: &Arc<dyn Material>,
+        config: &BvhConfig,
+    ) -> io::Result<(Object, BvhTree)> {
+        let (input, names) = decode_header(bytes, key)?;
+        let materials = names.resolve(Path::new(""), Some(material)).unwrap();
+
+        decode_body(input, names, &materials, config)
+    }
+
+    #[test]
+    fn cached_mesh_matches_fresh_build() {
+        let dir = ScratchDir::new("mesh-cache");
+        let cache = MeshCache::new(dir.to_path_buf());
+        let material: Arc<dyn Material> = Arc::new(Lambertian::new(Color::white()));
+        let config = BvhConfig {
+            max_prims_per_node: 2,
+            ..BvhConfig::default()
+        };
+
+        let (fresh, status) = cache.mesh(icosahedron(), Some(&material), &config).unwrap();
+        assert!(matches!(status, CacheStatus::Miss));
+        let entry = cache.entry_path(&icosahedron());
+        let written = fs::read(&entry).unwrap();
+        let (cached, status) = cache.mesh(icosahedron(), Some(&material), &config).unwrap();
+        assert!(matches!(status, CacheStatus::Hit));
+
+        // loading didn't need to rewrite the entry
+        assert_eq!(fs::read(&entry).unwrap(), written);
+        assert_eq!(fresh.bvh().len(), cached.bvh().len());
+        assert_eq!(fresh.bvh().stats().leaves, cached.bvh().stats().leaves);
+
+        let mut sampler = Sampler::new(7);
+        for _ in 0..100 {
+            let r = Ray::new(
+                Point::from(Vec3::random_unit(&mut sampler) * 3.0),
+                Vec3::random_unit(&mut sampler),
+            );
+            let t_range = 0.0..f64::INFINITY;
+
+            let (a, b) = (
+                fresh.bvh().bounce(r, &t_range),
+                cached.bvh().bounce(r, &t_range),
+            );
+            assert_eq!(a.map(|hit| hit.t), b.map(|hit| hit.t));
+        }
+    }
+
+    #[test]
+    fn keeps_normals_and_uvs() {
+        let dir = ScratchDir::new("attribute-cache");
+        let cache = MeshCache::new(dir.to_path_buf());
+        let material: Arc<dyn Material> = Arc::new(Lambertian::new(Color::white()));
+        let config = BvhConfig::default();
+
+        let obj = cache.dir().join("tri.obj");
+        fs::write(
+            &obj,
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvt 0 1\nvn 0 0 1\n\
+             f 1/1/1 2/2/1 3/3/1\nf 1 2 3\n",
+        )
+        .unwrap();
+
+        let (fresh, _) = cache.object(&obj, Some(&material), &config).unwrap();
+        let (cached, _) = cache.object(&obj, Some(&material), &config).unwrap();
+
+        for (a, b) in fresh.triangles().iter().zip(cached.triangles()) {
+            assert_eq!(a.vertex_normals(), b.vertex_normals());
+            assert_eq!(a.uvs(), b.uvs());
+        }
+        assert!(cached.triangles()[0].uvs().is_some());
+        assert!(cached.triangles()[1].vertex_normals().is_none());
+    }
+
+    #[test]
+    fn materials_are_looked_up_on_load() {
+        let dir = ScratchDir::new("material-cache");
+        let cache = MeshCache::new(dir.to_path_buf());
+        let config = BvhConfig::default();
+
+        let obj = cache.dir().join("quad.obj");
+        let mtl = cache.dir().join("quad.mtl");
+        fs::write(
+            &obj,
+            "mtllib quad.mtl\nv 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n\
+             f 1 2 3\nusemtl lamp\nf 1 3 4\n",
+        )
+        .unwrap();
+        fs::write(&mtl, "newmtl lamp\nKd 1 1 1\n").unwrap();
+
+        let (fresh, _) = cache.object(&obj, None, &config).unwrap();
+        let written = fs::read(cache.entry_path(&obj)).unwrap();
+        assert!(!fresh.triangles()[1].material().is_emissive());
+
+        // the library changed but the OBJ didn't, so the entry is reused with the new material
+        fs::write(&mtl, "newmtl lamp\nKe 5 5 5\n").unwrap();
+        let (cached, status) = cache.object(&obj, None, &config).unwrap();
+        assert!(matches!(status, CacheStatus::Hit));
+        assert_eq!(fs::read(cache.entry_path(&obj)).unwrap(), written);
+        assert_eq!(cached.material_ids(), &[None, Some(0)]);
+        assert!(!cached.triangles()[0].material().is_emissive());
+        assert!(cached.triangles()[1].material().is_emissive());
+
+        fs::remove_file(&mtl).unwrap();
+        assert!(matches!(
+            cache.object(&obj, None, &config),
+            Err(LoadError::Library { .. })
+        ));
+    }
+
+    #[test]
+    fn stale_entries_are_rebuilt() {
+        let dir = ScratchDir::new("stale-cache");
+        let cache = MeshCache::new(dir.to_path_buf());
+        let material: Arc<dyn Material> = Arc::new(Lambertian::new(Color::white()));
+        let config = BvhConfig::default();
+        let entry = cache.entry_path(&icosahedron());
+
+        cache
+            .object(icosahedron(), Some(&material), &config)
+            .unwrap();
+        let written = fs::read(&entry).unwrap();
+        let source = fs::read(icosahedron()).unwrap();
+        let key = cache_key(&source, &config);
+        assert!(decode(&written, key, &material, &config).is_ok());
+
+        // another build configuration
+        let other = BvhConfig {
+            num_buckets: 4,
+            ..BvhConfig::default()
+        };
+        assert!(decode(&written, cache_key(&source, &other), &material, &other).is_err());
+
+        // a damaged entry
+        let mut damaged = written.clone();
+        damaged[MAGIC.len() + 20] ^= 1;
+        assert!(decode(&damaged, key, &material, &config).is_err());
+        assert!(decode(&written[..10], key, &material, &config).is_err());
+
+        // an older version, with a valid checksum
+        let mut old = written[..written.len() - 8].to_vec();
+        old[MAGIC.len()..MAGIC.len() + 4].copy_from_slice(&(VERSION - 1).to_le_bytes());
+        let checksum = fnv1a(FNV_OFFSET, &old);
+        old.extend_from_slice(&checksum.to_le_bytes());
+        assert!(decode(&old, key, &material, &config).is_err());
+
+        // is replaced by a good one on the next load
+        fs::write(&entry, &damaged).unwrap();
+        let (object, status) = cache
+            .object(icosahedron(), Some(&material), &config)
+            .unwrap();
+        assert!(matches!(status, CacheStatus::Stale(_)));
+        assert_eq!(object.triangles().len(), 20);
+        assert_eq!(fs::read(&entry).unwrap(), written);
+    }
+
+    #[test]
+    fn unsaved_entries_are_returned() {
+        let dir = ScratchDir::new("unsaved-cache");
+        let material: Arc<dyn Material> = Arc::new(Lambertian::new(Color::white()));
+
+        // the cache directory can't be created where a file is in the way
+        fs::write(dir.join("cache"), "").unwrap();
+        let cache = MeshCache::new(dir.join("cache"));
+        let (object, status) = cache
+            .object(icosahedron(), Some(&material), &BvhConfig::default())
+            .unwrap();
+
+        assert_eq!(object.triangles().len(), 20);
+        match status {
+            CacheStatus::Unsaved { entry, .. } => {
+                assert_eq!(entry, cache.entry_path(&icosahedron()))
+            }
+            status => panic!("expected a failed save, got {:?}", status),
+        }
+    }
+}
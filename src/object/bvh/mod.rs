@@ -1,5 +1,10 @@
 mod bbox;
+#[allow(clippy::module_inception)]
 mod bvh;
+mod cache;
+mod stats;
 
 pub use bbox::*;
 pub use bvh::*;
+pub use cache::{CacheStatus, MeshCache};
+pub use stats::*;
@@ -0,0 +1,79 @@
+use std::fmt::Display;
+
+/// Summary of the shape of a `BvhTree`, for comparing builds
+#[derive(Debug, Clone, PartialEq)]
+pub struct BvhStats {
+    pub primitives: usize,
+    pub inner_nodes: usize,
+    pub leaves: usize,
+    /// Depth of the deepest leaf, where the root is at depth 0
+    pub max_depth: usize,
+    /// Average depth of the leaves
+    pub mean_depth: f64,
+    pub min_leaf_size: usize,
+    pub max_leaf_size: usize,
+    pub mean_leaf_size: f64,
+    /// Number of leaves holding each number of primitives, indexed by that number
+    pub leaf_size_histogram: Vec<usize>,
+    /// Expected cost of finding the closest hit for a ray through the root under the surface
+    /// area heuristic, in the units of the `BvhConfig` costs the tree was built with
+    pub sah_cost: f64,
+    /// Memory used by the tree itself, not counting the primitives it refers to
+    pub memory_bytes: usize,
+}
+
+impl BvhStats {
+    pub fn nodes(&self) -> usize {
+        self.inner_nodes + self.leaves
+    }
+}
+
+impl Display for BvhStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "primitives:  {}", self.primitives)?;
+        writeln!(
+            f,
+            "nodes:       {} ({} inner, {} leaves)",
+            self.nodes(),
+            self.inner_nodes,
+            self.leaves
+        )?;
+        writeln!(
+            f,
+            "depth:       {} max, {:.2} on average",
+            self.max_depth, self.mean_depth
+        )?;
+        writeln!(
+            f,
+            "leaf size:   {} to {}, {:.2} on average",
+            self.min_leaf_size, self.max_leaf_size, self.mean_leaf_size
+        )?;
+
+        let widest = self.leaf_size_histogram.iter().copied().max().unwrap_or(0);
+        for (size, &leaves) in self.leaf_size_histogram.iter().enumerate() {
+            if leaves == 0 {
+                continue;
+            }
+
+            // bars are at most 40 characters wide
+            let bar = "#".repeat((leaves * 40).div_ceil(widest));
+            writeln!(f, "  {:>4} prims: {:>8} {}", size, leaves, bar)?;
+        }
+
+        writeln!(f, "SAH cost:    {:.3}", self.sah_cost)?;
+        write!(
+            f,
+            "memory:      {:.2} MiB",
+            self.memory_bytes as f64 / (1024.0 * 1024.0)
+        )
+    }
+}
+
+/// Work done by a single ray traversing a `BvhTree`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TraversalCounts {
+    /// Number of nodes whose bounding box was tested against the ray
+    pub box_tests: u32,
+    /// Number of primitives tested against the ray
+    pub primitive_tests: u32,
+}
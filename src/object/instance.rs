@@ -0,0 +1,326 @@
+use std::{borrow::Cow, ops::Range, sync::Arc};
+
+use crate::{
+    geometry::{AnimatedTransform, Point, Ray, Transform, Vec3},
+    material::Material,
+    sampler::Sampler,
+};
+
+use super::{
+    bvh::{Bounded, BoundingBox, BvhConfig, BvhTree, Primitive},
+    transformed::{area_scale, hit_to_world},
+    Object, Visible, VisibleHit,
+};
+
+/// Geometry built into its own BVH once, which can then be placed in a scene any number of times
+/// through `Instance`s that share it.
+pub struct Mesh {
+    bvh: BvhTree,
+    /// Running total of the primitives' surface areas, used to sample points on the mesh
+    cumulative_area: Vec<f64>,
+    /// See `Object::dropped_faces`
+    dropped_faces: usize,
+    /// See `Object::warnings`
+    warnings: Vec<String>,
+}
+
+impl Mesh {
+    pub fn new(primitives: Vec<Arc<dyn Primitive>>, config: BvhConfig) -> Self {
+        Self::from_bvh(BvhTree::build_with(primitives, config), 0, Vec::new())
+    }
+
+    /// The triangles of a loaded OBJ file
+    pub fn from_object(object: Object, config: BvhConfig) -> Self {
+        let dropped_faces = object.dropped_faces();
+        let warnings = object.warnings().to_vec();
+
+        Self::from_bvh(
+            BvhTree::build_with(object.to_primitives(), config),
+            dropped_faces,
+            warnings,
+        )
+    }
+
+    /// Wraps an already built BVH, e.g. one loaded from a `MeshCache`
+    pub(super) fn from_bvh(bvh: BvhTree, dropped_faces: usize, warnings: Vec<String>) -> Self {
+        let cumulative_area = bvh
+            .primitives()
+            .iter()
+            .scan(0.0, |total, prim| {
+                *total += prim.surface_area();
+                Some(*total)
+            })
+            .collect();
+
+        Self {
+            bvh,
+            cumulative_area,
+            dropped_faces,
+            warnings,
+        }
+    }
+
+    pub fn bvh(&self) -> &BvhTree {
+        &self.bvh
+    }
+
+    /// See `Object::dropped_faces`
+    pub fn dropped_faces(&self) -> usize {
+        self.dropped_faces
+    }
+
+    /// See `Object::warnings`
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        self.cumulative_area.last().copied().unwrap_or(0.0)
+    }
+
+    /// Picks a point uniformly over the whole mesh, in the mesh's own coordinates
+    fn sample_surface(&self, time: f64, sampler: &mut Sampler) -> (Point<f64>, Vec3<f64>) {
+        let target = sampler.next_f64() * self.surface_area();
+        let idx = self
+            .cumulative_area
+            .partition_point(|&area| area <= target)
+            .min(self.cumulative_area.len() - 1);
+
+        self.bvh.primitives()[idx].sample_surface(time, sampler)
+    }
+}
+
+/// A `Mesh` placed in the scene by a transform, optionally drawn with a different material.
+///
+/// Rays are moved into the mesh's coordinates rather than the mesh into the scene's, so any
+/// number of instances cost one copy of the geometry and its BVH.
+pub struct Instance {
+    mesh: Arc<Mesh>,
+    motion: AnimatedTransform,
+    /// Where the instance is at the start of its motion
+    to_world: Transform,
+    to_object: Transform,
+    material: Option<Arc<dyn Material>>,
+    bbox: BoundingBox,
+}
+
+impl Instance {
+    /// Places `mesh` by `transform`, which maps the mesh's coordinates to the scene's. When
+    /// `material` is given, it replaces the materials of the mesh's primitives.
+    pub fn new(mesh: Arc<Mesh>, transform: Transform, material: Option<Arc<dyn Material>>) -> Self {
+        Self::moving(mesh, AnimatedTransform::fixed(transform), material)
+    }
+
+    /// Places `mesh` by a transform that changes over time, so that rays see the instance
+    /// wherever it is at their time
+    pub fn moving(
+        mesh: Arc<Mesh>,
+        motion: AnimatedTransform,
+        material: Option<Arc<dyn Material>>,
+    ) -> Self {
+        let to_world = motion.start().clone();
+        let bbox = if motion.is_moving() {
+            mesh.bvh.bbox().animate(&motion)
+        } else {
+            mesh.bvh.bbox().transform(&to_world)
+        };
+
+        Self {
+            mesh,
+            to_object: to_world.inverse(),
+            to_world,
+            motion,
+            material,
+            bbox,
+        }
+    }
+
+    pub fn mesh(&self) -> &Arc<Mesh> {
+        &self.mesh
+    }
+
+    pub fn material(&self) -> Option<&Arc<dyn Material>> {
+        self.material.as_ref()
+    }
+
+    pub fn motion(&self) -> &AnimatedTransform {
+        &self.motion
+    }
+
+    /// The transforms to and from the mesh's coordinates at `time`
+    fn transforms(&self, time: f64) -> (Cow<'_, Transform>, Cow<'_, Transform>) {
+        if self.motion.is_moving() {
+            let to_world = self.motion.at(time);
+            (Cow::Owned(to_world.inverse()), Cow::Owned(to_world))
+        } else {
+            (
+                Cow::Borrowed(&self.to_object),
+                Cow::Borrowed(&self.to_world),
+            )
+        }
+    }
+}
+
+impl Visible for Instance {
+    fn bounce(&self, r: Ray, t_range: &Range<f64>) -> Option<VisibleHit> {
+        let (to_object, to_world) = self.transforms(r.time());
+        let mut hit = self.mesh.bvh.bounce(to_object.ray(r), t_range)?;
+        if let Some(material) = &self.material {
+            hit.material = Arc::clone(material);
+        }
+
+        Some(hit_to_world(r, hit, &to_world))
+    }
+
+    fn occluded(&self, r: Ray, t_range: &Range<f64>) -> bool {
+        let (to_object, _) = self.transforms(r.time());
+
+        self.mesh.bvh.occluded(to_object.ray(r), t_range)
+    }
+}
+
+impl Bounded for Instance {
+    fn bbox(&self) -> BoundingBox {
+        self.bbox.clone()
+    }
+
+    /// See `area_scale` for when this is exact. Moving instances are measured at the start of
+    /// their motion, which is their area throughout when they `samples_uniformly`.
+    fn surface_area(&self) -> f64 {
+        self.mesh.surface_area() * area_scale(&self.to_world)
+    }
+
+    fn centroid(&self) -> Point<f64> {
+        self.bbox.centroid()
+    }
+}
+
+impl Primitive for Instance {
+    /// Uniform under the same transforms for which `surface_area` is exact
+    fn sample_surface(&self, time: f64, sampler: &mut Sampler) -> (Point<f64>, Vec3<f64>) {
+        let (point, normal) = self.mesh.sample_surface(time, sampler);
+        let (_, to_world) = self.transforms(time);
+
+        (to_world.point(point), to_world.normal(normal).unit())
+    }
+
+    /// Also requires a moving instance to keep its area along the way, as lights are picked by
+    /// an area that doesn't depend on time. Its samples then follow it to where it is at `time`.
+    fn samples_uniformly(&self) -> bool {
+        let area_scale = self.to_world.area_scale();
+        let keeps_area = match (area_scale, self.motion.end().area_scale()) {
+            (Some(start), Some(end)) => (start - end).abs() <= 1e-9 * start,
+            _ => false,
+        };
+
+        area_scale.is_some()
+            && (!self.motion.is_moving() || keeps_area)
+            && self
+                .mesh
+                .bvh
+                .primitives()
+                .iter()
+                .all(|prim| prim.samples_uniformly())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{color::Color, material::Lambertian, object::Sphere};
+
+    use super::*;
+
+    #[test]
+    fn instances_match_moved_geometry() {
+        let material: Arc<dyn Material> = Arc::new(Lambertian::new(Color::white()));
+        let sphere: Arc<dyn Primitive> = Arc::new(Sphere::new(
+            Point::new(0.0, 0.0, 0.0),
+            1.0,
+            Arc::clone(&material),
+        ));
+        let mesh = Arc::new(Mesh::new(vec![sphere], BvhConfig::default()));
+
+        let transform = Transform::scale(Vec3::new(2.0, 2.0, 2.0))
+            .then(&Transform::translate(Vec3::new(0.0, 0.0, -5.0)));
+        let instance = Instance::new(mesh, transform, None);
+        let moved = Sphere::new(Point::new(0.0, 0.0, -5.0), 2.0, material);
+
+        let mut sampler = Sampler::new(3);
+        for _ in 0..100 {
+            let origin = Point::from(Vec3::random(&mut sampler, -1.0, 1.0));
+            let r = Ray::new(
+                origin,
+                Vec3::new(0.0, 0.0, -1.0) + Vec3::random(&mut sampler, -0.3, 0.3),
+            );
+            let t_range = 0.0..f64::INFINITY;
+
+            let (a, b) = (instance.bounce(r, &t_range), moved.bounce(r, &t_range));
+            assert_eq!(a.is_some(), b.is_some());
+            assert_eq!(instance.occluded(r, &t_range), b.is_some());
+
+            if let (Some(a), Some(b)) = (a, b) {
+                assert!((a.t - b.t).abs() < 1e-9);
+                assert!((a.normal - b.normal).len() < 1e-9);
+                assert_eq!(a.front_face, b.front_face);
+            }
+        }
+
+        assert!((instance.surface_area() - moved.surface_area()).abs() < 1e-9);
+        assert!(instance.samples_uniformly());
+    }
+
+    #[test]
+    fn moving_instance_follows_time() {
+        let material: Arc<dyn Material> = Arc::new(Lambertian::new(Color::white()));
+        let sphere: Arc<dyn Primitive> =
+            Arc::new(Sphere::new(Point::new(0.0, 0.0, 0.0), 1.0, material));
+        let mesh = Arc::new(Mesh::new(vec![sphere], BvhConfig::default()));
+
+        let motion = AnimatedTransform::new(
+            Transform::translate(Vec3::new(0.0, 0.0, -5.0)),
+            Transform::translate(Vec3::new(4.0, 0.0, -5.0)),
+            0.0..1.0,
+        );
+        let instance = Instance::moving(mesh, motion, None);
+        let t_range = 0.0..f64::INFINITY;
+        let down = Vec3::new(0.0, 0.0, -1.0);
+
+        for (time, x, hits) in [(0.0, 0.0, true), (0.0, 4.0, false), (1.0, 4.0, true)] {
+            let r = Ray::with_time(Point::new(x, 0.0, 0.0), down, time);
+            assert_eq!(instance.bounce(r, &t_range).is_some(), hits);
+            assert_eq!(instance.occluded(r, &t_range), hits);
+        }
+
+        let r = Ray::with_time(Point::new(2.0, 0.0, 0.0), down, 0.5);
+        let hit = instance.bounce(r, &t_range).unwrap();
+        assert!((hit.t - 4.0).abs() < 1e-9);
+
+        // the bounds cover the sphere wherever it is along the way
+        let bbox = instance.bbox();
+        assert!(bbox.x().start <= -1.0 && bbox.x().end >= 5.0);
+
+        // samples are taken where the sphere is at their time
+        let mut sampler = Sampler::new(5);
+        assert!(instance.samples_uniformly());
+        for time in [0.0, 0.5, 1.0] {
+            let (point, _) = instance.sample_surface(time, &mut sampler);
+            let center = Point::new(4.0 * time, 0.0, -5.0);
+            assert!((Vec3::from(point - center).len() - 1.0).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn growing_instance_cant_be_sampled() {
+        let material: Arc<dyn Material> = Arc::new(Lambertian::new(Color::white()));
+        let sphere: Arc<dyn Primitive> =
+            Arc::new(Sphere::new(Point::new(0.0, 0.0, 0.0), 1.0, material));
+        let mesh = Arc::new(Mesh::new(vec![sphere], BvhConfig::default()));
+
+        let motion = AnimatedTransform::new(
+            Transform::identity(),
+            Transform::scale(Vec3::new(2.0, 2.0, 2.0)),
+            0.0..1.0,
+        );
+        assert!(!Instance::moving(mesh, motion, None).samples_uniformly());
+    }
+}
@@ -1,11 +1,17 @@
+mod instance;
+mod mtl;
 mod obj;
 mod planar;
 mod sphere;
+mod transformed;
+mod triangulate;
 mod visible;
 
 pub mod bvh;
 
+pub use instance::*;
 pub use obj::*;
 pub use planar::*;
 pub use sphere::*;
+pub use transformed::*;
 pub use visible::*;
@@ -0,0 +1,284 @@
+use std::{collections::HashMap, io::BufRead, sync::Arc};
+
+use crate::{
+    color::Color,
+    material::{Dielectric, DiffuseLight, Lambertian, Material, Metal},
+};
+
+use super::{obj::invalid, LoadError};
+
+type Result<T> = std::result::Result<T, LoadError>;
+
+/// A material from an MTL library, holding the statements that have something to map onto
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct MtlMaterial {
+    /// `Kd`
+    diffuse: Color,
+    /// `Ks`
+    specular: Color,
+    /// `Ns`, the specular exponent
+    shininess: f64,
+    /// `Ni`, the index of refraction
+    ior: f64,
+    /// `d`, or one minus `Tr`
+    dissolve: f64,
+    /// `Ke`
+    emission: Color,
+    /// `illum`, the illumination model
+    illum: Option<u32>,
+    /// File name given by `map_Kd`
+    pub(super) diffuse_map: Option<String>,
+}
+
+impl Default for MtlMaterial {
+    /// The material the MTL format assumes for anything a file leaves out
+    fn default() -> Self {
+        Self {
+            diffuse: Color::new(0.8, 0.8, 0.8),
+            specular: Color::black(),
+            shininess: 0.0,
+            ior: 1.0,
+            dissolve: 1.0,
+            emission: Color::black(),
+            illum: None,
+            diffuse_map: None,
+        }
+    }
+}
+
+impl MtlMaterial {
+    /// The closest of the renderer's materials. Emissive materials become lights, transparent
+    /// ones or those with a refracting illumination model (4, 6, 7, 9) glass, those with a
+    /// reflecting model (3, 5, 8) or only a specular color metal, and anything else diffuse.
+    pub(super) fn to_material(&self) -> Arc<dyn Material> {
+        let is_black = |c: Color| c.r() <= 0.0 && c.g() <= 0.0 && c.b() <= 0.0;
+
+        if !is_black(self.emission) {
+            Arc::new(DiffuseLight::new(self.emission))
+        } else if self.dissolve < 1.0 || matches!(self.illum, Some(4 | 6 | 7 | 9)) {
+            // Ni is often left at 1 by exporters, which would make the glass invisible
+            let ior = if self.ior > 1.0 { self.ior } else { 1.5 };
+            Arc::new(Dielectric::new(ior))
+        } else if matches!(self.illum, Some(3 | 5 | 8))
+            || (is_black(self.diffuse) && !is_black(self.specular))
+        {
+            let albedo = if is_black(self.specular) {
+                self.diffuse
+            } else {
+                self.specular
+            };
+            // a common mapping from the Phong exponent to roughness
+            let fuzz = (2.0 / (self.shininess.max(0.0) + 2.0)).sqrt();
+            Arc::new(Metal::new(albedo, fuzz))
+        } else {
+            Arc::new(Lambertian::new(self.diffuse))
+        }
+    }
+}
+
+/// Reads the materials of an MTL library by name. Everything after a `#` is a comment.
+/// Statements that have nothing to map onto, such as ambient colors and most texture maps, are
+/// skipped.
+pub(super) fn parse_mtl(reader: impl BufRead) -> Result<HashMap<String, MtlMaterial>> {
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, MtlMaterial)> = None;
+
+    for (idx, line) in reader.lines().enumerate() {
+        let line_no = idx + 1;
+        let line = line?;
+
+        let content = match line.find('#') {
+            Some(comment) => &line[..comment],
+            None => &line,
+        };
+        let mut tokens = content.split_whitespace();
+
+        let directive = match tokens.next() {
+            Some(directive) => directive,
+            None => continue,
+        };
+
+        if directive == "newmtl" {
+            // names can contain spaces
+            let name = content.trim_start()["newmtl".len()..].trim();
+            if name.is_empty() {
+                return Err(invalid(line_no, "missing material name"));
+            }
+
+            materials.extend(current.take());
+            current = Some((name.to_string(), MtlMaterial::default()));
+            continue;
+        }
+
+        const KNOWN: &[&str] = &["Kd", "Ks", "Ns", "Ni", "d", "Tr", "Ke", "illum", "map_Kd"];
+        if !KNOWN.contains(&directive) {
+            continue;
+        }
+
+        let material = match &mut current {
+            Some((_, material)) => material,
+            None => {
+                return Err(invalid(
+                    line_no,
+                    format!("`{}` before any `newmtl`", directive),
+                ))
+            }
+        };
+
+        match directive {
+            "Kd" => material.diffuse = color(&mut tokens, line_no)?,
+            "Ks" => material.specular = color(&mut tokens, line_no)?,
+            "Ke" => material.emission = color(&mut tokens, line_no)?,
+            "Ns" => material.shininess = number(tokens.next(), line_no)?,
+            "Ni" => material.ior = number(tokens.next(), line_no)?,
+            "d" => {
+                // the halo variant fades with the angle, which is as good as plain dissolve here
+                let value = match tokens.next() {
+                    Some("-halo") => tokens.next(),
+                    value => value,
+                };
+                material.dissolve = number(value, line_no)?;
+            }
+            "Tr" => material.dissolve = 1.0 - number(tokens.next(), line_no)?,
+            "illum" => {
+                let token = tokens.next().unwrap_or("");
+                let illum = token.parse().map_err(|_| {
+                    invalid(line_no, format!("invalid illumination model `{}`", token))
+                })?;
+                material.illum = Some(illum);
+            }
+            "map_Kd" => {
+                // options such as `-s 1 1 1` come before the file name
+                let file = tokens
+                    .last()
+                    .ok_or_else(|| invalid(line_no, "missing texture file"))?;
+                material.diffuse_map = Some(file.to_string());
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    materials.extend(current);
+
+    Ok(materials)
+}
+
+fn number(token: Option<&str>, line_no: usize) -> Result<f64> {
+    let token = token.ok_or_else(|| invalid(line_no, "missing value"))?;
+
+    token
+        .parse()
+        .map_err(|_| invalid(line_no, format!("invalid number `{}`", token)))
+}
+
+/// Either `r g b` or a single value for all three
+fn color<'a>(tokens: &mut impl Iterator<Item = &'a str>, line_no: usize) -> Result<Color> {
+    let r = number(tokens.next(), line_no)?;
+
+    match tokens.next() {
+        None => Ok(Color::new(r, r, r)),
+        g => Ok(Color::new(
+            r,
+            number(g, line_no)?,
+            number(tokens.next(), line_no)?,
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        geometry::{Point, Ray, Vec3},
+        object::VisibleHit,
+    };
+
+    use super::*;
+
+    #[test]
+    fn library_statements() {
+        let source = "
+            # two materials
+            newmtl red paint
+            Ka 0.1 0.1 0.1
+            Kd 0.8 0.1 0.1
+            Ks 0.5
+            Ns 250
+            illum 2
+            map_Kd -s 2 2 1 paint.png
+
+            newmtl glass
+            Ni 1.45
+            d -halo 0.2
+            illum 4
+        ";
+        let materials = parse_mtl(source.as_bytes()).unwrap();
+
+        assert_eq!(materials.len(), 2);
+        let red = &materials["red paint"];
+        assert_eq!(red.diffuse, Color::new(0.8, 0.1, 0.1));
+        assert_eq!(red.specular, Color::new(0.5, 0.5, 0.5));
+        assert_eq!(red.shininess, 250.0);
+        assert_eq!(red.illum, Some(2));
+        assert_eq!(red.diffuse_map.as_deref(), Some("paint.png"));
+
+        let glass = &materials["glass"];
+        assert_eq!(glass.ior, 1.45);
+        assert_eq!(glass.dissolve, 0.2);
+        assert_eq!(glass.diffuse, MtlMaterial::default().diffuse);
+    }
+
+    #[test]
+    fn maps_onto_materials() {
+        let material = |source: &str| {
+            let source = format!("newmtl m\n{}", source);
+            parse_mtl(source.as_bytes()).unwrap()["m"].to_material()
+        };
+        let r = Ray::new(Point::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let hit = |material: &Arc<dyn Material>| {
+            VisibleHit::new(
+                r,
+                Point::new(0.0, 0.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+                1.0,
+                Arc::clone(material),
+            )
+        };
+        let up = Vec3::new(0.0, 1.0, 0.0);
+
+        assert!(material("Ke 4 4 4").is_emissive());
+        assert!(!material("Ke 0 0 0").is_emissive());
+
+        let diffuse = material("Kd 1 0 0\nKs 0.5\nillum 2");
+        assert_eq!(diffuse.albedo(&hit(&diffuse)), Color::new(1.0, 0.0, 0.0));
+        assert!(diffuse.pdf(r, &hit(&diffuse), up).is_some());
+
+        // glass can only be sampled
+        for source in ["Tr 0.5", "Ni 1.3\nillum 7"] {
+            let glass = material(source);
+            assert!(glass.pdf(r, &hit(&glass), up).is_none());
+        }
+
+        // metals take on their specular color
+        let metal = material("Kd 1 0 0\nKs 0.2 0.3 0.4\nNs 500\nillum 3");
+        assert_eq!(metal.albedo(&hit(&metal)), Color::new(0.2, 0.3, 0.4));
+        let specular_only = material("Kd 0 0 0\nKs 0.9");
+        assert_eq!(
+            specular_only.albedo(&hit(&specular_only)),
+            Color::new(0.9, 0.9, 0.9)
+        );
+    }
+
+    #[test]
+    fn invalid_statements() {
+        let error = |source: &str| parse_mtl(source.as_bytes()).unwrap_err().to_string();
+
+        assert_eq!(error("Kd 1 1 1"), "line 1: `Kd` before any `newmtl`");
+        assert_eq!(error("newmtl m\nKd 1 x 1"), "line 2: invalid number `x`");
+        assert_eq!(error("newmtl m\nKd 1 1"), "line 2: missing value");
+        assert_eq!(error("newmtl"), "line 1: missing material name");
+        assert_eq!(
+            error("newmtl m\nillum two"),
+            "line 2: invalid illumination model `two`"
+        );
+    }
+}
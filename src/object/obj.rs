@@ -1,37 +1,202 @@
 use std::{
+    collections::HashMap,
+    error::Error,
+    fmt::Display,
     fs::File,
-    io::{BufRead, BufReader, Result},
-    iter::Peekable,
-    ops::Range,
-    path::PathBuf,
+    io::{self, BufRead, BufReader},
+    path::{Path, PathBuf},
     sync::Arc,
 };
 
 use crate::{
-    geometry::{Point, Ray},
+    geometry::{Point, Transform, Vec3},
     material::Material,
     object::Tri,
 };
 
-use super::bvh::Primitive;
+use super::{
+    bvh::Primitive,
+    mtl::{parse_mtl, MtlMaterial},
+    triangulate,
+};
+
+/// Why an OBJ file couldn't be loaded
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    /// A malformed statement, e.g. a vertex with a coordinate that isn't a number
+    Parse {
+        line: usize,
+        message: String,
+    },
+    /// A face refers to a vertex, texture coordinate or normal that isn't defined before it
+    IndexOutOfRange {
+        line: usize,
+        index: i64,
+        count: usize,
+    },
+    /// A statement this loader doesn't handle, such as free-form curves and surfaces
+    Unsupported {
+        line: usize,
+        directive: String,
+    },
+    /// A material library named by `mtllib` couldn't be read
+    Library {
+        path: PathBuf,
+        error: Box<LoadError>,
+    },
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadError::Io(err) => write!(f, "{}", err),
+            LoadError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            LoadError::IndexOutOfRange { line, index, count } => write!(
+                f,
+                "line {}: index {} out of range for {} items",
+                line, index, count
+            ),
+            LoadError::Unsupported { line, directive } => {
+                write!(f, "line {}: unsupported statement `{}`", line, directive)
+            }
+            LoadError::Library { path, error } => write!(f, "{}: {}", path.display(), error),
+        }
+    }
+}
+
+impl Error for LoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LoadError::Io(err) => Some(err),
+            LoadError::Library { error, .. } => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(err: io::Error) -> Self {
+        LoadError::Io(err)
+    }
+}
+
+type Result<T> = std::result::Result<T, LoadError>;
+
+/// Largest angle in degrees between faces whose normals are averaged in files that leave
+/// smoothing entirely to the reader, giving neither normals nor smoothing groups
+const CREASE_ANGLE: f64 = 30.0;
+
+/// Statements that don't describe polygons, and can be skipped without losing any of them
+const IGNORED: &[&str] = &[
+    "o",
+    "g",
+    "mg",
+    "usemap",
+    "maplib",
+    "l",
+    "p",
+    "vp",
+    "lod",
+    "bevel",
+    "c_interp",
+    "d_interp",
+    "shadow_obj",
+    "trace_obj",
+];
 
 pub struct Object {
     triangles: Vec<Tri>,
+    /// Faces of the file that were left out for having no area
+    dropped_faces: usize,
+    material_names: MaterialNames,
+    /// Index into the names of `material_names` for each triangle, `None` for triangles of faces
+    /// before any `usemtl`
+    material_ids: Vec<Option<usize>>,
+    /// See `Object::warnings`
+    warnings: Vec<String>,
 }
 
 impl Object {
-    pub fn new(path: impl Into<PathBuf>, material: Arc<dyn Material>) -> Result<Self> {
+    /// Loads the OBJ file at `path`. Its triangles are drawn with `material` when given, and
+    /// otherwise with the materials the file picks with `usemtl` from its `mtllib` libraries.
+    pub fn new(path: impl Into<PathBuf>, material: Option<Arc<dyn Material>>) -> Result<Self> {
         let path = path.into();
 
-        let file = File::open(path)?;
-        let file = BufReader::new(file);
+        let file = File::open(&path)?;
+        let base_dir = path.parent().unwrap_or(Path::new(""));
 
-        let tokens = tokenize(file);
-        let (vertices, faces) = parse_obj(tokens);
+        Self::from_reader(BufReader::new(file), base_dir, material)
+    }
 
-        let triangles = triangulate(vertices, faces, material);
+    /// Parses the text of an OBJ file, whose material libraries are looked for in `base_dir`
+    pub fn from_reader(
+        reader: impl BufRead,
+        base_dir: &Path,
+        material: Option<Arc<dyn Material>>,
+    ) -> Result<Self> {
+        let mut data = parse_obj(reader)?;
+        smooth_normals(&mut data);
+        let materials = data.material_names.resolve(base_dir, material.as_ref())?;
 
-        Ok(Self { triangles })
+        let (triangles, material_ids, dropped_faces) = triangulate(&data, &materials);
+
+        Ok(Self {
+            triangles,
+            dropped_faces,
+            material_names: data.material_names,
+            material_ids,
+            warnings: materials.warnings,
+        })
+    }
+
+    pub(super) fn from_triangles(
+        triangles: Vec<Tri>,
+        dropped_faces: usize,
+        material_names: MaterialNames,
+        material_ids: Vec<Option<usize>>,
+        warnings: Vec<String>,
+    ) -> Self {
+        Self {
+            triangles,
+            dropped_faces,
+            material_names,
+            material_ids,
+            warnings,
+        }
+    }
+
+    pub fn triangles(&self) -> &[Tri] {
+        &self.triangles
+    }
+
+    pub(super) fn material_names(&self) -> &MaterialNames {
+        &self.material_names
+    }
+
+    pub(super) fn material_ids(&self) -> &[Option<usize>] {
+        &self.material_ids
+    }
+
+    /// Number of faces that were left out for having no area, e.g. because their vertices are
+    /// all on a line. Faces with more than three vertices are split into triangles, and are only
+    /// dropped when none of those triangles have any area.
+    pub fn dropped_faces(&self) -> usize {
+        self.dropped_faces
+    }
+
+    /// Parts of the file's materials that are drawn differently than it asks for, such as
+    /// materials missing from its libraries (drawn plain grey) or unsupported texture maps.
+    /// These are left for the caller to report.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    /// Moves every triangle of the object by `t`
+    pub fn transform(&mut self, t: &Transform) {
+        for tri in &mut self.triangles {
+            *tri = tri.transform(t);
+        }
     }
 
     pub fn to_primitives(self) -> Vec<Arc<dyn Primitive>> {
@@ -45,103 +210,712 @@ impl Object {
     }
 }
 
-fn triangulate(
-    vertices: Vec<Point<f64>>,
-    faces: Vec<Vec<usize>>,
-    material: Arc<dyn Material>,
-) -> Vec<Tri> {
-    assert!(
-        faces.iter().all(|x| x.len() == 3),
-        "TODO: Handle non-triangle object faces"
-    );
+/// The material libraries an OBJ file names with `mtllib`, and the names of the materials it
+/// picks from them with `usemtl`, in order of first use
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(super) struct MaterialNames {
+    pub(super) libraries: Vec<String>,
+    pub(super) names: Vec<String>,
+}
+
+impl MaterialNames {
+    /// Looks up the materials in the libraries, which are found relative to `base_dir`. Every
+    /// name resolves to `material` instead when one is given, without reading the libraries.
+    pub(super) fn resolve(
+        &self,
+        base_dir: &Path,
+        material: Option<&Arc<dyn Material>>,
+    ) -> Result<Materials> {
+        if let Some(material) = material {
+            return Ok(Materials {
+                named: vec![Arc::clone(material); self.names.len()],
+                default: Arc::clone(material),
+                warnings: Vec::new(),
+            });
+        }
+
+        let mut library = HashMap::new();
+        for file in &self.libraries {
+            let path = base_dir.join(file);
+            let parsed = File::open(&path)
+                .map_err(LoadError::from)
+                .and_then(|file| parse_mtl(BufReader::new(file)))
+                .map_err(|error| LoadError::Library {
+                    path,
+                    error: Box::new(error),
+                })?;
 
+            library.extend(parsed);
+        }
+
+        let default = MtlMaterial::default().to_material();
+        let mut warnings = Vec::new();
+        let named = self
+            .names
+            .iter()
+            .map(|name| match library.get(name) {
+                Some(material) => {
+                    if let Some(texture) = &material.diffuse_map {
+                        warnings.push(format!(
+                            "textures aren't supported, material `{}` is drawn without `{}`",
+                            name, texture
+                        ));
+                    }
+                    material.to_material()
+                }
+                None => {
+                    warnings.push(format!(
+                        "material `{}` isn't defined by any material library",
+                        name
+                    ));
+                    Arc::clone(&default)
+                }
+            })
+            .collect();
+
+        Ok(Materials {
+            named,
+            default,
+            warnings,
+        })
+    }
+}
+
+/// The materials for the names of a `MaterialNames`, by index
+pub(super) struct Materials {
+    named: Vec<Arc<dyn Material>>,
+    /// For faces without a material, the MTL format's plain grey
+    default: Arc<dyn Material>,
+    /// See `Object::warnings`
+    warnings: Vec<String>,
+}
+
+impl Materials {
+    pub(super) fn get(&self, id: Option<usize>) -> &Arc<dyn Material> {
+        id.map_or(&self.default, |id| &self.named[id])
+    }
+
+    pub(super) fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+}
+
+/// The statements of an OBJ file, with face indices resolved to start at 0
+#[derive(Default)]
+struct ObjData {
+    positions: Vec<Point<f64>>,
+    uvs: Vec<[f64; 2]>,
+    normals: Vec<Vec3<f64>>,
+    faces: Vec<Vec<FaceVertex>>,
+    material_names: MaterialNames,
+    /// The `usemtl` material in effect for each face, by index into `material_names.names`
+    face_materials: Vec<Option<usize>>,
+    /// The smoothing group of each face, `None` for faces that aren't smoothed
+    face_smoothing: Vec<Option<u32>>,
+    /// Whether the file has any `s` statements
+    has_smoothing_groups: bool,
+}
+
+/// One corner of a face, e.g. `3/1/2`: a position with an optional texture coordinate and normal
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct FaceVertex {
+    position: usize,
+    uv: Option<usize>,
+    normal: Option<usize>,
+}
+
+/// Splits the faces into triangles drawn with their faces' materials, returning them along with
+/// the material id of each and the number of faces that were dropped for having no area
+fn triangulate(data: &ObjData, materials: &Materials) -> (Vec<Tri>, Vec<Option<usize>>, usize) {
     let mut triangles = Vec::new();
+    let mut material_ids = Vec::new();
+    let mut dropped = 0;
+
+    for (face, &material_id) in data.faces.iter().zip(&data.face_materials) {
+        let polygon: Vec<_> = face.iter().map(|v| data.positions[v.position]).collect();
+        let split = triangulate::triangulate(&polygon);
+
+        if split.is_empty() {
+            dropped += 1;
+        }
+        for corners in split {
+            let material = materials.get(material_id);
+            triangles.push(triangle(data, corners.map(|idx| face[idx]), material));
+            material_ids.push(material_id);
+        }
+    }
 
-    for face in faces {
-        let face: Vec<_> = face.iter().map(|x| x - 1).collect();
+    (triangles, material_ids, dropped)
+}
 
-        let a = vertices[face[0]];
-        let b = vertices[face[1]];
-        let c = vertices[face[2]];
+fn triangle(data: &ObjData, [a, b, c]: [FaceVertex; 3], material: &Arc<dyn Material>) -> Tri {
+    let mut tri = Tri::new(
+        data.positions[a.position],
+        data.positions[b.position],
+        data.positions[c.position],
+        Arc::clone(material),
+    );
 
-        let tri = Tri::new(a, b, c, Arc::clone(&material));
-        triangles.push(tri);
+    // only when every corner has one, as there is nothing sensible to fill in for the rest
+    if let (Some(a), Some(b), Some(c)) = (a.normal, b.normal, c.normal) {
+        tri = tri.with_vertex_normals([data.normals[a], data.normals[b], data.normals[c]]);
+    }
+    if let (Some(a), Some(b), Some(c)) = (a.uv, b.uv, c.uv) {
+        tri = tri.with_uvs([data.uvs[a], data.uvs[b], data.uvs[c]]);
     }
 
-    triangles
+    tri
 }
 
-fn tokenize(file: BufReader<File>) -> impl Iterator<Item = String> {
-    file.lines()
-        .map(|line| {
-            line.unwrap()
-                .split_whitespace()
-                .map(|x| String::from(x))
-                .collect::<Vec<_>>()
+/// Gives the corners of smoothed faces that have no normal one averaged from the faces around
+/// their vertex, weighted by the angle of each face at the vertex. Faces in the same smoothing
+/// group are averaged together. Files without smoothing groups or normals are smoothed as one
+/// group, but only across edges where the faces meet at less than `CREASE_ANGLE`, so that
+/// models meant to be faceted stay that way.
+fn smooth_normals(data: &mut ObjData) {
+    let (groups, min_cos) = if data.has_smoothing_groups {
+        (data.face_smoothing.clone(), None)
+    } else if data.normals.is_empty() {
+        (
+            vec![Some(0); data.faces.len()],
+            Some(CREASE_ANGLE.to_radians().cos()),
+        )
+    } else {
+        return;
+    };
+
+    let face_normals: Vec<_> = data
+        .faces
+        .iter()
+        .map(|face| {
+            let polygon: Vec<_> = face.iter().map(|v| data.positions[v.position]).collect();
+            let normal = triangulate::newell_normal(&polygon);
+            (normal.len() > 0.0).then(|| normal.unit())
         })
-        .flatten()
-        .skip_while(|x| is_skip_token(x))
+        .collect();
+
+    // the smoothed faces touching each position, with their angle there
+    let mut around = vec![Vec::new(); data.positions.len()];
+    for (idx, face) in data.faces.iter().enumerate() {
+        if groups[idx].is_none() || face_normals[idx].is_none() {
+            continue;
+        }
+
+        for (corner, vertex) in face.iter().enumerate() {
+            let angle = corner_angle(&data.positions, face, corner);
+            around[vertex.position].push((idx, angle));
+        }
+    }
+
+    for idx in 0..data.faces.len() {
+        let normal = match (groups[idx], face_normals[idx]) {
+            (Some(_), Some(normal)) => normal,
+            _ => continue,
+        };
+
+        let mut smoothed = false;
+        let corner_normals: Vec<_> = data.faces[idx]
+            .iter()
+            .map(|vertex| {
+                let mut sum = Vec3::new(0.0, 0.0, 0.0);
+                for &(other, angle) in &around[vertex.position] {
+                    let other_normal = face_normals[other].unwrap();
+                    if groups[other] == groups[idx]
+                        && min_cos.is_none_or(|min_cos| normal.dot(other_normal) >= min_cos)
+                    {
+                        sum += other_normal * angle;
+                        smoothed |= other != idx;
+                    }
+                }
+
+                // faces can only cancel out when they fold back onto each other
+                if sum.len() > 0.0 {
+                    sum.unit()
+                } else {
+                    normal
+                }
+            })
+            .collect();
+
+        // faces with nothing to smooth with stay flat
+        if !smoothed {
+            continue;
+        }
+
+        for (corner, smooth) in corner_normals.into_iter().enumerate() {
+            if data.faces[idx][corner].normal.is_none() {
+                data.normals.push(smooth);
+                data.faces[idx][corner].normal = Some(data.normals.len() - 1);
+            }
+        }
+    }
 }
 
-fn is_skip_token(token: &String) -> bool {
-    token == "\\" || token.trim().is_empty()
+/// Angle in radians of the polygon at its `corner`th vertex
+fn corner_angle(positions: &[Point<f64>], face: &[FaceVertex], corner: usize) -> f64 {
+    let n = face.len();
+    let at = positions[face[corner].position];
+    let prev = Vec3::from(positions[face[(corner + n - 1) % n].position] - at);
+    let next = Vec3::from(positions[face[(corner + 1) % n].position] - at);
+
+    if prev.len() == 0.0 || next.len() == 0.0 {
+        return 0.0;
+    }
+
+    prev.unit().dot(next.unit()).clamp(-1.0, 1.0).acos()
 }
 
-fn parse_obj(tokens: impl Iterator<Item = String>) -> (Vec<Point<f64>>, Vec<Vec<usize>>) {
-    let mut tokens = tokens.peekable();
+pub(super) fn invalid(line_no: usize, message: impl Display) -> LoadError {
+    LoadError::Parse {
+        line: line_no,
+        message: message.to_string(),
+    }
+}
+
+/// Reads the statements of an OBJ file line by line. Everything after a `#` is a comment, and a
+/// line ending in `\` continues on the next one. Statements that don't describe polygons, their
+/// materials or their smoothing (objects, groups, lines, ...) are skipped, while any others are
+/// unsupported.
+fn parse_obj(reader: impl BufRead) -> Result<ObjData> {
+    let mut data = ObjData::default();
+    let mut material = None;
+    let mut smoothing = None;
 
-    let mut points = Vec::new();
-    let mut faces = Vec::new();
+    let mut lines = reader.lines().enumerate();
+    while let Some((idx, line)) = lines.next() {
+        let line_no = idx + 1;
+        let mut line = line?;
 
-    while let Some(token) = tokens.peek() {
-        let token = token.as_str();
+        while line.trim_end().ends_with('\\') {
+            let end = line.trim_end().len() - 1;
+            line.truncate(end);
+            line.push(' ');
 
-        match token {
-            "v" => points.push(vertex(&mut tokens)),
-            "f" => faces.push(face(&mut tokens)),
-            _ => panic!("Unexpected token {}", token),
+            match lines.next() {
+                Some((_, next)) => line.push_str(&next?),
+                None => break,
+            }
+        }
+
+        let content = match line.find('#') {
+            Some(comment) => &line[..comment],
+            None => &line,
+        };
+        let mut tokens = content.split_whitespace();
+
+        match tokens.next() {
+            Some("v") => {
+                let [x, y, z] = numbers(&mut tokens, line_no, "vertex")?;
+                data.positions.push(Point::new(x, y, z));
+            }
+            Some("vt") => {
+                // v defaults to 0 for 1D textures, and the optional w is unused
+                let u = number(tokens.next(), line_no, "texture coordinate")?;
+                let v = match tokens.next() {
+                    Some(v) => number(Some(v), line_no, "texture coordinate")?,
+                    None => 0.0,
+                };
+                data.uvs.push([u, v]);
+            }
+            Some("vn") => {
+                let [x, y, z] = numbers(&mut tokens, line_no, "normal")?;
+                data.normals.push(Vec3::new(x, y, z));
+            }
+            Some("f") => {
+                let face = tokens
+                    .map(|token| face_vertex(token, &data, line_no))
+                    .collect::<Result<Vec<_>>>()?;
+
+                if face.len() < 3 {
+                    return Err(invalid(line_no, "face needs at least 3 vertices"));
+                }
+                data.faces.push(face);
+                data.face_materials.push(material);
+                data.face_smoothing.push(smoothing);
+            }
+            Some("s") => {
+                let token = tokens.next().unwrap_or("");
+                smoothing = match token {
+                    "off" | "0" => None,
+                    group => Some(group.parse().map_err(|_| {
+                        invalid(line_no, format!("invalid smoothing group `{}`", group))
+                    })?),
+                };
+                data.has_smoothing_groups = true;
+            }
+            Some("mtllib") => {
+                let start = data.material_names.libraries.len();
+                data.material_names
+                    .libraries
+                    .extend(tokens.map(str::to_string));
+
+                if data.material_names.libraries.len() == start {
+                    return Err(invalid(line_no, "missing material library"));
+                }
+            }
+            Some("usemtl") => {
+                // names can contain spaces
+                let name = content.trim_start()["usemtl".len()..].trim();
+                if name.is_empty() {
+                    return Err(invalid(line_no, "missing material name"));
+                }
+
+                let names = &mut data.material_names.names;
+                material = Some(match names.iter().position(|known| known == name) {
+                    Some(id) => id,
+                    None => {
+                        names.push(name.to_string());
+                        names.len() - 1
+                    }
+                });
+            }
+            Some(directive) if IGNORED.contains(&directive) => {}
+            Some(directive) => {
+                return Err(LoadError::Unsupported {
+                    line: line_no,
+                    directive: directive.to_string(),
+                })
+            }
+            None => {}
         }
     }
 
-    (points, faces)
+    Ok(data)
 }
 
-fn vertex(tokens: &mut Peekable<impl Iterator<Item = String>>) -> Point<f64> {
-    let _vertex = tokens.next().expect("Missing 'v' for vertex");
+fn number(token: Option<&str>, line_no: usize, what: &str) -> Result<f64> {
+    let token = token.ok_or_else(|| invalid(line_no, format!("missing {} coordinate", what)))?;
 
-    let x: f64 = tokens
-        .next()
-        .expect("Expected x coordinate for vertex")
-        .parse()
-        .expect("Unable to parse float");
-    let y: f64 = tokens
-        .next()
-        .expect("Expected y coordinate for vertex")
+    token
         .parse()
-        .expect("Unable to parse float");
-    let z: f64 = tokens
-        .next()
-        .expect("Expected z coordinate for vertex")
+        .map_err(|_| invalid(line_no, format!("invalid number `{}`", token)))
+}
+
+fn numbers<'a>(
+    tokens: &mut impl Iterator<Item = &'a str>,
+    line_no: usize,
+    what: &str,
+) -> Result<[f64; 3]> {
+    Ok([
+        number(tokens.next(), line_no, what)?,
+        number(tokens.next(), line_no, what)?,
+        number(tokens.next(), line_no, what)?,
+    ])
+}
+
+/// Parses one of `v`, `v/vt`, `v//vn` or `v/vt/vn`
+fn face_vertex(token: &str, data: &ObjData, line_no: usize) -> Result<FaceVertex> {
+    let mut parts = token.split('/');
+
+    let position = parts.next().unwrap_or("");
+    let uv = parts.next().filter(|part| !part.is_empty());
+    let normal = parts.next().filter(|part| !part.is_empty());
+    if parts.next().is_some() {
+        return Err(invalid(line_no, format!("invalid face vertex `{}`", token)));
+    }
+
+    Ok(FaceVertex {
+        position: index(position, data.positions.len(), line_no)?,
+        uv: uv
+            .map(|uv| index(uv, data.uvs.len(), line_no))
+            .transpose()?,
+        normal: normal
+            .map(|normal| index(normal, data.normals.len(), line_no))
+            .transpose()?,
+    })
+}
+
+/// Resolves an index into a list of `count` items read so far. Indices count from 1, and
+/// negative ones count back from the end, with -1 the most recent item.
+fn index(token: &str, count: usize, line_no: usize) -> Result<usize> {
+    let idx: i64 = token
         .parse()
-        .expect("Unable to parse float");
+        .map_err(|_| invalid(line_no, format!("invalid index `{}`", token)))?;
 
-    Point::new(x, y, z)
+    let resolved = if idx > 0 { idx - 1 } else { count as i64 + idx };
+
+    if idx == 0 || resolved < 0 || resolved >= count as i64 {
+        return Err(LoadError::IndexOutOfRange {
+            line: line_no,
+            index: idx,
+            count,
+        });
+    }
+
+    Ok(resolved as usize)
 }
 
-fn face(tokens: &mut Peekable<impl Iterator<Item = String>>) -> Vec<usize> {
-    let mut vertices = Vec::new();
+#[cfg(test)]
+mod tests {
+    use crate::{color::Color, material::Lambertian, test_util::ScratchDir};
 
-    let _face = tokens.next().expect("Missing 'f' for face");
+    use super::*;
 
-    while let Some(token) = tokens.peek() {
-        match token.parse::<usize>() {
-            Ok(idx) => {
-                tokens.next();
-                vertices.push(idx);
-            }
-            _ => break,
+    fn parse(source: &str) -> Result<ObjData> {
+        parse_obj(source.as_bytes())
+    }
+
+    fn error_line(source: &str) -> String {
+        match parse(source) {
+            Err(err) => err.to_string(),
+            Ok(_) => panic!("expected a parse error"),
         }
     }
 
-    vertices
+    #[test]
+    fn full_face_syntax() {
+        let source = "
+            # exported by hand
+            mtllib scene.mtl
+            o quad
+            v 0 0 0
+            v 1 0 0 1.0  # with a weight
+            v 1 1 0
+            v 0 1 0
+            vt 0 0
+            vt 1 0
+            vt 1 1
+            vn 0 0 1
+            g front
+            usemtl red
+            s off
+            f 1/1/1 2/2/1 3/3/1
+            f -4//-1 -2//-1 \\
+              -1//-1
+            f 1/1 3/3 4
+            l 1 2
+        ";
+        let data = parse(source).unwrap();
+
+        assert_eq!(data.positions.len(), 4);
+        assert_eq!(data.uvs.len(), 3);
+        assert_eq!(data.normals.len(), 1);
+        assert_eq!(data.faces.len(), 3);
+
+        let corner = |position, uv, normal| FaceVertex {
+            position,
+            uv,
+            normal,
+        };
+        assert_eq!(data.faces[0][2], corner(2, Some(2), Some(0)));
+        assert_eq!(
+            data.faces[1],
+            vec![
+                corner(0, None, Some(0)),
+                corner(2, None, Some(0)),
+                corner(3, None, Some(0)),
+            ]
+        );
+        assert_eq!(data.faces[2][2], corner(3, None, None));
+
+        assert_eq!(data.material_names.libraries, vec!["scene.mtl"]);
+        assert_eq!(data.material_names.names, vec!["red"]);
+        assert_eq!(data.face_materials, vec![Some(0); 3]);
+
+        let material: Arc<dyn Material> = Arc::new(Lambertian::new(Color::white()));
+        let materials = data
+            .material_names
+            .resolve(Path::new(""), Some(&material))
+            .unwrap();
+        let (triangles, _, _) = triangulate(&data, &materials);
+        assert!(triangles[0].vertex_normals().is_some() && triangles[0].uvs().is_some());
+        assert!(triangles[1].vertex_normals().is_some() && triangles[1].uvs().is_none());
+        assert!(triangles[2].vertex_normals().is_none() && triangles[2].uvs().is_none());
+    }
+
+    #[test]
+    fn polygons_and_degenerate_faces() {
+        let source = "
+            v 0 0 0
+            v 1 0 0
+            v 1 1 0
+            v 0 1 0
+            v 2 0 0
+            f 1 2 3 4
+            f 1 2 5
+            f 1 2 2 1
+            f 4 3 2 1 4
+        ";
+        let material: Arc<dyn Material> = Arc::new(Lambertian::new(Color::white()));
+        let object = Object::from_reader(source.as_bytes(), Path::new(""), Some(material)).unwrap();
+
+        assert_eq!(object.triangles().len(), 4);
+        assert_eq!(object.dropped_faces(), 2);
+    }
+
+    #[test]
+    fn computed_normals() {
+        let material: Arc<dyn Material> = Arc::new(Lambertian::new(Color::white()));
+        let load = |source: &str| {
+            Object::from_reader(
+                source.as_bytes(),
+                Path::new(""),
+                Some(Arc::clone(&material)),
+            )
+            .unwrap()
+        };
+        let close = |a: Vec3<f64>, b: Vec3<f64>| (a - b).len() < 1e-9;
+
+        // two faces meeting at a shallow angle are smoothed where they meet
+        let tent = load("v 0 0 0\nv 1 0 0\nv 0.5 1 0.2\nv 0.5 -1 0.2\nf 1 2 3\nf 2 1 4\n");
+        let [a, b, c] = tent.triangles()[0].vertex_normals().unwrap();
+        assert!(close(a, Vec3::new(0.0, 0.0, 1.0)));
+        assert!(close(b, Vec3::new(0.0, 0.0, 1.0)));
+        assert!(close(c, Vec3::new(0.0, -0.2, 1.0).unit()));
+
+        // the inside of a cube's corner stays faceted without smoothing groups
+        let corner = "v 0 0 0\nv 1 0 0\nv 0 1 0\nv 0 0 1\nv 1 1 0\nv 0 1 1\nv 1 0 1\n";
+        let faces = "f 1 2 5 3\nf 1 3 6 4\nf 1 4 7 2\n";
+        let faceted = load(&format!("{}{}", corner, faces));
+        assert!(faceted
+            .triangles()
+            .iter()
+            .all(|tri| tri.vertex_normals().is_none()));
+
+        // but is smoothed within a group, weighing each face by its angle at the vertex
+        let grouped = load(&format!("{}s 1\n{}s off\nf 1 2 5 3\n", corner, faces));
+        let [o, x, _] = grouped.triangles()[0].vertex_normals().unwrap();
+        assert!(close(o, Vec3::new(1.0, 1.0, 1.0).unit()));
+        assert!(close(x, Vec3::new(0.0, 1.0, 1.0).unit()));
+        assert!(grouped.triangles()[..6]
+            .iter()
+            .all(|tri| tri.vertex_normals().is_some()));
+        assert!(grouped.triangles()[6..]
+            .iter()
+            .all(|tri| tri.vertex_normals().is_none()));
+    }
+
+    #[test]
+    fn per_face_materials() {
+        let dir = ScratchDir::new("mtl");
+        std::fs::write(
+            dir.join("lamps.mtl"),
+            "newmtl red\nKd 1 0 0\nmap_Kd red.png\nnewmtl lamp\nKe 4 4 4\n",
+        )
+        .unwrap();
+
+        let source = "
+            mtllib lamps.mtl
+            v 0 0 0
+            v 1 0 0
+            v 1 1 0
+            v 0 1 0
+            f 1 2 3
+            usemtl lamp
+            f 1 2 3 4
+            usemtl red
+            f 1 2 3
+            usemtl lamp
+            f 1 3 4
+            usemtl missing
+            f 1 2 4
+        ";
+        let object = Object::from_reader(source.as_bytes(), &dir, None).unwrap();
+
+        assert_eq!(
+            object.material_ids(),
+            &[None, Some(0), Some(0), Some(1), Some(0), Some(2)]
+        );
+        let emissive: Vec<_> = object
+            .triangles()
+            .iter()
+            .map(|tri| tri.material().is_emissive())
+            .collect();
+        assert_eq!(emissive, [false, true, true, false, true, false]);
+        assert_eq!(
+            object.warnings(),
+            [
+                "textures aren't supported, material `red` is drawn without `red.png`",
+                "material `missing` isn't defined by any material library",
+            ]
+        );
+
+        // the same material is shared by every triangle using it
+        let triangles = object.triangles();
+        assert!(Arc::ptr_eq(
+            triangles[1].material(),
+            triangles[4].material()
+        ));
+
+        // an override replaces every material, and the libraries aren't read at all
+        let white: Arc<dyn Material> = Arc::new(Lambertian::new(Color::white()));
+        let object = Object::from_reader(
+            source.replace("lamps.mtl", "absent.mtl").as_bytes(),
+            &dir,
+            Some(Arc::clone(&white)),
+        )
+        .unwrap();
+        assert!(object
+            .triangles()
+            .iter()
+            .all(|tri| Arc::ptr_eq(tri.material(), &white)));
+        assert!(object.warnings().is_empty());
+
+        let err = Object::from_reader("mtllib absent.mtl".as_bytes(), &dir, None)
+            .err()
+            .unwrap();
+        assert!(
+            matches!(&err, LoadError::Library { error, .. } if matches!(**error, LoadError::Io(_)))
+        );
+
+        std::fs::write(dir.join("broken.mtl"), "newmtl m\nKd red").unwrap();
+        let err = Object::from_reader("mtllib broken.mtl".as_bytes(), &dir, None)
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "{}: line 2: invalid number `red`",
+                dir.join("broken.mtl").display()
+            )
+        );
+    }
+
+    #[test]
+    fn invalid_statements() {
+        assert_eq!(error_line("v 0 0 0\nv 1 x 0"), "line 2: invalid number `x`");
+        assert_eq!(error_line("v 0 0"), "line 1: missing vertex coordinate");
+        assert_eq!(
+            error_line("v 0 0 0\nf 1 2 3"),
+            "line 2: index 2 out of range for 1 items"
+        );
+        assert_eq!(
+            error_line("v 0 0 0\nv 1 0 0\nv 1 1 0\nf 0 1 2"),
+            "line 4: index 0 out of range for 3 items"
+        );
+        assert_eq!(
+            error_line("v 0 0 0\nv 1 0 0\nv 1 1 0\nf 1 2 -4"),
+            "line 4: index -4 out of range for 3 items"
+        );
+        assert_eq!(
+            error_line("v 0 0 0\nv 1 0 0\nf 1 2"),
+            "line 3: face needs at least 3 vertices"
+        );
+        assert_eq!(error_line("mtllib"), "line 1: missing material library");
+        assert_eq!(error_line("s one"), "line 1: invalid smoothing group `one`");
+        assert_eq!(
+            error_line("usemtl  # none"),
+            "line 1: missing material name"
+        );
+
+        assert!(matches!(
+            parse("v 0 0 0\nf 1 1 9"),
+            Err(LoadError::IndexOutOfRange {
+                line: 2,
+                index: 9,
+                count: 1
+            })
+        ));
+        assert!(matches!(
+            parse("cstype bspline\ncurv 0 1 1 2"),
+            Err(LoadError::Unsupported { line: 1, directive }) if directive == "cstype"
+        ));
+        assert!(matches!(
+            Object::new(
+                "does/not/exist.obj",
+                Some(Arc::new(Lambertian::new(Color::white())))
+            ),
+            Err(LoadError::Io(_))
+        ));
+    }
 }
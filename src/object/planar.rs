@@ -1,12 +1,13 @@
 use std::{ops::Range, sync::Arc};
 
 use crate::{
-    geometry::{Point, Ray, Vec3},
+    geometry::{Point, Ray, Transform, Vec3},
     material::Material,
+    sampler::Sampler,
 };
 
 use super::{
-    bvh::{Bounded, BoundingBox, Primitive},
+    bvh::{Bounded, BoundingBox, Intersect, Primitive},
     Visible, VisibleHit,
 };
 
@@ -27,8 +28,9 @@ impl InfinitePlane {
     }
 }
 
-impl Visible for InfinitePlane {
-    fn bounce(&self, r: Ray, t_range: &Range<f64>) -> Option<VisibleHit> {
+impl Intersect for InfinitePlane {
+    /// Distance along `r` to the plane, if it lies within `t_range`
+    fn intersect(&self, r: Ray, t_range: &Range<f64>) -> Option<f64> {
         let denom = self.normal.dot(r.direction());
 
         // ray direction is parallel to the plane
@@ -40,41 +42,110 @@ impl Visible for InfinitePlane {
 
         let t = numer / denom;
 
-        if t_range.contains(&t) {
-            Some(VisibleHit::new(
-                r,
-                r.at(t),
-                self.normal,
-                t,
-                Arc::clone(&self.material),
-            ))
-        } else {
-            None
-        }
+        t_range.contains(&t).then_some(t)
+    }
+}
+
+impl Visible for InfinitePlane {
+    fn bounce(&self, r: Ray, t_range: &Range<f64>) -> Option<VisibleHit> {
+        let t = self.intersect(r, t_range)?;
+
+        Some(VisibleHit::new(
+            r,
+            r.at(t),
+            self.normal,
+            t,
+            Arc::clone(&self.material),
+        ))
+    }
+
+    fn occluded(&self, r: Ray, t_range: &Range<f64>) -> bool {
+        self.intersect(r, t_range).is_some()
     }
 }
 
+#[derive(Clone)]
 pub struct Tri {
     vertices: [Point<f64>; 3],
     normal: Vec3<f64>,
+    /// Surface normals at the vertices, when they were given rather than derived from the winding
+    vertex_normals: Option<[Vec3<f64>; 3]>,
+    /// Texture coordinates at the vertices
+    uvs: Option<[[f64; 2]; 3]>,
     material: Arc<dyn Material>,
 }
 
 impl Tri {
     pub fn new(a: Point<f64>, b: Point<f64>, c: Point<f64>, material: Arc<dyn Material>) -> Self {
-        let normal = Vec3::from(b - a).cross((c - a).into());
+        let normal = Vec3::from(b - a).cross((c - a).into()).unit();
 
         Self {
             vertices: [a, b, c],
             material,
             normal,
+            vertex_normals: None,
+            uvs: None,
         }
     }
+
+    /// Attaches normals to the vertices, in the same order as the vertices
+    pub fn with_vertex_normals(mut self, normals: [Vec3<f64>; 3]) -> Self {
+        self.vertex_normals = Some(normals.map(|n| n.unit()));
+        self
+    }
+
+    /// Attaches texture coordinates to the vertices, in the same order as the vertices
+    pub fn with_uvs(mut self, uvs: [[f64; 2]; 3]) -> Self {
+        self.uvs = Some(uvs);
+        self
+    }
+
+    pub fn vertices(&self) -> [Point<f64>; 3] {
+        self.vertices
+    }
+
+    pub fn vertex_normals(&self) -> Option<[Vec3<f64>; 3]> {
+        self.vertex_normals
+    }
+
+    pub fn uvs(&self) -> Option<[[f64; 2]; 3]> {
+        self.uvs
+    }
+
+    pub fn material(&self) -> &Arc<dyn Material> {
+        &self.material
+    }
+
+    /// A copy of the triangle with its vertices moved by `t`. Transforms that mirror the
+    /// triangle also reverse its winding, so the normal keeps facing the same side of the surface.
+    pub fn transform(&self, t: &Transform) -> Self {
+        let mirror = t.determinant() < 0.0;
+
+        let [a, b, c] = wind(self.vertices.map(|v| t.point(v)), mirror);
+        let mut tri = Self::new(a, b, c, Arc::clone(&self.material));
+        tri.vertex_normals = self
+            .vertex_normals
+            .map(|normals| wind(normals.map(|n| t.normal(n).unit()), mirror));
+        tri.uvs = self.uvs.map(|uvs| wind(uvs, mirror));
+
+        tri
+    }
+}
+
+/// Per-vertex values in the order of a triangle whose winding was reversed when `reverse`
+fn wind<T>([a, b, c]: [T; 3], reverse: bool) -> [T; 3] {
+    if reverse {
+        [a, c, b]
+    } else {
+        [a, b, c]
+    }
 }
 
 const EPSILON: f64 = 0.000001;
-impl Visible for Tri {
-    fn bounce(&self, r: Ray, t_range: &Range<f64>) -> Option<VisibleHit> {
+impl Tri {
+    /// Distance along `r` to the triangle, if it is hit within `t_range`, along with the
+    /// barycentric coordinates `u` and `v` of the hit, which weigh the second and third vertex
+    fn intersect_barycentric(&self, r: Ray, t_range: &Range<f64>) -> Option<(f64, f64, f64)> {
         // implementation of the Möller–Trumbore ray-triangle intersection algorithm
         // variable names taken from: https://en.wikipedia.org/wiki/M%C3%B6ller%E2%80%93Trumbore_intersection_algorithm
 
@@ -96,7 +167,7 @@ impl Visible for Tri {
         let s = Vec3::from(r.origin() - a);
         let u = dot_inv * s.dot(h);
 
-        if u < 0.0 || u > 1.0 {
+        if !(0.0..=1.0).contains(&u) {
             return None;
         }
 
@@ -109,17 +180,33 @@ impl Visible for Tri {
 
         let t = dot_inv * e2.dot(q);
 
-        if t_range.contains(&t) {
-            Some(VisibleHit::new(
-                r,
-                r.at(t),
-                self.normal,
-                t,
-                Arc::clone(&self.material),
-            ))
-        } else {
-            None
-        }
+        t_range.contains(&t).then_some((t, u, v))
+    }
+}
+
+impl Intersect for Tri {
+    /// Distance along `r` to the triangle, if it is hit within `t_range`
+    fn intersect(&self, r: Ray, t_range: &Range<f64>) -> Option<f64> {
+        self.intersect_barycentric(r, t_range).map(|(t, _, _)| t)
+    }
+}
+
+impl Visible for Tri {
+    /// Triangles with vertex normals are shaded with the normal interpolated between them, while
+    /// the side of the triangle that was hit is still decided by its flat normal
+    fn bounce(&self, r: Ray, t_range: &Range<f64>) -> Option<VisibleHit> {
+        let (t, u, v) = self.intersect_barycentric(r, t_range)?;
+
+        let hit = VisibleHit::new(r, r.at(t), self.normal, t, Arc::clone(&self.material));
+
+        Some(match self.vertex_normals {
+            Some([a, b, c]) => hit.with_shading_normal(r, a * (1.0 - u - v) + b * u + c * v),
+            None => hit,
+        })
+    }
+
+    fn occluded(&self, r: Ray, t_range: &Range<f64>) -> bool {
+        self.intersect(r, t_range).is_some()
     }
 }
 
@@ -139,7 +226,6 @@ impl Bounded for Tri {
         let normalize = Point::new(inv, inv, inv);
 
         self.vertices
-            .clone()
             .into_iter()
             .reduce(|acc, item| acc + item)
             .unwrap()
@@ -147,4 +233,120 @@ impl Bounded for Tri {
     }
 }
 
-impl Primitive for Tri {}
+impl Primitive for Tri {
+    fn sample_surface(&self, _time: f64, sampler: &mut Sampler) -> (Point<f64>, Vec3<f64>) {
+        let (a, b, c) = (self.vertices[0], self.vertices[1], self.vertices[2]);
+
+        // fold samples from the far half of the parallelogram back into the triangle
+        let (mut u, mut v) = (sampler.next_f64(), sampler.next_f64());
+        if u + v > 1.0 {
+            u = 1.0 - u;
+            v = 1.0 - v;
+        }
+
+        let point = a + (Vec3::from(b - a) * u + Vec3::from(c - a) * v).into();
+
+        (point, self.normal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{color::Color, material::Lambertian, object::transformed::hit_to_world};
+
+    use super::*;
+
+    #[test]
+    fn transform_keeps_normal_side() {
+        let material = Arc::new(Lambertian::new(Color::white()));
+        let tri = Tri::new(
+            Point::new(0.0, 0.0, 0.0),
+            Point::new(1.0, 0.0, 0.0),
+            Point::new(0.0, 1.0, 0.0),
+            material,
+        )
+        .with_vertex_normals([
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(1.0, 0.0, 1.0),
+            Vec3::new(0.0, 1.0, 1.0),
+        ])
+        .with_uvs([[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]]);
+
+        for t in [
+            Transform::rotate(Vec3::new(1.0, 2.0, 3.0), 70.0),
+            Transform::scale(Vec3::new(-1.0, 2.0, 1.0)),
+        ] {
+            let moved = tri.transform(&t);
+            let expected = t.normal(tri.normal).unit();
+
+            assert!((moved.normal - expected).len() < 1e-9);
+
+            // per-vertex values stay with their vertex even when the winding is reversed
+            let normals = moved.vertex_normals().unwrap();
+            let uvs = moved.uvs().unwrap();
+            for (idx, vertex) in moved.vertices().into_iter().enumerate() {
+                let original = tri
+                    .vertices()
+                    .iter()
+                    .position(|&v| Vec3::from(t.point(v) - vertex).len() < 1e-9)
+                    .unwrap();
+                let normal = t.normal(tri.vertex_normals().unwrap()[original]).unit();
+
+                assert!((normals[idx] - normal).len() < 1e-9);
+                assert_eq!(uvs[idx], tri.uvs().unwrap()[original]);
+            }
+        }
+    }
+
+    #[test]
+    fn interpolates_vertex_normals() {
+        let material = Arc::new(Lambertian::new(Color::white()));
+        let normals = [
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(1.0, 0.0, 1.0),
+            Vec3::new(0.0, 1.0, 1.0),
+        ];
+        let tri = Tri::new(
+            Point::new(0.0, 0.0, 0.0),
+            Point::new(1.0, 0.0, 0.0),
+            Point::new(0.0, 1.0, 0.0),
+            material,
+        )
+        .with_vertex_normals(normals);
+        let (u, v) = (0.25, 0.5);
+        let expected =
+            (normals[0].unit() * (1.0 - u - v) + normals[1].unit() * u + normals[2].unit() * v)
+                .unit();
+
+        let from_front = Ray::new(Point::new(u, v, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = tri.bounce(from_front, &(0.0..f64::INFINITY)).unwrap();
+        assert!(hit.front_face);
+        assert!((hit.normal - expected).len() < 1e-9);
+        assert!((hit.geometric_normal - Vec3::new(0.0, 0.0, 1.0)).len() < 1e-9);
+
+        // both normals face the ray when the back is hit
+        let from_back = Ray::new(Point::new(u, v, -1.0), Vec3::new(0.0, 0.0, 1.0));
+        let hit = tri.bounce(from_back, &(0.0..f64::INFINITY)).unwrap();
+        assert!(!hit.front_face);
+        assert!((hit.normal + expected).len() < 1e-9);
+        assert!((hit.geometric_normal - Vec3::new(0.0, 0.0, -1.0)).len() < 1e-9);
+
+        // and so do they once moved into the world by an instance
+        let to_world = Transform::rotate(Vec3::new(1.0, 0.0, 0.0), 90.0);
+        let moved = hit_to_world(from_back, hit, &to_world);
+        assert!((moved.normal + to_world.normal(expected).unit()).len() < 1e-9);
+        assert!(moved.normal.dot(moved.geometric_normal) > 0.0);
+
+        // a grazing ray that the interpolated normal would reflect into the surface
+        let leaning = Tri::new(
+            Point::new(0.0, 0.0, 0.0),
+            Point::new(1.0, 0.0, 0.0),
+            Point::new(0.0, 1.0, 0.0),
+            Arc::new(Lambertian::new(Color::white())),
+        )
+        .with_vertex_normals([Vec3::new(1.0, 0.0, 0.2); 3]);
+        let grazing = Ray::new(Point::new(-0.75, 0.25, 0.1), Vec3::new(1.0, 0.0, -0.1));
+        let hit = leaning.bounce(grazing, &(0.0..f64::INFINITY)).unwrap();
+        assert!((hit.normal - Vec3::new(0.0, 0.0, 1.0)).len() < 1e-9);
+    }
+}
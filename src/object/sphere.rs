@@ -3,10 +3,11 @@ use std::{ops::Range, sync::Arc};
 use crate::{
     geometry::{Point, Ray, Vec3},
     material::Material,
+    sampler::Sampler,
 };
 
 use super::{
-    bvh::{Bounded, BoundingBox, Primitive},
+    bvh::{Bounded, BoundingBox, Intersect, Primitive},
     Visible, VisibleHit,
 };
 
@@ -26,51 +27,149 @@ impl Sphere {
     }
 }
 
+impl Intersect for Sphere {
+    /// Distance along `r` to the nearest intersection within `t_range`
+    fn intersect(&self, r: Ray, t_range: &Range<f64>) -> Option<f64> {
+        intersect_sphere(self.center, self.radius, r, t_range)
+    }
+}
+
+fn intersect_sphere(center: Point<f64>, radius: f64, r: Ray, t_range: &Range<f64>) -> Option<f64> {
+    let direction = r.direction();
+    let ray_origin = r.origin();
+
+    let offset = Vec3::from(ray_origin - center);
+
+    // Form quadratic for sphere intersection checking (simplified)
+    let a = direction.len_sq();
+    let half_b = direction.dot(offset);
+    let c = offset.len_sq() - radius.powi(2);
+
+    let discriminant = half_b.powi(2) - a * c;
+    let sqrt_discrim = discriminant.sqrt();
+
+    let plus_root = (-half_b + sqrt_discrim) / a;
+    let minus_root = (-half_b - sqrt_discrim) / a;
+
+    if t_range.contains(&minus_root) {
+        Some(minus_root)
+    } else if t_range.contains(&plus_root) {
+        Some(plus_root)
+    } else {
+        None
+    }
+}
+
+fn sphere_bbox(center: Point<f64>, radius: f64) -> BoundingBox {
+    let first = Point::new(radius, radius, radius);
+    let second = -first;
+
+    BoundingBox::from_points(&[first + center, second + center])
+}
+
 impl Visible for Sphere {
     fn bounce(&self, r: Ray, t_range: &Range<f64>) -> Option<VisibleHit> {
-        let direction = r.direction();
-        let ray_origin = r.origin();
+        let root = self.intersect(r, t_range)?;
+        let hit_point = r.at(root);
+
+        Some(VisibleHit::new(
+            r,
+            hit_point,
+            Vec3::from(hit_point - self.center) / self.radius,
+            root,
+            Arc::clone(&self.material),
+        ))
+    }
+
+    fn occluded(&self, r: Ray, t_range: &Range<f64>) -> bool {
+        self.intersect(r, t_range).is_some()
+    }
+}
+
+impl Bounded for Sphere {
+    fn bbox(&self) -> BoundingBox {
+        sphere_bbox(self.center, self.radius)
+    }
+
+    fn surface_area(&self) -> f64 {
+        4.0 * std::f64::consts::PI * self.radius.powi(2)
+    }
 
-        let offset = Vec3::from(ray_origin - self.center);
+    fn centroid(&self) -> Point<f64> {
+        self.center
+    }
+}
 
-        // Form quadratic for sphere intersection checking (simplified)
-        let a = direction.len_sq();
-        let half_b = direction.dot(offset);
-        let c = offset.len_sq() - self.radius.powi(2);
+impl Primitive for Sphere {
+    fn sample_surface(&self, _time: f64, sampler: &mut Sampler) -> (Point<f64>, Vec3<f64>) {
+        let normal = Vec3::random_unit(sampler);
 
-        let discriminant = half_b.powi(2) - a * c;
-        let sqrt_discrim = discriminant.sqrt();
+        (self.center + (normal * self.radius).into(), normal)
+    }
+}
+
+/// A sphere whose center moves in a straight line from `start` to `end` over a time range,
+/// staying put before and after it.
+pub struct MovingSphere {
+    start: Point<f64>,
+    end: Point<f64>,
+    time: Range<f64>,
+    radius: f64,
+    material: Arc<dyn Material>,
+}
 
-        let plus_root = (-half_b + sqrt_discrim) / a;
-        let minus_root = (-half_b - sqrt_discrim) / a;
+impl MovingSphere {
+    pub fn new(
+        start: Point<f64>,
+        end: Point<f64>,
+        time: Range<f64>,
+        radius: f64,
+        material: Arc<dyn Material>,
+    ) -> MovingSphere {
+        MovingSphere {
+            start,
+            end,
+            time,
+            radius,
+            material,
+        }
+    }
 
-        let root = if t_range.contains(&minus_root) {
-            minus_root
-        } else if t_range.contains(&plus_root) {
-            plus_root
+    pub fn center(&self, time: f64) -> Point<f64> {
+        let frac = if self.time.end > self.time.start {
+            ((time - self.time.start) / (self.time.end - self.time.start)).clamp(0.0, 1.0)
         } else {
-            return None;
+            0.0
         };
 
+        self.start + (Vec3::from(self.end - self.start) * frac).into()
+    }
+}
+
+impl Visible for MovingSphere {
+    fn bounce(&self, r: Ray, t_range: &Range<f64>) -> Option<VisibleHit> {
+        let center = self.center(r.time());
+        let root = intersect_sphere(center, self.radius, r, t_range)?;
         let hit_point = r.at(root);
 
         Some(VisibleHit::new(
             r,
             hit_point,
-            Vec3::from(hit_point - self.center) / self.radius,
+            Vec3::from(hit_point - center) / self.radius,
             root,
             Arc::clone(&self.material),
         ))
     }
+
+    fn occluded(&self, r: Ray, t_range: &Range<f64>) -> bool {
+        intersect_sphere(self.center(r.time()), self.radius, r, t_range).is_some()
+    }
 }
 
-impl Bounded for Sphere {
+impl Bounded for MovingSphere {
+    /// Covers the whole path, since the path is a straight line
     fn bbox(&self) -> BoundingBox {
-        let center = self.center;
-        let first = Point::new(self.radius, self.radius, self.radius);
-        let second = -first;
-
-        BoundingBox::from_points(&[first + center, second + center])
+        sphere_bbox(self.start, self.radius) + sphere_bbox(self.end, self.radius)
     }
 
     fn surface_area(&self) -> f64 {
@@ -78,8 +177,50 @@ impl Bounded for Sphere {
     }
 
     fn centroid(&self) -> Point<f64> {
-        self.center
+        self.center(self.time.start + (self.time.end - self.time.start) / 2.0)
     }
 }
 
-impl Primitive for Sphere {}
+impl Primitive for MovingSphere {
+    fn sample_surface(&self, time: f64, sampler: &mut Sampler) -> (Point<f64>, Vec3<f64>) {
+        let normal = Vec3::random_unit(sampler);
+
+        (self.center(time) + (normal * self.radius).into(), normal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{color::Color, material::Lambertian};
+
+    use super::*;
+
+    #[test]
+    fn moving_sphere_follows_time() {
+        let material = Arc::new(Lambertian::new(Color::white()));
+        let sphere = MovingSphere::new(
+            Point::new(0.0, 0.0, -5.0),
+            Point::new(4.0, 0.0, -5.0),
+            0.0..1.0,
+            1.0,
+            material,
+        );
+        let t_range = 0.0..f64::INFINITY;
+        let down = Vec3::new(0.0, 0.0, -1.0);
+
+        for (time, x, hits) in [
+            (0.0, 0.0, true),
+            (0.0, 2.0, false),
+            (0.5, 2.0, true),
+            (2.0, 4.0, true),
+        ] {
+            let r = Ray::with_time(Point::new(x, 0.0, 0.0), down, time);
+            assert_eq!(sphere.bounce(r, &t_range).is_some(), hits);
+            assert_eq!(sphere.occluded(r, &t_range), hits);
+        }
+
+        let bbox = sphere.bbox();
+        assert_eq!(bbox.x(), -1.0..5.0);
+        assert_eq!(bbox.z(), -6.0..-4.0);
+    }
+}
@@ -0,0 +1,151 @@
+use std::ops::Range;
+
+use crate::{
+    geometry::{Point, Ray, Transform, Vec3},
+    sampler::Sampler,
+};
+
+use super::{
+    bvh::{Bounded, BoundingBox, Primitive},
+    Visible, VisibleHit,
+};
+
+/// A primitive moved by a transform. Rays are brought into the primitive's own coordinates to be
+/// intersected, so e.g. a non-uniformly scaled `Sphere` becomes an ellipsoid.
+pub struct Transformed<P: Primitive> {
+    inner: P,
+    to_world: Transform,
+    to_object: Transform,
+    bbox: BoundingBox,
+}
+
+impl<P: Primitive> Transformed<P> {
+    /// Places `inner` by `transform`, which maps its coordinates to the scene's
+    pub fn new(inner: P, transform: Transform) -> Self {
+        let bbox = inner.bbox().transform(&transform);
+
+        Self {
+            inner,
+            to_object: transform.inverse(),
+            to_world: transform,
+            bbox,
+        }
+    }
+
+    pub fn inner(&self) -> &P {
+        &self.inner
+    }
+
+    pub fn transform(&self) -> &Transform {
+        &self.to_world
+    }
+}
+
+/// Turns a hit found along `to_object.ray(r)` into a hit along `r`. The object space ray isn't
+/// normalized, so the distance along it is also the distance along `r`.
+pub(super) fn hit_to_world(r: Ray, hit: VisibleHit, to_world: &Transform) -> VisibleHit {
+    let outward = if hit.front_face {
+        hit.geometric_normal
+    } else {
+        -hit.geometric_normal
+    };
+
+    VisibleHit::new(
+        r,
+        r.at(hit.t),
+        to_world.normal(outward).unit(),
+        hit.t,
+        hit.material,
+    )
+    .with_shading_normal(r, to_world.normal(hit.normal))
+}
+
+/// Surface area scale of `to_world`, which is exact for rotations, translations and uniform
+/// scales. Non-uniform scales stretch differently oriented parts of a surface by different
+/// amounts, for which this is only an estimate.
+pub(super) fn area_scale(to_world: &Transform) -> f64 {
+    to_world
+        .area_scale()
+        .unwrap_or_else(|| to_world.determinant().abs().powf(2.0 / 3.0))
+}
+
+impl<P: Primitive> Visible for Transformed<P> {
+    fn bounce(&self, r: Ray, t_range: &Range<f64>) -> Option<VisibleHit> {
+        let hit = self.inner.bounce(self.to_object.ray(r), t_range)?;
+
+        Some(hit_to_world(r, hit, &self.to_world))
+    }
+
+    fn occluded(&self, r: Ray, t_range: &Range<f64>) -> bool {
+        self.inner.occluded(self.to_object.ray(r), t_range)
+    }
+}
+
+impl<P: Primitive> Bounded for Transformed<P> {
+    fn bbox(&self) -> BoundingBox {
+        self.bbox.clone()
+    }
+
+    /// See `area_scale` for when this is exact
+    fn surface_area(&self) -> f64 {
+        self.inner.surface_area() * area_scale(&self.to_world)
+    }
+
+    fn centroid(&self) -> Point<f64> {
+        self.to_world.point(self.inner.centroid())
+    }
+}
+
+impl<P: Primitive> Primitive for Transformed<P> {
+    /// Uniform under the same transforms for which `surface_area` is exact
+    fn sample_surface(&self, time: f64, sampler: &mut Sampler) -> (Point<f64>, Vec3<f64>) {
+        let (point, normal) = self.inner.sample_surface(time, sampler);
+
+        (
+            self.to_world.point(point),
+            self.to_world.normal(normal).unit(),
+        )
+    }
+
+    fn samples_uniformly(&self) -> bool {
+        self.inner.samples_uniformly() && self.to_world.area_scale().is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{color::Color, material::Lambertian, object::Sphere};
+
+    use super::*;
+
+    #[test]
+    fn scaled_sphere_is_ellipsoid() {
+        let material = Arc::new(Lambertian::new(Color::white()));
+        let sphere = Sphere::new(Point::new(0.0, 0.0, 0.0), 1.0, material);
+        let ellipsoid = Transformed::new(
+            sphere,
+            Transform::scale(Vec3::new(3.0, 1.0, 1.0))
+                .then(&Transform::translate(Vec3::new(0.0, 0.0, -10.0))),
+        );
+
+        // along the stretched axis
+        let r = Ray::new(Point::new(-5.0, 0.0, -10.0), Vec3::new(1.0, 0.0, 0.0));
+        let hit = ellipsoid.bounce(r, &(0.0..f64::INFINITY)).unwrap();
+        assert!((hit.t - 2.0).abs() < 1e-9);
+        assert!((hit.normal - Vec3::new(-1.0, 0.0, 0.0)).len() < 1e-9);
+
+        // misses the unscaled sphere's silhouette but not the ellipsoid's
+        let r = Ray::new(Point::new(2.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(ellipsoid.occluded(r, &(0.0..f64::INFINITY)));
+        assert!(!ellipsoid.occluded(r, &(0.0..9.0)));
+
+        let bbox = ellipsoid.bbox();
+        assert_eq!(bbox.x(), -3.0..3.0);
+        assert_eq!(bbox.z(), -11.0..-9.0);
+
+        // sampling by the sphere's area would crowd the samples at the ends
+        assert!(!ellipsoid.samples_uniformly());
+    }
+}
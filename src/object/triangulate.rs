@@ -0,0 +1,238 @@
+use crate::geometry::{Point, Vec3};
+
+/// Splits a planar polygon into triangles, returned as indices into `polygon` wound the same way
+/// as the polygon. Convex polygons are split into a fan around the first vertex, and concave ones
+/// by clipping ears. Triangles without area are left out, so degenerate polygons (repeated or
+/// collinear vertices) give fewer triangles or none at all.
+pub(super) fn triangulate(polygon: &[Point<f64>]) -> Vec<[usize; 3]> {
+    // consecutive repeats of the same point, including the last and first, add nothing
+    let mut corners: Vec<usize> = (0..polygon.len()).collect();
+    corners.dedup_by(|b, a| polygon[*a] == polygon[*b]);
+    while corners.len() > 1 && polygon[corners[0]] == polygon[*corners.last().unwrap()] {
+        corners.pop();
+    }
+
+    if corners.len() < 3 {
+        return Vec::new();
+    }
+
+    let points: Vec<_> = corners.iter().map(|&idx| polygon[idx]).collect();
+    let normal = newell_normal(&points);
+
+    let local = if is_convex(&points, normal) {
+        fan(points.len())
+    } else {
+        // a self-intersecting polygon has no ears left at some point, and a fan is as good a
+        // guess as any for it
+        ear_clip(&project(&points, normal)).unwrap_or_else(|| fan(points.len()))
+    };
+
+    local
+        .into_iter()
+        .filter(|&[a, b, c]| !is_degenerate(points[a], points[b], points[c]))
+        .map(|tri| tri.map(|idx| corners[idx]))
+        .collect()
+}
+
+/// Whether the triangle has no area to speak of, relative to its size
+fn is_degenerate(a: Point<f64>, b: Point<f64>, c: Point<f64>) -> bool {
+    let (ab, ac) = (Vec3::from(b - a), Vec3::from(c - a));
+
+    ab.cross(ac).len() <= 1e-12 * ab.len_sq().max(ac.len_sq())
+}
+
+/// Normal of the plane best fitting the polygon, pointing the way its winding faces, with a
+/// length of twice its area
+pub(super) fn newell_normal(points: &[Point<f64>]) -> Vec3<f64> {
+    let mut normal = Vec3::new(0.0, 0.0, 0.0);
+
+    for (idx, &current) in points.iter().enumerate() {
+        let next = points[(idx + 1) % points.len()];
+        normal += Vec3::new(
+            (current.y() - next.y()) * (current.z() + next.z()),
+            (current.z() - next.z()) * (current.x() + next.x()),
+            (current.x() - next.x()) * (current.y() + next.y()),
+        );
+    }
+
+    normal
+}
+
+/// Whether every corner turns the same way around `normal` (or goes straight on)
+fn is_convex(points: &[Point<f64>], normal: Vec3<f64>) -> bool {
+    let n = points.len();
+
+    (0..n).all(|idx| {
+        let (prev, current, next) = (
+            points[(idx + n - 1) % n],
+            points[idx],
+            points[(idx + 1) % n],
+        );
+        let turn = Vec3::from(current - prev).cross((next - current).into());
+
+        turn.dot(normal) >= 0.0
+    })
+}
+
+fn fan(count: usize) -> Vec<[usize; 3]> {
+    (1..count - 1).map(|idx| [0, idx, idx + 1]).collect()
+}
+
+/// Flattens the points onto the axis-aligned plane the polygon is most nearly parallel to
+fn project(points: &[Point<f64>], normal: Vec3<f64>) -> Vec<[f64; 2]> {
+    let (x, y, z) = (normal.x().abs(), normal.y().abs(), normal.z().abs());
+
+    points
+        .iter()
+        .map(|p| {
+            if x >= y && x >= z {
+                [p.y(), p.z()]
+            } else if y >= z {
+                [p.z(), p.x()]
+            } else {
+                [p.x(), p.y()]
+            }
+        })
+        .collect()
+}
+
+/// Twice the signed area of the 2D triangle, positive when it is wound counter-clockwise
+fn signed_area(a: [f64; 2], b: [f64; 2], c: [f64; 2]) -> f64 {
+    (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])
+}
+
+/// Triangulates a simple 2D polygon by repeatedly cutting off an ear: a convex corner whose
+/// triangle contains no other vertex. Returns `None` when no ear is left before the end, which
+/// only happens for polygons that cross themselves.
+fn ear_clip(points: &[[f64; 2]]) -> Option<Vec<[usize; 3]>> {
+    let n = points.len();
+
+    // corners turning the same way as the polygon as a whole are convex
+    let winding = (0..n)
+        .map(|idx| {
+            let (a, b) = (points[idx], points[(idx + 1) % n]);
+            a[0] * b[1] - b[0] * a[1]
+        })
+        .sum::<f64>()
+        .signum();
+
+    let mut remaining: Vec<usize> = (0..n).collect();
+    let mut triangles = Vec::with_capacity(n - 2);
+
+    while remaining.len() > 3 {
+        let len = remaining.len();
+
+        let ear = (0..len).find(|&idx| {
+            let corner = [
+                remaining[(idx + len - 1) % len],
+                remaining[idx],
+                remaining[(idx + 1) % len],
+            ];
+            let [a, b, c] = corner.map(|idx| points[idx]);
+
+            if signed_area(a, b, c) * winding <= 0.0 {
+                return false;
+            }
+
+            remaining
+                .iter()
+                .filter(|idx| !corner.contains(idx))
+                .all(|&idx| !in_triangle(points[idx], a, b, c, winding))
+        })?;
+
+        triangles.push([
+            remaining[(ear + len - 1) % len],
+            remaining[ear],
+            remaining[(ear + 1) % len],
+        ]);
+        remaining.remove(ear);
+    }
+
+    triangles.push([remaining[0], remaining[1], remaining[2]]);
+
+    Some(triangles)
+}
+
+/// Whether `p` lies inside or on the edge of the triangle, which is wound the way of `winding`
+fn in_triangle(p: [f64; 2], a: [f64; 2], b: [f64; 2], c: [f64; 2], winding: f64) -> bool {
+    signed_area(a, b, p) * winding >= 0.0
+        && signed_area(b, c, p) * winding >= 0.0
+        && signed_area(c, a, p) * winding >= 0.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn area(polygon: &[Point<f64>], triangles: &[[usize; 3]]) -> f64 {
+        triangles
+            .iter()
+            .map(|&[a, b, c]| {
+                Vec3::from(polygon[b] - polygon[a])
+                    .cross((polygon[c] - polygon[a]).into())
+                    .len()
+                    / 2.0
+            })
+            .sum()
+    }
+
+    #[test]
+    fn quad_fan() {
+        let quad = [
+            Point::new(0.0, 0.0, 0.0),
+            Point::new(1.0, 0.0, 0.0),
+            Point::new(1.0, 1.0, 0.0),
+            Point::new(0.0, 1.0, 0.0),
+        ];
+
+        assert_eq!(triangulate(&quad), vec![[0, 1, 2], [0, 2, 3]]);
+    }
+
+    #[test]
+    fn concave_polygon() {
+        // an L shape standing in the xz plane, wound clockwise seen from above
+        let l_shape = [
+            Point::new(0.0, 0.0, 0.0),
+            Point::new(0.0, 0.0, 2.0),
+            Point::new(2.0, 0.0, 2.0),
+            Point::new(2.0, 0.0, 1.0),
+            Point::new(1.0, 0.0, 1.0),
+            Point::new(1.0, 0.0, 0.0),
+        ];
+        let triangles = triangulate(&l_shape);
+
+        assert_eq!(triangles.len(), 4);
+        assert!((area(&l_shape, &triangles) - 3.0).abs() < 1e-9);
+
+        // every triangle faces the same way as the polygon
+        let normal = newell_normal(&l_shape);
+        for &[a, b, c] in &triangles {
+            let facing =
+                Vec3::from(l_shape[b] - l_shape[a]).cross((l_shape[c] - l_shape[a]).into());
+            assert!(facing.dot(normal) > 0.0);
+        }
+    }
+
+    #[test]
+    fn degenerate_polygons() {
+        let p = |x, y| Point::new(x, y, 0.0);
+
+        // collinear
+        assert!(triangulate(&[p(0.0, 0.0), p(1.0, 0.0), p(2.0, 0.0)]).is_empty());
+        // repeated vertices
+        assert!(triangulate(&[p(0.0, 0.0), p(1.0, 0.0), p(1.0, 0.0), p(0.0, 0.0)]).is_empty());
+
+        // a repeated vertex in an otherwise fine quad
+        let quad = [
+            p(0.0, 0.0),
+            p(1.0, 0.0),
+            p(1.0, 0.0),
+            p(1.0, 1.0),
+            p(0.0, 1.0),
+        ];
+        let triangles = triangulate(&quad);
+        assert_eq!(triangles.len(), 2);
+        assert!((area(&quad, &triangles) - 1.0).abs() < 1e-9);
+        assert!(triangles.iter().all(|tri| !tri.contains(&2)));
+    }
+}
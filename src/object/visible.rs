@@ -5,12 +5,19 @@ use crate::{
     material::Material,
 };
 
+#[allow(clippy::manual_non_exhaustive)]
 pub struct VisibleHit {
     pub point: Point<f64>,
+    /// Normal used for shading, which differs from `geometric_normal` on surfaces with
+    /// interpolated normals. Both face the side of the surface the ray came from.
     pub normal: Vec3<f64>,
+    /// Normal of the surface itself, which decides `front_face`
+    pub geometric_normal: Vec3<f64>,
     pub t: f64,
     pub material: Arc<dyn Material>,
     pub front_face: bool,
+    /// Index of the primitive within the acceleration structure that produced the hit, if any
+    pub primitive_id: Option<usize>,
 
     /// Introduce a private field to force users to use the new() function (can't bypass front_face calculation).
     /// Still allows for pub access of fields.
@@ -33,16 +40,42 @@ impl VisibleHit {
         Self {
             point,
             normal,
+            geometric_normal: normal,
             t,
             material,
             front_face,
+            primitive_id: None,
             _force_new: (),
         }
     }
+
+    /// Shades the hit with `normal` instead of the geometric normal, e.g. one interpolated
+    /// across a triangle. It is flipped to the side the ray came from, like the geometric normal.
+    /// Near silhouettes the ray can meet the surface from behind `normal`, and would be reflected
+    /// into the surface rather than off it, so those hits keep the geometric normal.
+    pub fn with_shading_normal(mut self, r: Ray, normal: Vec3<f64>) -> Self {
+        let normal = normal.unit();
+        let normal = if normal.dot(self.geometric_normal) < 0.0 {
+            -normal
+        } else {
+            normal
+        };
+
+        if r.direction().reflect(normal).dot(self.geometric_normal) > 0.0 {
+            self.normal = normal;
+        }
+
+        self
+    }
 }
 
 pub trait Visible: Sync {
     fn bounce(&self, r: Ray, t_range: &Range<f64>) -> Option<VisibleHit>;
+
+    /// Whether anything is hit along `r` within `t_range`. Unlike `bounce` this can stop at the
+    /// first intersection found and never builds a hit record, which makes it the query to use
+    /// for shadow rays.
+    fn occluded(&self, r: Ray, t_range: &Range<f64>) -> bool;
 }
 
 /// Stores a list of references to Hit objects
@@ -64,6 +97,14 @@ impl VisibleList {
         list
     }
 
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
     pub fn add(&mut self, object: Box<dyn Visible>) {
         self.objects.push(object);
     }
@@ -73,6 +114,12 @@ impl VisibleList {
     }
 }
 
+impl Default for VisibleList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Visible for VisibleList {
     /// Returns the closest hit from hitting all elements in the list
     fn bounce(&self, r: Ray, t_range: &Range<f64>) -> Option<VisibleHit> {
@@ -91,4 +138,8 @@ impl Visible for VisibleList {
             })
             .reduce(|acc, hit| if acc.t > hit.t { hit } else { acc })
     }
+
+    fn occluded(&self, r: Ray, t_range: &Range<f64>) -> bool {
+        self.objects.iter().any(|x| x.occluded(r, t_range))
+    }
 }
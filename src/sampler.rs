@@ -0,0 +1,59 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+/// Source of random numbers for rendering.
+///
+/// Each pixel gets its own sampler seeded from the render seed and the pixel coordinate, so a
+/// render is reproducible no matter which thread ends up rendering which pixel.
+pub struct Sampler {
+    rng: StdRng,
+}
+
+impl Sampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Creates the sampler for pixel `(x, y)` of a render seeded with `seed`
+    pub fn for_pixel(seed: u64, x: usize, y: usize) -> Self {
+        let pixel = ((y as u64) << 32) | (x as u64 & 0xffff_ffff);
+
+        Self::new(mix(seed ^ mix(pixel)))
+    }
+
+    /// Returns a random number in the half-open range [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        self.rng.gen()
+    }
+
+    /// Returns a random number in the half-open range [min, max)
+    pub fn range(&mut self, min: f64, max: f64) -> f64 {
+        self.rng.gen_range(min..max)
+    }
+}
+
+/// SplitMix64 finalizer, spreading nearby inputs across the whole seed space
+fn mix(mut z: u64) -> u64 {
+    z = z.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixel_samplers_are_reproducible() {
+        let draw = |seed, x, y| {
+            let mut sampler = Sampler::for_pixel(seed, x, y);
+            (0..4).map(|_| sampler.next_f64()).collect::<Vec<_>>()
+        };
+
+        assert_eq!(draw(1, 3, 7), draw(1, 3, 7));
+        assert_ne!(draw(1, 3, 7), draw(1, 7, 3));
+        assert_ne!(draw(1, 3, 7), draw(2, 3, 7));
+    }
+}
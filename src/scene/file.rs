@@ -0,0 +1,1415 @@
+//! Loader for the plain-text scene description format.
+//!
+//! A scene file is a list of directives, one per line. Each directive starts with its name,
+//! followed by positional words and `key=value` properties. Values are numbers, bare words,
+//! quoted strings, or vectors written as `(x, y, z)`. Everything after a `#` is a comment.
+//!
+//! ```text
+//! render width=400 height=225 samples=100 max_depth=50
+//! camera from=(3, 3, 10) at=(0, 1, 0) fov=30
+//! sky day
+//!
+//! material pink diffuse color=(1, 0, 1)
+//! material ground diffuse color=(0.2, 0.2, 0.01)
+//!
+//! object path="teapot.obj" material=pink
+//! plane origin=(0, 0, 0) normal=(0, 1, 0) material=ground
+//! ```
+//!
+//! Relative paths are resolved against the directory containing the scene file.
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt::Display,
+    fs, io,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    color::Color,
+    geometry::{Point, Transform, Vec3},
+    material::Material,
+    object::{bvh::CacheStatus, LoadError, Mesh},
+    sky::{Day, Uniform},
+};
+
+use super::Scene;
+
+/// Image and sampling parameters that accompany a scene description.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderSettings {
+    pub width: usize,
+    pub height: usize,
+    pub samples_per_pixel: u32,
+    pub max_depth: u32,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            width: 400,
+            height: 225,
+            samples_per_pixel: 100,
+            max_depth: 50,
+        }
+    }
+}
+
+impl RenderSettings {
+    pub fn aspect_ratio(&self) -> f64 {
+        self.width as f64 / self.height as f64
+    }
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(io::Error),
+    Parse {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    /// An OBJ file the scene refers to couldn't be loaded
+    Load {
+        line: usize,
+        column: usize,
+        path: PathBuf,
+        error: LoadError,
+    },
+}
+
+impl SceneError {
+    fn at(pos: Position, message: impl Into<String>) -> Self {
+        SceneError::Parse {
+            line: pos.line,
+            column: pos.column,
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for SceneError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SceneError::Io(err) => write!(f, "{}", err),
+            SceneError::Parse {
+                line,
+                column,
+                message,
+            } => write!(f, "{}:{}: {}", line, column, message),
+            SceneError::Load {
+                line,
+                column,
+                path,
+                error,
+            } => write!(f, "{}:{}: {}: {}", line, column, path.display(), error),
+        }
+    }
+}
+
+impl Error for SceneError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SceneError::Io(err) => Some(err),
+            SceneError::Parse { .. } => None,
+            SceneError::Load { error, .. } => Some(error),
+        }
+    }
+}
+
+impl From<io::Error> for SceneError {
+    fn from(err: io::Error) -> Self {
+        SceneError::Io(err)
+    }
+}
+
+type Result<T> = std::result::Result<T, SceneError>;
+
+impl Scene {
+    /// Reads a scene description from disk, returning the scene along with its render settings.
+    pub fn load(path: impl Into<PathBuf>) -> Result<(Scene, RenderSettings)> {
+        Scene::new().load_into(path)
+    }
+
+    /// Like `load`, but adds to this scene, so options that affect loading (such as
+    /// `mesh_cache` or `bvh_config`) can be set beforehand.
+    pub fn load_into(self, path: impl Into<PathBuf>) -> Result<(Scene, RenderSettings)> {
+        let path = path.into();
+        let source = fs::read_to_string(&path)?;
+        let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+        self.parse_into(&source, base_dir)
+    }
+
+    /// Builds a scene from the text of a scene description.
+    /// Relative paths inside the description are resolved against `base_dir`.
+    pub fn parse(source: &str, base_dir: &Path) -> Result<(Scene, RenderSettings)> {
+        Scene::new().parse_into(source, base_dir)
+    }
+
+    /// Like `parse`, but adds to this scene
+    pub fn parse_into(self, source: &str, base_dir: &Path) -> Result<(Scene, RenderSettings)> {
+        let mut builder = Builder::new(base_dir, self);
+
+        for (idx, line) in source.lines().enumerate() {
+            let tokens = tokenize(line, idx + 1)?;
+
+            if let Some(statement) = parse_statement(tokens)? {
+                builder.apply(statement)?;
+            }
+        }
+
+        Ok(builder.finish())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Position {
+    line: usize,
+    column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Str(String),
+    Number(f64),
+    Equals,
+    LeftParen,
+    RightParen,
+    Comma,
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Word(word) => write!(f, "`{}`", word),
+            Token::Str(s) => write!(f, "\"{}\"", s),
+            Token::Number(n) => write!(f, "`{}`", n),
+            Token::Equals => write!(f, "`=`"),
+            Token::LeftParen => write!(f, "`(`"),
+            Token::RightParen => write!(f, "`)`"),
+            Token::Comma => write!(f, "`,`"),
+        }
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '-' | '+' | '.' | '/')
+}
+
+fn tokenize(line: &str, line_no: usize) -> Result<Vec<(Token, Position)>> {
+    let mut tokens = Vec::new();
+    let mut chars = line.chars().enumerate().peekable();
+
+    while let Some((idx, c)) = chars.next() {
+        let pos = Position {
+            line: line_no,
+            column: idx + 1,
+        };
+
+        let token = match c {
+            '#' => break,
+            c if c.is_whitespace() => continue,
+            '=' => Token::Equals,
+            '(' => Token::LeftParen,
+            ')' => Token::RightParen,
+            ',' => Token::Comma,
+            '"' => {
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, c)) => s.push(c),
+                        None => return Err(SceneError::at(pos, "unterminated string")),
+                    }
+                }
+                Token::Str(s)
+            }
+            c if is_word_char(c) => {
+                let mut word = String::from(c);
+                while let Some(&(_, c)) = chars.peek() {
+                    if !is_word_char(c) {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+
+                let numeric = word.starts_with(|c: char| c.is_ascii_digit() || "+-.".contains(c));
+                if numeric {
+                    match word.parse() {
+                        Ok(n) => Token::Number(n),
+                        Err(_) => {
+                            return Err(SceneError::at(pos, format!("invalid number `{}`", word)))
+                        }
+                    }
+                } else {
+                    Token::Word(word)
+                }
+            }
+            c => return Err(SceneError::at(pos, format!("unexpected character `{}`", c))),
+        };
+
+        tokens.push((token, pos));
+    }
+
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Number(f64),
+    Vector(Vec3<f64>),
+    Word(String),
+    Str(String),
+}
+
+impl Value {
+    fn kind(&self) -> &'static str {
+        match self {
+            Value::Number(_) => "a number",
+            Value::Vector(_) => "a vector",
+            Value::Word(_) => "a name",
+            Value::Str(_) => "a string",
+        }
+    }
+}
+
+/// A single parsed directive, e.g. `material red diffuse color=(1, 0, 0)`
+struct Statement {
+    name: String,
+    pos: Position,
+    args: Vec<(String, Position)>,
+    props: HashMap<String, (Value, Position)>,
+}
+
+fn parse_statement(tokens: Vec<(Token, Position)>) -> Result<Option<Statement>> {
+    let mut tokens = tokens.into_iter().peekable();
+
+    let (name, pos) = match tokens.next() {
+        None => return Ok(None),
+        Some((Token::Word(name), pos)) => (name, pos),
+        Some((token, pos)) => {
+            return Err(SceneError::at(
+                pos,
+                format!("expected a directive, found {}", token),
+            ))
+        }
+    };
+
+    let mut statement = Statement {
+        name,
+        pos,
+        args: Vec::new(),
+        props: HashMap::new(),
+    };
+
+    while let Some((token, pos)) = tokens.next() {
+        let word = match token {
+            Token::Word(word) => word,
+            token => return Err(SceneError::at(pos, format!("unexpected {}", token))),
+        };
+
+        if !matches!(tokens.peek(), Some((Token::Equals, _))) {
+            statement.args.push((word, pos));
+            continue;
+        }
+        let (_, equals_pos) = tokens.next().unwrap();
+
+        let value = match tokens.next() {
+            Some((Token::Number(n), _)) => Value::Number(n),
+            Some((Token::Word(w), _)) => Value::Word(w),
+            Some((Token::Str(s), _)) => Value::Str(s),
+            Some((Token::LeftParen, paren_pos)) => {
+                let mut coords = Vec::new();
+                loop {
+                    match tokens.next() {
+                        Some((Token::Number(n), _)) => coords.push(n),
+                        Some((token, pos)) => {
+                            return Err(SceneError::at(
+                                pos,
+                                format!("expected a number, found {}", token),
+                            ))
+                        }
+                        None => return Err(SceneError::at(paren_pos, "unclosed `(`")),
+                    }
+
+                    match tokens.next() {
+                        Some((Token::Comma, _)) => continue,
+                        Some((Token::RightParen, _)) => break,
+                        Some((token, pos)) => {
+                            return Err(SceneError::at(
+                                pos,
+                                format!("expected `,` or `)`, found {}", token),
+                            ))
+                        }
+                        None => return Err(SceneError::at(paren_pos, "unclosed `(`")),
+                    }
+                }
+
+                if coords.len() != 3 {
+                    return Err(SceneError::at(
+                        paren_pos,
+                        format!("expected 3 components, found {}", coords.len()),
+                    ));
+                }
+
+                Value::Vector(Vec3::new(coords[0], coords[1], coords[2]))
+            }
+            Some((token, pos)) => {
+                return Err(SceneError::at(
+                    pos,
+                    format!("expected a value, found {}", token),
+                ))
+            }
+            None => {
+                return Err(SceneError::at(
+                    equals_pos,
+                    format!("missing value for `{}`", word),
+                ))
+            }
+        };
+
+        if statement.props.contains_key(&word) {
+            return Err(SceneError::at(pos, format!("`{}` is given twice", word)));
+        }
+        statement.props.insert(word, (value, pos));
+    }
+
+    Ok(Some(statement))
+}
+
+impl Statement {
+    /// Ensures the statement has exactly `count` positional arguments and only the given properties
+    fn expect(&self, count: usize, allowed: &[&str]) -> Result<()> {
+        if self.args.len() > count {
+            let (arg, pos) = &self.args[count];
+            return Err(SceneError::at(
+                *pos,
+                format!("unexpected argument `{}` to `{}`", arg, self.name),
+            ));
+        }
+
+        if self.args.len() < count {
+            return Err(SceneError::at(
+                self.pos,
+                format!("`{}` expects {} argument(s)", self.name, count),
+            ));
+        }
+
+        let mut unknown: Vec<_> = self
+            .props
+            .iter()
+            .filter(|(key, _)| !allowed.contains(&key.as_str()))
+            .collect();
+        unknown.sort_by_key(|(_, (_, pos))| pos.column);
+
+        if let Some((key, (_, pos))) = unknown.first() {
+            return Err(SceneError::at(
+                *pos,
+                format!("unknown property `{}` for `{}`", key, self.name),
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn arg(&self, idx: usize) -> (&str, Position) {
+        let (arg, pos) = &self.args[idx];
+        (arg.as_str(), *pos)
+    }
+
+    /// Reports a failure to load the file given by the statement's `path`
+    fn load_error(&self, path: PathBuf, error: LoadError) -> SceneError {
+        let (_, pos) = self.props["path"];
+
+        SceneError::Load {
+            line: pos.line,
+            column: pos.column,
+            path,
+            error,
+        }
+    }
+
+    /// Describes a problem with the file given by the statement's `path` that didn't stop it
+    /// from loading, in the same form as `load_error`
+    fn load_warning(&self, path: &Path, message: impl Display) -> String {
+        let (_, pos) = self.props["path"];
+
+        format!(
+            "{}:{}: {}: {}",
+            pos.line,
+            pos.column,
+            path.display(),
+            message
+        )
+    }
+
+    fn missing(&self, key: &str) -> SceneError {
+        SceneError::at(
+            self.pos,
+            format!("`{}` is missing property `{}`", self.name, key),
+        )
+    }
+
+    fn mismatch(key: &str, expected: &str, value: &Value, pos: Position) -> SceneError {
+        SceneError::at(
+            pos,
+            format!("`{}` must be {}, found {}", key, expected, value.kind()),
+        )
+    }
+
+    fn number(&self, key: &str) -> Result<Option<f64>> {
+        match self.props.get(key) {
+            None => Ok(None),
+            Some((Value::Number(n), _)) => Ok(Some(*n)),
+            Some((value, pos)) => Err(Statement::mismatch(key, "a number", value, *pos)),
+        }
+    }
+
+    /// A non-negative integer small enough for the `u32` settings counts end up in
+    fn count(&self, key: &str) -> Result<Option<u32>> {
+        match self.number(key)? {
+            Some(n) if n < 0.0 || n.fract() != 0.0 => {
+                let (_, pos) = self.props[key];
+                Err(SceneError::at(
+                    pos,
+                    format!("`{}` must be a non-negative integer", key),
+                ))
+            }
+            Some(n) if n > u32::MAX as f64 => {
+                let (_, pos) = self.props[key];
+                Err(SceneError::at(
+                    pos,
+                    format!("`{}` must be at most {}", key, u32::MAX),
+                ))
+            }
+            n => Ok(n.map(|n| n as u32)),
+        }
+    }
+
+    /// Like `count`, for counts that can't be less than `min`
+    fn count_at_least(&self, key: &str, min: u32) -> Result<Option<u32>> {
+        match self.count(key)? {
+            Some(n) if n < min => {
+                let (_, pos) = self.props[key];
+                Err(SceneError::at(
+                    pos,
+                    format!("`{}` must be at least {}", key, min),
+                ))
+            }
+            n => Ok(n),
+        }
+    }
+
+    fn vector(&self, key: &str) -> Result<Option<Vec3<f64>>> {
+        match self.props.get(key) {
+            None => Ok(None),
+            Some((Value::Vector(v), _)) => Ok(Some(*v)),
+            Some((value, pos)) => Err(Statement::mismatch(key, "a vector", value, *pos)),
+        }
+    }
+
+    fn name(&self, key: &str) -> Result<Option<(&str, Position)>> {
+        match self.props.get(key) {
+            None => Ok(None),
+            Some((Value::Word(s), pos)) | Some((Value::Str(s), pos)) => Ok(Some((s, *pos))),
+            Some((value, pos)) => Err(Statement::mismatch(key, "a name", value, *pos)),
+        }
+    }
+
+    fn required_number(&self, key: &str) -> Result<f64> {
+        self.number(key)?.ok_or_else(|| self.missing(key))
+    }
+
+    /// Like `required_number`, for sizes and such that must be greater than zero
+    fn required_positive(&self, key: &str) -> Result<f64> {
+        let n = self.required_number(key)?;
+        if n > 0.0 {
+            return Ok(n);
+        }
+
+        let (_, pos) = self.props[key];
+        Err(SceneError::at(
+            pos,
+            format!("`{}` must be greater than zero", key),
+        ))
+    }
+
+    fn required_vector(&self, key: &str) -> Result<Vec3<f64>> {
+        self.vector(key)?.ok_or_else(|| self.missing(key))
+    }
+
+    fn required_point(&self, key: &str) -> Result<Point<f64>> {
+        self.required_vector(key).map(Point::from)
+    }
+
+    fn required_color(&self, key: &str) -> Result<Color> {
+        self.required_vector(key)
+            .map(|v| Color::new(v.x(), v.y(), v.z()))
+    }
+
+    fn required_name(&self, key: &str) -> Result<(&str, Position)> {
+        self.name(key)?.ok_or_else(|| self.missing(key))
+    }
+
+    /// Reads the optional `scale`, `axis`/`angle` and `translate` properties, applied in that
+    /// order. `scale` is either a single factor or one per axis, and `axis` defaults to up (y).
+    fn transform(&self) -> Result<Transform> {
+        self.transform_from(|key| key.to_string())
+    }
+
+    /// Reads where a moving instance ends up from the `end_scale`, `end_axis`, `end_angle` and
+    /// `end_translate` properties, each defaulting to its starting counterpart. `None` when none
+    /// of them are given.
+    fn end_transform(&self) -> Result<Option<Transform>> {
+        let end_key = |key: &str| format!("end_{}", key);
+        if !TRANSFORM_KEYS
+            .iter()
+            .any(|key| self.props.contains_key(&end_key(key)))
+        {
+            return Ok(None);
+        }
+
+        self.transform_from(|key| {
+            let end = end_key(key);
+            if self.props.contains_key(&end) {
+                end
+            } else {
+                key.to_string()
+            }
+        })
+        .map(Some)
+    }
+
+    /// Reads a transform from the properties `key` names for each of `TRANSFORM_KEYS`
+    fn transform_from(&self, key: impl Fn(&str) -> String) -> Result<Transform> {
+        let [scale, axis, angle, translate] = TRANSFORM_KEYS.map(key);
+        let mut transform = Transform::identity();
+
+        match self.props.get(&scale) {
+            None => {}
+            Some((value, pos)) => {
+                let factors = match value {
+                    Value::Number(s) => Vec3::new(*s, *s, *s),
+                    Value::Vector(v) => *v,
+                    value => {
+                        return Err(Statement::mismatch(
+                            &scale,
+                            "a number or a vector",
+                            value,
+                            *pos,
+                        ))
+                    }
+                };
+
+                if factors.x() == 0.0 || factors.y() == 0.0 || factors.z() == 0.0 {
+                    return Err(SceneError::at(
+                        *pos,
+                        format!("`{}` must not be zero", scale),
+                    ));
+                }
+                transform = transform.then(&Transform::scale(factors));
+            }
+        }
+
+        match (self.vector(&axis)?, self.number(&angle)?) {
+            (Some(_), None) => return Err(self.missing(&angle)),
+            (axis_value, Some(angle)) => {
+                let axis_value = axis_value.unwrap_or_else(|| Vec3::new(0.0, 1.0, 0.0));
+                if axis_value.near_zero() {
+                    let (_, pos) = self.props[&axis];
+                    return Err(SceneError::at(pos, format!("`{}` must not be zero", axis)));
+                }
+                transform = transform.then(&Transform::rotate(axis_value, angle));
+            }
+            (None, None) => {}
+        }
+
+        if let Some(offset) = self.vector(&translate)? {
+            transform = transform.then(&Transform::translate(offset));
+        }
+
+        Ok(transform)
+    }
+}
+
+const TRANSFORM_KEYS: [&str; 4] = ["scale", "axis", "angle", "translate"];
+
+/// Time at which moving things reach their `end_` properties, having started at time 0
+const MOTION_END: f64 = 1.0;
+
+struct CameraSpec {
+    look_from: Point<f64>,
+    look_at: Point<f64>,
+    up: Vec3<f64>,
+    vfov: f64,
+    aperture: f64,
+    focus_dist: Option<f64>,
+    shutter: Range<f64>,
+}
+
+impl Default for CameraSpec {
+    fn default() -> Self {
+        Self {
+            look_from: Point::new(0.0, 0.0, 0.0),
+            look_at: Point::new(0.0, 0.0, -1.0),
+            up: Vec3::new(0.0, 1.0, 0.0),
+            vfov: 90.0,
+            aperture: 0.0,
+            focus_dist: None,
+            shutter: 0.0..0.0,
+        }
+    }
+}
+
+/// Accumulates directives into a scene, deferring the camera until the aspect ratio is known
+struct Builder<'a> {
+    base_dir: &'a Path,
+    scene: Scene,
+    settings: RenderSettings,
+    camera: CameraSpec,
+    materials: HashMap<String, Arc<dyn Material>>,
+    meshes: HashMap<String, Arc<Mesh>>,
+}
+
+impl<'a> Builder<'a> {
+    fn new(base_dir: &'a Path, scene: Scene) -> Self {
+        Self {
+            base_dir,
+            scene,
+            settings: RenderSettings::default(),
+            camera: CameraSpec::default(),
+            materials: HashMap::new(),
+            meshes: HashMap::new(),
+        }
+    }
+
+    fn material(&self, statement: &Statement) -> Result<Arc<dyn Material>> {
+        let (name, pos) = statement.required_name("material")?;
+
+        self.materials
+            .get(name)
+            .map(Arc::clone)
+            .ok_or_else(|| SceneError::at(pos, format!("unknown material `{}`", name)))
+    }
+
+    /// Like `material`, for statements where the material can be left out
+    fn optional_material(&self, statement: &Statement) -> Result<Option<Arc<dyn Material>>> {
+        match statement.name("material")? {
+            Some(_) => self.material(statement).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Resolves the statement's `path` against the scene's directory, checking the file exists
+    fn object_path(&self, statement: &Statement) -> Result<PathBuf> {
+        let (path, pos) = statement.required_name("path")?;
+        let path = self.base_dir.join(path);
+
+        if !path.is_file() {
+            return Err(SceneError::at(
+                pos,
+                format!("object file `{}` does not exist", path.display()),
+            ));
+        }
+
+        Ok(path)
+    }
+
+    fn apply(&mut self, st: Statement) -> Result<()> {
+        match st.name.as_str() {
+            "render" => {
+                st.expect(0, &["width", "height", "samples", "max_depth"])?;
+
+                // pixels are placed on the image plane by dividing by `width - 1` and `height - 1`
+                let settings = &mut self.settings;
+                if let Some(width) = st.count_at_least("width", 2)? {
+                    settings.width = width as usize;
+                }
+                if let Some(height) = st.count_at_least("height", 2)? {
+                    settings.height = height as usize;
+                }
+                if let Some(samples) = st.count_at_least("samples", 1)? {
+                    settings.samples_per_pixel = samples;
+                }
+                if let Some(max_depth) = st.count("max_depth")? {
+                    settings.max_depth = max_depth;
+                }
+            }
+            "camera" => {
+                st.expect(
+                    0,
+                    &[
+                        "from",
+                        "at",
+                        "up",
+                        "fov",
+                        "aperture",
+                        "focus",
+                        "shutter_open",
+                        "shutter_close",
+                    ],
+                )?;
+
+                let camera = &mut self.camera;
+                if let Some(from) = st.vector("from")? {
+                    camera.look_from = from.into();
+                }
+                if let Some(at) = st.vector("at")? {
+                    camera.look_at = at.into();
+                }
+                if let Some(up) = st.vector("up")? {
+                    camera.up = up;
+                }
+                if let Some(fov) = st.number("fov")? {
+                    camera.vfov = fov;
+                }
+                if let Some(aperture) = st.number("aperture")? {
+                    camera.aperture = aperture;
+                }
+                camera.focus_dist = st.number("focus")?;
+
+                let open = st.number("shutter_open")?.unwrap_or(0.0);
+                let close = st.number("shutter_close")?.unwrap_or(open);
+                if close < open {
+                    let (_, pos) = st.props["shutter_close"];
+                    return Err(SceneError::at(
+                        pos,
+                        "`shutter_close` must not be before `shutter_open`",
+                    ));
+                }
+                camera.shutter = open..close;
+            }
+            "sky" => {
+                st.expect(1, &["color"])?;
+
+                match st.arg(0) {
+                    ("day", _) => self.scene.sky(Day::new()),
+                    ("uniform", _) => self.scene.sky(Uniform::new(st.required_color("color")?)),
+                    (kind, pos) => {
+                        return Err(SceneError::at(pos, format!("unknown sky `{}`", kind)))
+                    }
+                }
+            }
+            "material" => {
+                st.expect(2, &["color", "fuzz", "ior"])?;
+
+                let (name, name_pos) = st.arg(0);
+                let material = match st.arg(1) {
+                    ("diffuse", _) => self.scene.diffuse_material(st.required_color("color")?),
+                    ("metal", _) => self.scene.metal_material(
+                        st.required_color("color")?,
+                        st.number("fuzz")?.unwrap_or(0.0),
+                    ),
+                    ("dielectric", _) => {
+                        self.scene.dielectric_material(st.required_positive("ior")?)
+                    }
+                    ("light", _) => self.scene.light_material(st.required_color("color")?),
+                    (kind, pos) => {
+                        return Err(SceneError::at(
+                            pos,
+                            format!("unknown material type `{}`", kind),
+                        ))
+                    }
+                };
+
+                if self.materials.contains_key(name) {
+                    return Err(SceneError::at(
+                        name_pos,
+                        format!("material `{}` is already defined", name),
+                    ));
+                }
+                self.materials.insert(name.to_string(), material);
+            }
+            "sphere" => {
+                st.expect(0, &["center", "end_center", "radius", "material"])?;
+
+                let material = self.material(&st)?;
+                let center = st.required_point("center")?;
+                let radius = st.required_positive("radius")?;
+                match st.vector("end_center")? {
+                    Some(end) => self.scene.moving_sphere(
+                        center,
+                        end.into(),
+                        0.0..MOTION_END,
+                        radius,
+                        &material,
+                    ),
+                    None => self.scene.sphere(center, radius, &material),
+                }
+            }
+            "plane" => {
+                st.expect(0, &["origin", "normal", "material"])?;
+
+                let material = self.material(&st)?;
+                let normal = st.required_vector("normal")?;
+                if normal.near_zero() {
+                    let (_, pos) = st.props["normal"];
+                    return Err(SceneError::at(pos, "`normal` must not be zero"));
+                }
+                self.scene
+                    .plane(st.required_point("origin")?, normal, &material);
+            }
+            "triangle" => {
+                st.expect(0, &["a", "b", "c", "material"])?;
+
+                let material = self.material(&st)?;
+                let [a, b, c] = ["a", "b", "c"].map(|key| st.required_point(key));
+                let (a, b, c) = (a?, b?, c?);
+
+                // the normal comes from the cross product of the edges, which is zero for these
+                let (ab, ac) = (Vec3::from(b - a), Vec3::from(c - a));
+                if ab.cross(ac).len() <= 1e-12 * ab.len() * ac.len() {
+                    return Err(SceneError::at(
+                        st.pos,
+                        "`triangle` corners must not lie on one line",
+                    ));
+                }
+                self.scene.triangle(a, b, c, &material);
+            }
+            "object" => {
+                st.expect(
+                    0,
+                    &["path", "material", "scale", "axis", "angle", "translate"],
+                )?;
+
+                let material = self.optional_material(&st)?;
+                let path = self.object_path(&st)?;
+                let transform = st.transform()?;
+                let report = self
+                    .scene
+                    .object(&path, material.as_ref(), &transform)
+                    .map_err(|err| st.load_error(path.clone(), err))?;
+                self.warn_left_out(&st, &path, report.dropped_faces, &report.warnings);
+                self.warn_cache(&st, &path, report.cache);
+            }
+            "mesh" => {
+                st.expect(1, &["path", "material"])?;
+
+                let (name, name_pos) = st.arg(0);
+                let material = self.optional_material(&st)?;
+                let path = self.object_path(&st)?;
+
+                if self.meshes.contains_key(name) {
+                    return Err(SceneError::at(
+                        name_pos,
+                        format!("mesh `{}` is already defined", name),
+                    ));
+                }
+                let (mesh, cache) = self
+                    .scene
+                    .mesh(&path, material.as_ref())
+                    .map_err(|err| st.load_error(path.clone(), err))?;
+                self.warn_left_out(&st, &path, mesh.dropped_faces(), mesh.warnings());
+                self.warn_cache(&st, &path, cache);
+                self.meshes.insert(name.to_string(), mesh);
+            }
+            "instance" => {
+                st.expect(
+                    1,
+                    &[
+                        "material",
+                        "scale",
+                        "axis",
+                        "angle",
+                        "translate",
+                        "end_scale",
+                        "end_axis",
+                        "end_angle",
+                        "end_translate",
+                    ],
+                )?;
+
+                let (name, name_pos) = st.arg(0);
+                let mesh = self
+                    .meshes
+                    .get(name)
+                    .ok_or_else(|| SceneError::at(name_pos, format!("unknown mesh `{}`", name)))?;
+                let material = self.optional_material(&st)?;
+
+                let start = st.transform()?;
+                let end = st.end_transform()?;
+
+                // a negative scale only on one end would have to pass through a flat instance
+                if let Some(end) = &end {
+                    if start.determinant() * end.determinant() < 0.0 {
+                        let (_, pos) = st.props["end_scale"];
+                        return Err(SceneError::at(
+                            pos,
+                            "`end_scale` must mirror the same as `scale`, or neither must",
+                        ));
+                    }
+                }
+
+                // lights are sampled uniformly over their area, which only a uniform scale keeps
+                // uniform
+                if material.as_ref().is_some_and(|m| m.is_emissive()) {
+                    let end_scale = if st.props.contains_key("end_scale") {
+                        "end_scale"
+                    } else {
+                        "scale"
+                    };
+
+                    for (transform, key) in [(Some(&start), "scale"), (end.as_ref(), end_scale)] {
+                        if transform.is_some_and(|t| t.area_scale().is_none()) {
+                            let (_, pos) = st.props[key];
+                            return Err(SceneError::at(
+                                pos,
+                                format!("`{}` of a light must be the same along every axis", key),
+                            ));
+                        }
+                    }
+                }
+                match end {
+                    Some(end) => self.scene.moving_instance(
+                        mesh,
+                        start,
+                        end,
+                        0.0..MOTION_END,
+                        material.as_ref(),
+                    ),
+                    None => self.scene.instance(mesh, start, material.as_ref()),
+                }
+            }
+            name => {
+                return Err(SceneError::at(
+                    st.pos,
+                    format!("unknown directive `{}`", name),
+                ))
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Keeps what was left out of the file given by the statement's `path` as scene warnings
+    fn warn_left_out(
+        &mut self,
+        statement: &Statement,
+        path: &Path,
+        dropped_faces: usize,
+        warnings: &[String],
+    ) {
+        if dropped_faces > 0 {
+            let message = format!("dropped {} faces without area", dropped_faces);
+            self.scene
+                .warnings
+                .push(statement.load_warning(path, message));
+        }
+        for warning in warnings {
+            self.scene
+                .warnings
+                .push(statement.load_warning(path, warning));
+        }
+    }
+
+    /// Warns when the file given by the statement's `path` couldn't be cached, which only slows
+    /// down the next render. An entry that had to be rebuilt isn't worth a warning.
+    fn warn_cache(&mut self, statement: &Statement, path: &Path, cache: Option<CacheStatus>) {
+        if let Some(CacheStatus::Unsaved { entry, error }) = cache {
+            let message = format!("couldn't save mesh cache {}: {}", entry.display(), error);
+            self.scene
+                .warnings
+                .push(statement.load_warning(path, message));
+        }
+    }
+
+    fn finish(mut self) -> (Scene, RenderSettings) {
+        let camera = self.camera;
+        let focus_dist = camera
+            .focus_dist
+            .unwrap_or_else(|| Vec3::from(camera.look_at - camera.look_from).len());
+
+        self.scene.camera(
+            camera.look_from,
+            camera.look_at,
+            camera.up,
+            camera.vfov,
+            self.settings.aspect_ratio(),
+            camera.aperture,
+            focus_dist,
+        );
+        self.scene.shutter(camera.shutter.start, camera.shutter.end);
+
+        (self.scene, self.settings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::ScratchDir;
+
+    use super::*;
+
+    fn parse(source: &str) -> Result<(Scene, RenderSettings)> {
+        Scene::parse(source, Path::new(""))
+    }
+
+    fn error_position(source: &str) -> (usize, usize) {
+        match parse(source) {
+            Err(SceneError::Parse { line, column, .. }) => (line, column),
+            Err(err) => panic!("unexpected error {}", err),
+            Ok(_) => panic!("expected a parse error"),
+        }
+    }
+
+    #[test]
+    fn tokenize_line() {
+        let tokens: Vec<_> = tokenize("sphere center=(1, -2.5, 3) # comment", 1)
+            .unwrap()
+            .into_iter()
+            .map(|(token, _)| token)
+            .collect();
+
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Word("sphere".into()),
+                Token::Word("center".into()),
+                Token::Equals,
+                Token::LeftParen,
+                Token::Number(1.0),
+                Token::Comma,
+                Token::Number(-2.5),
+                Token::Comma,
+                Token::Number(3.0),
+                Token::RightParen,
+            ]
+        );
+    }
+
+    #[test]
+    fn full_scene() {
+        let source = "
+            # a small scene
+            render width=200 height=100 samples=8 max_depth=4
+            camera from=(0, 1, 5) at=(0, 0, 0) fov=40 aperture=0.1
+            sky uniform color=(0.5, 0.5, 0.5)
+
+            material red diffuse color=(1, 0, 0)
+            material mirror metal color=(1, 1, 1) fuzz=0.2
+            material glass dielectric ior=1.5
+            material lamp light color=(4, 4, 4)
+
+            sphere center=(0, 0, 0) radius=1 material=red
+            sphere center=(2, 0, 0) radius=0.5 material=glass
+            sphere center=(0, 5, 0) radius=1 material=lamp
+            triangle a=(0, 0, 0) b=(1, 0, 0) c=(0, 1, 0) material=mirror
+            plane origin=(0, -1, 0) normal=(0, 1, 0) material=red
+        ";
+
+        let (_, settings) = parse(source).unwrap();
+
+        assert_eq!(
+            settings,
+            RenderSettings {
+                width: 200,
+                height: 100,
+                samples_per_pixel: 8,
+                max_depth: 4,
+            }
+        );
+    }
+
+    #[test]
+    fn unknown_material_position() {
+        let source =
+            "material red diffuse color=(1, 0, 0)\nsphere center=(0, 0, 0) radius=1 material=blue";
+
+        assert_eq!(error_position(source), (2, 34));
+    }
+
+    #[test]
+    fn unknown_directive_position() {
+        assert_eq!(error_position("\n\n   cube size=1"), (3, 4));
+    }
+
+    #[test]
+    fn render_settings_out_of_range() {
+        assert_eq!(error_position("render width=0"), (1, 8));
+        assert_eq!(error_position("render width=1"), (1, 8));
+        assert_eq!(error_position("render width=2 height=0"), (1, 16));
+        assert_eq!(error_position("render height=1"), (1, 8));
+        assert_eq!(error_position("render samples=0"), (1, 8));
+        assert!(parse("render width=2 height=2 samples=1 max_depth=0").is_ok());
+
+        // would wrap around when stored as a u32
+        assert_eq!(error_position("render samples=4294967297"), (1, 8));
+        assert_eq!(error_position("render max_depth=1e20"), (1, 8));
+    }
+
+    #[test]
+    fn degenerate_geometry() {
+        let error_position = |statement: &str| {
+            error_position(&format!(
+                "material m diffuse color=(1, 1, 1)\n{}",
+                statement
+            ))
+        };
+
+        assert_eq!(
+            error_position("sphere center=(0, 0, 0) radius=0 material=m"),
+            (2, 25)
+        );
+        assert_eq!(
+            error_position("sphere center=(0, 0, 0) radius=-1 material=m"),
+            (2, 25)
+        );
+        assert_eq!(
+            error_position("plane origin=(0, 0, 0) normal=(0, 0, 0) material=m"),
+            (2, 24)
+        );
+        assert_eq!(
+            error_position("triangle a=(0, 0, 0) b=(1, 1, 1) c=(2, 2, 2) material=m"),
+            (2, 1)
+        );
+        assert_eq!(
+            error_position("triangle a=(0, 0, 0) b=(0, 0, 0) c=(1, 0, 0) material=m"),
+            (2, 1)
+        );
+        assert_eq!(error_position("material glass dielectric ior=0"), (2, 27));
+        assert_eq!(
+            error_position("material glass dielectric ior=-1.5"),
+            (2, 27)
+        );
+
+        // small but not flat
+        let source = "material m diffuse color=(1, 1, 1)\n\
+                      triangle a=(0, 0, 0) b=(1e-6, 0, 0) c=(0, 1e-6, 0) material=m";
+        assert!(parse(source).is_ok());
+    }
+
+    #[test]
+    fn wrong_value_type() {
+        assert_eq!(
+            error_position(
+                "material x diffuse color=(1, 1, 1)\nsphere center=1 radius=1 material=x"
+            ),
+            (2, 8)
+        );
+        assert_eq!(error_position("render width=2.5"), (1, 8));
+    }
+
+    #[test]
+    fn malformed_vector() {
+        assert_eq!(error_position("camera from=(1, 2)"), (1, 13));
+        assert_eq!(error_position("camera from=(1, 2 3)"), (1, 19));
+    }
+
+    #[test]
+    fn unknown_mesh_position() {
+        assert_eq!(error_position("instance tree translate=(1, 0, 0)"), (1, 10));
+    }
+
+    #[test]
+    fn invalid_transform() {
+        let golden = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+        let error_position = |instance: &str| {
+            let source = format!(
+                "material m diffuse color=(1, 1, 1)\nmesh ico path=\"icosahedron.obj\" material=m\n{}",
+                instance
+            );
+
+            match Scene::parse(&source, &golden) {
+                Err(SceneError::Parse { line, column, .. }) => (line, column),
+                Err(err) => panic!("unexpected error {}", err),
+                Ok(_) => panic!("expected a parse error"),
+            }
+        };
+
+        assert_eq!(error_position("instance ico scale=(1, 0, 1)"), (3, 14));
+        assert_eq!(error_position("instance ico scale=big"), (3, 14));
+        assert_eq!(error_position("instance ico axis=(0, 1, 0)"), (3, 1));
+        assert_eq!(
+            error_position("instance ico axis=(0, 0, 0) angle=10"),
+            (3, 14)
+        );
+        assert_eq!(
+            error_position("instance ico scale=2 end_scale=(0, 1, 1)"),
+            (3, 22)
+        );
+        assert_eq!(error_position("instance ico end_axis=(1, 0, 0)"), (3, 1));
+        assert_eq!(
+            error_position("instance ico scale=2 end_scale=(-1, 1, 1)"),
+            (3, 22)
+        );
+        // mirrored at both ends
+        let source = "mesh ico path=\"icosahedron.obj\"\n\
+                      instance ico scale=(1, -1, 1) end_scale=(-2, -1, -3)";
+        assert!(Scene::parse(source, &golden).is_ok());
+    }
+
+    #[test]
+    fn stretched_lights() {
+        let golden = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+        let parse = |instance: &str| {
+            let source = format!(
+                "material lamp light color=(4, 4, 4)\nmesh ico path=\"icosahedron.obj\"\n{}",
+                instance
+            );
+            Scene::parse(&source, &golden)
+        };
+        let error_position = |instance: &str| match parse(instance) {
+            Err(SceneError::Parse { line, column, .. }) => (line, column),
+            Err(err) => panic!("unexpected error {}", err),
+            Ok(_) => panic!("expected a parse error"),
+        };
+
+        assert_eq!(
+            error_position("instance ico material=lamp scale=(1, 2, 1)"),
+            (3, 28)
+        );
+        assert_eq!(
+            error_position("instance ico material=lamp scale=2 end_scale=(2, 2, 1)"),
+            (3, 36)
+        );
+        assert_eq!(
+            error_position("instance ico material=lamp scale=(1, 2, 1) end_scale=1"),
+            (3, 28)
+        );
+
+        // lights scaled the same along every axis, and anything else scaled any way
+        let (scene, _) = parse("instance ico material=lamp scale=(-2, 2, 2) angle=30").unwrap();
+        assert_eq!(scene.light_ids, vec![0]);
+        assert!(parse("instance ico scale=(1, 2, 1)").is_ok());
+    }
+
+    #[test]
+    fn motion_blur() {
+        let source = "
+            camera shutter_open=0.25 shutter_close=0.75
+            material m diffuse color=(1, 1, 1)
+            sphere center=(0, 0, -2) end_center=(1, 0, -2) radius=0.5 material=m
+        ";
+        let (scene, _) = parse(source).unwrap();
+
+        assert_eq!(scene.camera.shutter(), 0.25..0.75);
+        assert_eq!(scene.primitives().len(), 1);
+
+        assert_eq!(
+            error_position("camera shutter_open=1 shutter_close=0.5"),
+            (1, 23)
+        );
+    }
+
+    #[test]
+    fn broken_object_file() {
+        let dir = ScratchDir::new("broken-obj");
+        fs::write(dir.join("broken.obj"), "v 0 0 0\nf 1 2 3\n").unwrap();
+
+        let source = "material m diffuse color=(1, 1, 1)\nobject path=\"broken.obj\" material=m";
+        match Scene::parse(source, &dir) {
+            Err(SceneError::Load {
+                line: 2,
+                column: 8,
+                error: LoadError::IndexOutOfRange { line: 2, .. },
+                ..
+            }) => {}
+            Err(err) => panic!("unexpected error {}", err),
+            Ok(_) => panic!("expected a load error"),
+        }
+    }
+
+    #[test]
+    fn dropped_faces_are_warnings() {
+        let dir = ScratchDir::new("dropped-faces");
+        fs::write(
+            dir.join("flat.obj"),
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nv 2 0 0\nf 1 2 3\nf 1 2 4\n",
+        )
+        .unwrap();
+
+        let source = "
+            material m diffuse color=(1, 1, 1)
+            object path=\"flat.obj\" material=m
+            mesh flat path=\"flat.obj\" material=m
+        ";
+        let (scene, _) = Scene::parse(source, &dir).unwrap();
+
+        let path = dir.join("flat.obj");
+        assert_eq!(
+            scene.warnings(),
+            [
+                format!("3:20: {}: dropped 1 faces without area", path.display()),
+                format!("4:23: {}: dropped 1 faces without area", path.display()),
+            ]
+        );
+    }
+
+    #[test]
+    fn unsaved_cache_is_a_warning() {
+        let dir = ScratchDir::new("unsaved-scene-cache");
+        fs::write(dir.join("tri.obj"), "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n").unwrap();
+        let source = "mesh tri path=\"tri.obj\"";
+
+        let mut scene = Scene::new();
+        scene.mesh_cache(dir.join("cache"));
+        let (scene, _) = scene.parse_into(source, &dir).unwrap();
+        assert!(scene.warnings().is_empty());
+
+        fs::remove_dir_all(dir.join("cache")).unwrap();
+        fs::write(dir.join("cache"), "").unwrap();
+        let mut scene = Scene::new();
+        scene.mesh_cache(dir.join("cache"));
+        let (scene, _) = scene.parse_into(source, &dir).unwrap();
+
+        let prefix = format!(
+            "1:10: {}: couldn't save mesh cache ",
+            dir.join("tri.obj").display()
+        );
+        assert_eq!(scene.warnings().len(), 1);
+        assert!(scene.warnings()[0].starts_with(&prefix));
+    }
+
+    #[test]
+    fn object_materials_from_library() {
+        let dir = ScratchDir::new("obj-mtl");
+        fs::write(
+            dir.join("lamp.obj"),
+            "mtllib lamp.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl glow\nf 1 2 3\n",
+        )
+        .unwrap();
+        fs::write(dir.join("lamp.mtl"), "newmtl glow\nKe 4 4 4\n").unwrap();
+
+        let source = "
+            material m diffuse color=(1, 1, 1)
+            object path=\"lamp.obj\"
+            object path=\"lamp.obj\" material=m
+            mesh lamp path=\"lamp.obj\"
+        ";
+        let (scene, _) = Scene::parse(source, &dir).unwrap();
+
+        // only the object drawn with the library's emissive material is a light
+        assert_eq!(scene.primitives().len(), 2);
+        assert_eq!(scene.light_ids, vec![0]);
+        assert!(scene.warnings().is_empty());
+
+        // materials the libraries don't define are warnings, only where they are looked up
+        fs::write(dir.join("lamp.mtl"), "newmtl dim\nKe 1 1 1\n").unwrap();
+        let (scene, _) = Scene::parse(source, &dir).unwrap();
+        let path = dir.join("lamp.obj");
+        assert_eq!(
+            scene.warnings(),
+            [
+                format!(
+                    "3:20: {}: material `glow` isn't defined by any material library",
+                    path.display()
+                ),
+                format!(
+                    "5:23: {}: material `glow` isn't defined by any material library",
+                    path.display()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_property() {
+        assert_eq!(error_position("material red diffuse"), (1, 1));
+    }
+}
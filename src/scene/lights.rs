@@ -0,0 +1,162 @@
+use std::sync::Arc;
+
+use crate::{
+    geometry::{Point, Vec3},
+    object::bvh::Primitive,
+    sampler::Sampler,
+};
+
+/// Emissive primitives that can be sampled directly for next-event estimation.
+///
+/// A light is chosen with probability proportional to its area and then sampled uniformly over
+/// its surface, so every point on every light has the same area density `1 / total_area`.
+/// This lets the pdf of a direction be computed from any hit on a light without knowing which
+/// primitive was hit, and is why only primitives that `samples_uniformly` can be lights.
+pub struct LightList {
+    lights: Vec<Arc<dyn Primitive>>,
+    cumulative_area: Vec<f64>,
+}
+
+pub struct LightSample<'a> {
+    pub point: Point<f64>,
+    pub normal: Vec3<f64>,
+    /// The light the point was sampled on
+    pub light: &'a dyn Primitive,
+}
+
+impl Default for LightList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LightList {
+    pub fn new() -> Self {
+        Self {
+            lights: Vec::new(),
+            cumulative_area: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, light: Arc<dyn Primitive>) {
+        debug_assert!(
+            light.samples_uniformly(),
+            "light can't be sampled uniformly"
+        );
+        let area = self.total_area() + light.surface_area();
+
+        self.lights.push(light);
+        self.cumulative_area.push(area);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+
+    pub fn total_area(&self) -> f64 {
+        self.cumulative_area.last().copied().unwrap_or(0.0)
+    }
+
+    /// Picks a point on one of the lights as they are at `time`
+    pub fn sample(&self, time: f64, sampler: &mut Sampler) -> Option<LightSample<'_>> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let target = sampler.next_f64() * self.total_area();
+        let idx = self
+            .cumulative_area
+            .partition_point(|&area| area <= target)
+            .min(self.lights.len() - 1);
+
+        let light = self.lights[idx].as_ref();
+        let (point, normal) = light.sample_surface(time, sampler);
+
+        Some(LightSample {
+            point,
+            normal,
+            light,
+        })
+    }
+
+    /// Solid-angle density of sampling `point` (with surface normal `normal`) as seen from `origin`
+    pub fn pdf(&self, origin: Point<f64>, point: Point<f64>, normal: Vec3<f64>) -> f64 {
+        let total_area = self.total_area();
+        if total_area == 0.0 {
+            return 0.0;
+        }
+
+        let to_light = Vec3::from(point - origin);
+        let dist_sq = to_light.len_sq();
+        let cosine = normal.unit().dot(to_light / dist_sq.sqrt()).abs();
+
+        if cosine < 1e-8 {
+            return 0.0;
+        }
+
+        dist_sq / (cosine * total_area)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{color::Color, material::Lambertian, object::Tri};
+
+    use super::*;
+
+    fn unit_square_light(height: f64) -> LightList {
+        let mat = Arc::new(Lambertian::new(Color::white()));
+        let a = Point::new(0.0, height, 0.0);
+        let b = Point::new(1.0, height, 0.0);
+        let c = Point::new(1.0, height, 1.0);
+        let d = Point::new(0.0, height, 1.0);
+
+        let mut lights = LightList::new();
+        lights.add(Arc::new(Tri::new(a, b, c, mat.clone())));
+        lights.add(Arc::new(Tri::new(a, c, d, mat)));
+
+        lights
+    }
+
+    #[test]
+    fn total_area() {
+        let lights = unit_square_light(1.0);
+
+        assert!((lights.total_area() - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn samples_lie_on_lights() {
+        let lights = unit_square_light(2.0);
+        let mut sampler = Sampler::new(0);
+
+        for _ in 0..100 {
+            let sample = lights.sample(0.0, &mut sampler).unwrap();
+
+            assert_eq!(sample.point.y(), 2.0);
+            assert!((0.0..=1.0).contains(&sample.point.x()));
+            assert!((0.0..=1.0).contains(&sample.point.z()));
+            assert!((sample.normal.y().abs() - 1.0).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn pdf_directly_below() {
+        let lights = unit_square_light(2.0);
+        let pdf = lights.pdf(
+            Point::new(0.5, 0.0, 0.5),
+            Point::new(0.5, 2.0, 0.5),
+            Vec3::new(0.0, -1.0, 0.0),
+        );
+
+        assert!((pdf - 4.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn empty_list() {
+        let lights = LightList::new();
+
+        assert!(lights.sample(0.0, &mut Sampler::new(0)).is_none());
+        assert_eq!(lights.total_area(), 0.0);
+    }
+}
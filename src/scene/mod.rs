@@ -0,0 +1,371 @@
+use std::{
+    ops::Range,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    camera::Camera,
+    color::Color,
+    geometry::{AnimatedTransform, Point, Transform, Vec3},
+    material::{Dielectric, DiffuseLight, Lambertian, Material, Metal},
+    object::{
+        bvh::{BvhConfig, BvhTree, CacheStatus, MeshCache, Primitive},
+        InfinitePlane, Instance, LoadError, Mesh, MovingSphere, Object, Sphere, Tri, VisibleList,
+    },
+    sky::{Sky, Uniform},
+};
+
+mod file;
+mod lights;
+mod render;
+mod view;
+
+pub use file::*;
+pub use lights::*;
+pub use view::*;
+
+/*
+Should have:
+- a global material register
+- an adaptable representation of a scene
+- a way to render a scene with parameters
+*/
+
+/*
+let scene = Scene::new();
+let diffuse = scene.diffuse_material(Color::new(1.0, 0.0, 0.0));
+let sphere = scene.sphere(Point::new(0.0, 0.0, -1.0), 0.5, diffuse);
+
+*/
+
+/// Minimum distance along a ray for a hit to count, preventing surfaces from shadowing themselves
+pub const HIT_TOLERANCE: f64 = 0.0001;
+
+pub struct Scene {
+    primitives: Vec<Arc<dyn Primitive>>,
+    objects: VisibleList,
+    lights: LightList,
+    /// Indices into `primitives` of the primitives in `lights`, in increasing order
+    light_ids: Vec<usize>,
+    camera: Camera,
+    sky: Box<dyn Sky>,
+    show_progress: bool,
+    show_bvh_stats: bool,
+    bvh_config: BvhConfig,
+    /// The BVH from the last render, refit rather than rebuilt by the next one when possible
+    bvh_cache: Mutex<Option<BvhTree>>,
+    /// Where OBJ files are cached along with their BVH, if anywhere
+    mesh_cache: Option<MeshCache>,
+    seed: u64,
+    /// Problems found while loading a scene file that didn't stop it from loading
+    warnings: Vec<String>,
+}
+
+type PrimArc = Arc<dyn Primitive>;
+
+/// What `Scene::object` left out of an OBJ file or drew differently, for the caller to report
+#[derive(Debug, Default)]
+pub struct ObjectReport {
+    /// See `Object::dropped_faces`
+    pub dropped_faces: usize,
+    /// See `Object::warnings`
+    pub warnings: Vec<String>,
+    /// How the file was loaded from the mesh cache, `None` without one
+    pub cache: Option<CacheStatus>,
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Scene {
+    pub fn new() -> Self {
+        Self {
+            objects: VisibleList::new(),
+            primitives: Vec::new(),
+            lights: LightList::new(),
+            light_ids: Vec::new(),
+            camera: Camera::default(),
+            sky: Box::new(Uniform::new(Color::white())),
+            show_progress: true,
+            show_bvh_stats: false,
+            bvh_config: BvhConfig::default(),
+            bvh_cache: Mutex::new(None),
+            mesh_cache: None,
+            seed: 0,
+            warnings: Vec::new(),
+        }
+    }
+
+    // pub fn add(&mut self, object: impl Visible + 'static) {
+    //     self.objects.add(Box::new(object));
+    // }
+
+    /// Loads an OBJ file, placing it in the scene by `transform`. It is drawn with `material`
+    /// when given, and otherwise with the materials from its MTL libraries, of which the
+    /// emissive ones are sampled as lights. The triangles are moved once when loading, so this
+    /// costs nothing while rendering. Nothing is added when the file can't be loaded.
+    ///
+    /// Returns what had to be left out of the file, such as faces without area.
+    pub fn object(
+        &mut self,
+        path: impl Into<PathBuf>,
+        material: Option<&Arc<dyn Material>>,
+        transform: &Transform,
+    ) -> Result<ObjectReport, LoadError> {
+        let path = path.into();
+        let (mut obj, cache) = match &self.mesh_cache {
+            Some(cache) => {
+                let (obj, status) = cache.object(&path, material, &self.bvh_config)?;
+                (obj, Some(status))
+            }
+            None => (Object::new(&path, material.map(Arc::clone))?, None),
+        };
+        let report = ObjectReport {
+            dropped_faces: obj.dropped_faces(),
+            warnings: obj.warnings().to_vec(),
+            cache,
+        };
+        obj.transform(transform);
+
+        let lights: Vec<_> = obj
+            .triangles()
+            .iter()
+            .map(|tri| tri.material().is_emissive())
+            .collect();
+        for (prim, is_light) in obj.to_primitives().into_iter().zip(lights) {
+            self.add_primitive(prim, is_light);
+        }
+
+        Ok(report)
+    }
+
+    /// Loads an OBJ file into a mesh with its own BVH, to be placed any number of times with
+    /// `instance`. Nothing is added to the scene until then. Like `object`, the mesh is drawn
+    /// with `material` when given and with the materials from its MTL libraries otherwise.
+    ///
+    /// Also returns how the mesh was loaded from the mesh cache, if there is one.
+    pub fn mesh(
+        &mut self,
+        path: impl Into<PathBuf>,
+        material: Option<&Arc<dyn Material>>,
+    ) -> Result<(Arc<Mesh>, Option<CacheStatus>), LoadError> {
+        let path = path.into();
+        let (mesh, cache) = match &self.mesh_cache {
+            Some(cache) => {
+                let (mesh, status) = cache.mesh(&path, material, &self.bvh_config)?;
+                (mesh, Some(status))
+            }
+            None => {
+                let object = Object::new(&path, material.map(Arc::clone))?;
+                (Mesh::from_object(object, self.bvh_config.clone()), None)
+            }
+        };
+
+        Ok((Arc::new(mesh), cache))
+    }
+
+    /// Places a copy of `mesh`, moved into the scene by `transform` and drawn with `material`
+    /// instead of its own materials when given. Only instances with an emissive `material`
+    /// are sampled as lights, and only when `transform` scales uniformly. Other emissive
+    /// instances still light the scene, but only where scattered rays happen to hit them.
+    pub fn instance(
+        &mut self,
+        mesh: &Arc<Mesh>,
+        transform: Transform,
+        material: Option<&Arc<dyn Material>>,
+    ) {
+        let is_light = material.is_some_and(|material| material.is_emissive());
+        let instance: PrimArc = Arc::new(Instance::new(
+            Arc::clone(mesh),
+            transform,
+            material.map(Arc::clone),
+        ));
+
+        self.add_primitive(instance, is_light);
+    }
+
+    /// Like `instance`, for a copy of `mesh` that moves between the `start` and `end`
+    /// transforms over `time`. Rays only see it move while the camera's shutter is open.
+    /// Emissive instances whose scale changes along the way aren't sampled as lights.
+    pub fn moving_instance(
+        &mut self,
+        mesh: &Arc<Mesh>,
+        start: Transform,
+        end: Transform,
+        time: Range<f64>,
+        material: Option<&Arc<dyn Material>>,
+    ) {
+        let is_light = material.is_some_and(|material| material.is_emissive());
+        let instance: PrimArc = Arc::new(Instance::moving(
+            Arc::clone(mesh),
+            AnimatedTransform::new(start, end, time),
+            material.map(Arc::clone),
+        ));
+
+        self.add_primitive(instance, is_light);
+    }
+
+    pub fn sphere(&mut self, center: Point<f64>, radius: f64, material: &Arc<dyn Material>) {
+        let sphere: PrimArc = Arc::new(Sphere::new(center, radius, Arc::clone(material)));
+        self.add_primitive(sphere, material.is_emissive());
+    }
+
+    /// A sphere moving in a straight line from `start` to `end` over `time`
+    pub fn moving_sphere(
+        &mut self,
+        start: Point<f64>,
+        end: Point<f64>,
+        time: Range<f64>,
+        radius: f64,
+        material: &Arc<dyn Material>,
+    ) {
+        let sphere: PrimArc = Arc::new(MovingSphere::new(
+            start,
+            end,
+            time,
+            radius,
+            Arc::clone(material),
+        ));
+        self.add_primitive(sphere, material.is_emissive());
+    }
+
+    pub fn plane(&mut self, origin: Point<f64>, normal: Vec3<f64>, material: &Arc<dyn Material>) {
+        self.objects.add(Box::new(InfinitePlane::new(
+            origin,
+            normal,
+            Arc::clone(material),
+        )))
+    }
+
+    pub fn triangle(
+        &mut self,
+        a: Point<f64>,
+        b: Point<f64>,
+        c: Point<f64>,
+        material: &Arc<dyn Material>,
+    ) {
+        let tri: PrimArc = Arc::new(Tri::new(a, b, c, Arc::clone(material)));
+        self.add_primitive(tri, material.is_emissive());
+    }
+
+    /// Every primitive in the scene's BVH, in the order they were added
+    pub fn primitives(&self) -> &[Arc<dyn Primitive>] {
+        &self.primitives
+    }
+
+    /// Replaces the primitives with moved versions of them, given in the same order, e.g. for
+    /// the next frame of an animation. Lights stay registered by their position in the list.
+    ///
+    /// The next render refits the previous frame's BVH to the new positions instead of building
+    /// a new one, unless the primitives moved so much that a rebuild pays off.
+    pub fn set_primitives(&mut self, primitives: Vec<Arc<dyn Primitive>>) {
+        assert_eq!(
+            primitives.len(),
+            self.primitives.len(),
+            "the number of primitives can't change"
+        );
+
+        self.lights = LightList::new();
+        for &id in &self.light_ids {
+            self.lights.add(Arc::clone(&primitives[id]));
+        }
+
+        self.primitives = primitives;
+    }
+
+    /// Adds `prim` to the BVH, and to the lights when `is_light` and it can be sampled as one
+    fn add_primitive(&mut self, prim: PrimArc, is_light: bool) {
+        if is_light && prim.samples_uniformly() {
+            self.lights.add(Arc::clone(&prim));
+            self.light_ids.push(self.primitives.len());
+        }
+
+        self.primitives.push(prim);
+    }
+
+    pub fn diffuse_material(&mut self, color: Color) -> Arc<dyn Material> {
+        Arc::new(Lambertian::new(color))
+    }
+
+    pub fn metal_material(&mut self, color: Color, fuzz: f64) -> Arc<dyn Material> {
+        Arc::new(Metal::new(color, fuzz))
+    }
+
+    pub fn dielectric_material(&mut self, ref_idx: f64) -> Arc<dyn Material> {
+        Arc::new(Dielectric::new(ref_idx))
+    }
+
+    pub fn light_material(&mut self, color: Color) -> Arc<dyn Material> {
+        Arc::new(DiffuseLight::new(color))
+    }
+
+    pub fn sky(&mut self, sky: impl Sky + 'static) {
+        self.sky = Box::new(sky);
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn camera(
+        &mut self,
+        look_from: Point<f64>,
+        look_at: Point<f64>,
+        up: Vec3<f64>,
+        vfov: f64,
+        aspect_ratio: f64,
+        aperture: f64,
+        focus_dist: f64,
+    ) {
+        let shutter = self.camera.shutter();
+        self.camera = Camera::new(
+            look_from,
+            look_at,
+            up,
+            vfov,
+            aspect_ratio,
+            aperture,
+            focus_dist,
+        );
+        self.camera.set_shutter(shutter.start, shutter.end);
+    }
+
+    /// Keeps the camera's shutter open from `open` to `close`, blurring anything that moves in
+    /// the meantime. The shutter is instant (at time 0) by default.
+    pub fn shutter(&mut self, open: f64, close: f64) {
+        self.camera.set_shutter(open, close);
+    }
+
+    pub fn progress(&mut self, show: bool) {
+        self.show_progress = show;
+    }
+
+    /// Prints statistics about the BVH each time one is built for rendering
+    pub fn bvh_stats(&mut self, show: bool) {
+        self.show_bvh_stats = show;
+    }
+
+    pub fn bvh_config(&mut self, config: BvhConfig) {
+        self.bvh_config = config;
+    }
+
+    /// Caches the OBJ files loaded from now on in `dir`, along with the BVH built over each for
+    /// `mesh`. Later loads of an unchanged file with the same `BvhConfig` read the cache instead
+    /// of parsing and building again.
+    pub fn mesh_cache(&mut self, dir: impl Into<PathBuf>) {
+        self.mesh_cache = Some(MeshCache::new(dir));
+    }
+
+    /// Seeds the random numbers used to render, renders with the same seed are identical
+    pub fn seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    /// Problems found while loading a scene file that didn't stop it from loading, such as
+    /// faces without area dropped from an OBJ file or materials missing from its libraries. Nothing is printed, so it's up to the
+    /// caller to report them.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+}
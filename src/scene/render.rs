@@ -0,0 +1,318 @@
+use std::sync::Arc;
+
+use indicatif::{ProgressBar, ProgressStyle};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+use crate::{
+    color::Color,
+    geometry::{Ray, Vec3},
+    image::{Framebuffer, Image, Layer},
+    integrator::{Integrator, SurfaceSample},
+    object::bvh::BvhTree,
+    sampler::Sampler,
+};
+
+use super::{Scene, SceneView};
+
+impl Scene {
+    pub fn render(&self, image: &mut Image, samples_per_pixel: u32, integrator: &dyn Integrator) {
+        let view = self.view();
+        let (width, height) = (image.width(), image.height());
+        let scale = 1.0 / (samples_per_pixel as f64);
+
+        let pixels = self.render_pixels(width, height, |x, y, sampler| {
+            let mut color = Color::new(0.0, 0.0, 0.0);
+            for _ in 0..samples_per_pixel {
+                let r = self.camera_ray(x, y, width, height, sampler);
+
+                color += integrator.radiance(&view, r, sampler);
+            }
+
+            integrator.encode(color * scale)
+        });
+
+        *image = Image::from_pixels(width, height, pixels);
+        self.keep_bvh(view);
+    }
+
+    /// Renders every layer of `framebuffer` from the same set of samples
+    pub fn render_layers(
+        &self,
+        framebuffer: &mut Framebuffer,
+        samples_per_pixel: u32,
+        integrator: &dyn Integrator,
+    ) {
+        let view = self.view();
+        let (width, height) = (framebuffer.width(), framebuffer.height());
+
+        let stats = self.render_pixels(width, height, |x, y, sampler| {
+            let mut stats = PixelStats::new();
+            for _ in 0..samples_per_pixel {
+                let r = self.camera_ray(x, y, width, height, sampler);
+                let (color, surface) = integrator.radiance_with_surface(&view, r, sampler);
+
+                stats.add(color, surface);
+            }
+
+            stats
+        });
+
+        for layer in Layer::ALL {
+            let pixels = stats
+                .iter()
+                .map(|stats| match layer {
+                    Layer::Beauty => integrator.encode(stats.mean),
+                    Layer::Albedo => stats.albedo(),
+                    Layer::Normal => stats.normal(),
+                    Layer::Depth => stats.depth(),
+                    Layer::SampleCount => {
+                        let count = stats.count as f64;
+                        Color::new(count, count, count)
+                    }
+                    Layer::Variance => stats.variance(),
+                })
+                .collect();
+
+            *framebuffer.layer_mut(layer) = Image::from_pixels(width, height, pixels);
+        }
+
+        self.keep_bvh(view);
+    }
+
+    fn view(&self) -> SceneView<'_> {
+        // explicitly cloning the Arc references to the primitives
+        let primitives: Vec<_> = self.primitives.iter().map(Arc::clone).collect();
+
+        let cached = self.bvh_cache.lock().unwrap().take();
+        let (bvh, action) = match cached {
+            Some(mut bvh) if bvh.len() == primitives.len() && *bvh.config() == self.bvh_config => {
+                if bvh.update(primitives) {
+                    (bvh, "Rebuilt")
+                } else {
+                    (bvh, "Refit")
+                }
+            }
+            _ => (
+                BvhTree::build_with(primitives, self.bvh_config.clone()),
+                "Built",
+            ),
+        };
+
+        // bvh.print();
+
+        if self.show_progress {
+            eprintln!(
+                "{} BVH over {} primitives in {:.2?}",
+                action,
+                bvh.len(),
+                bvh.build_time()
+            );
+        }
+        if self.show_bvh_stats {
+            eprintln!("{}", bvh.stats());
+        }
+
+        SceneView::new(self, bvh)
+    }
+
+    /// Holds on to the BVH of a finished render for the next one to refit
+    fn keep_bvh(&self, view: SceneView) {
+        *self.bvh_cache.lock().unwrap() = Some(view.into_bvh());
+    }
+
+    /// Generates a camera ray through a random point in pixel `(x, y)`
+    fn camera_ray(
+        &self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+        sampler: &mut Sampler,
+    ) -> Ray {
+        let u = (x as f64 + sampler.next_f64()) / (width - 1) as f64;
+        let v = (y as f64 + sampler.next_f64()) / (height - 1) as f64;
+
+        self.camera.ray_at(u, v, sampler)
+    }
+
+    /// Evaluates `pixel(x, y, sampler)` for every pixel in parallel, returning the results in image
+    /// storage order (left to right, top row first).
+    ///
+    /// Each pixel gets its own sampler seeded from the scene seed and its coordinate, so the
+    /// output doesn't depend on how rayon schedules the pixels.
+    fn render_pixels<T: Send>(
+        &self,
+        width: usize,
+        height: usize,
+        pixel: impl Fn(usize, usize, &mut Sampler) -> T + Sync,
+    ) -> Vec<T> {
+        let pb = if self.show_progress {
+            let pb = ProgressBar::new((width * height) as u64);
+            pb.set_style(ProgressStyle::default_bar().template(
+                "[{elapsed_precise}] {wide_bar} ({percent}%) [{pos}px / {len}px ({per_sec})]",
+            ));
+            // pb.set_draw_delta((width * height / 100) as u64);
+            pb.set_draw_delta(1000);
+
+            Some(pb)
+        } else {
+            None
+        };
+
+        (0..width * height)
+            .into_par_iter()
+            .map(|idx| {
+                let x = idx % width;
+                let y = (height - 1) - idx / width;
+                let mut sampler = Sampler::for_pixel(self.seed, x, y);
+                let value = pixel(x, y, &mut sampler);
+
+                if let Some(pb) = &pb {
+                    pb.inc(1);
+                }
+
+                value
+            })
+            .collect()
+    }
+}
+
+/// Running statistics over the samples of a single pixel
+struct PixelStats {
+    count: u32,
+    mean: Color,
+    /// Sum of squared differences from the mean (Welford's algorithm)
+    m2: Color,
+    albedo_sum: Color,
+    normal_sum: Vec3<f64>,
+    depth_sum: f64,
+    hits: u32,
+}
+
+impl PixelStats {
+    fn new() -> Self {
+        Self {
+            count: 0,
+            mean: Color::black(),
+            m2: Color::black(),
+            albedo_sum: Color::black(),
+            normal_sum: Vec3::new(0.0, 0.0, 0.0),
+            depth_sum: 0.0,
+            hits: 0,
+        }
+    }
+
+    fn add(&mut self, color: Color, surface: Option<SurfaceSample>) {
+        self.count += 1;
+
+        let delta = color - self.mean;
+        self.mean += delta * (1.0 / self.count as f64);
+        self.m2 += delta * (color - self.mean);
+
+        if let Some(surface) = surface {
+            self.albedo_sum += surface.albedo;
+            self.normal_sum += surface.normal;
+            self.depth_sum += surface.depth;
+            self.hits += 1;
+        }
+    }
+
+    fn albedo(&self) -> Color {
+        self.albedo_sum * (1.0 / self.count.max(1) as f64)
+    }
+
+    fn normal(&self) -> Color {
+        if self.normal_sum.near_zero() {
+            return Color::black();
+        }
+
+        let n = self.normal_sum.unit();
+        Color::new(n.x(), n.y(), n.z())
+    }
+
+    fn depth(&self) -> Color {
+        let depth = self.depth_sum / self.hits.max(1) as f64;
+
+        Color::new(depth, depth, depth)
+    }
+
+    fn variance(&self) -> Color {
+        if self.count < 2 {
+            return Color::black();
+        }
+
+        self.m2 * (1.0 / (self.count - 1) as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixel_stats_variance() {
+        let mut stats = PixelStats::new();
+        for value in [1.0, 2.0, 3.0, 4.0] {
+            stats.add(Color::new(value, 0.0, 2.0 * value), None);
+        }
+
+        assert_eq!(stats.mean, Color::new(2.5, 0.0, 5.0));
+        let variance = stats.variance();
+        assert!((variance.r() - 5.0 / 3.0).abs() < 1e-12);
+        assert_eq!(variance.g(), 0.0);
+        assert!((variance.b() - 20.0 / 3.0).abs() < 1e-12);
+        assert_eq!(stats.depth(), Color::black());
+    }
+
+    #[test]
+    fn animated_frames_match_fresh_scenes() {
+        use crate::{
+            geometry::Point,
+            integrator::PathIntegrator,
+            object::{bvh::Primitive, Sphere},
+        };
+
+        let spheres = |scene: &mut Scene, frame: f64| -> Vec<Arc<dyn Primitive>> {
+            let material = scene.diffuse_material(Color::new(0.8, 0.3, 0.3));
+
+            (0..20)
+                .map(|i| {
+                    let x = (i % 5) as f64 - 2.0 + frame * (i as f64 * 0.1);
+                    let z = -2.0 - (i / 5) as f64;
+                    let center = Point::new(x, 0.0, z);
+
+                    Arc::new(Sphere::new(center, 0.4, Arc::clone(&material))) as Arc<dyn Primitive>
+                })
+                .collect()
+        };
+        let render = |scene: &Scene| {
+            let mut image = Image::new(16, 16, Color::black());
+            scene.render(&mut image, 2, &PathIntegrator::new(4));
+
+            (0..16)
+                .flat_map(|y| (0..16).map(move |x| (x, y)))
+                .map(|(x, y)| image.get(x, y))
+                .collect::<Vec<_>>()
+        };
+
+        let mut animated = Scene::new();
+        animated.progress(false);
+        for sphere in spheres(&mut animated, 0.0) {
+            animated.add_primitive(sphere, false);
+        }
+        render(&animated);
+
+        for frame in [0.5, 1.0, 8.0] {
+            let moved = spheres(&mut animated, frame);
+            animated.set_primitives(moved);
+
+            let mut fresh = Scene::new();
+            fresh.progress(false);
+            for sphere in spheres(&mut fresh, frame) {
+                fresh.add_primitive(sphere, false);
+            }
+
+            assert_eq!(render(&animated), render(&fresh));
+        }
+    }
+}
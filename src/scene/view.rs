@@ -0,0 +1,105 @@
+use std::ops::Range;
+
+use crate::{
+    color::Color,
+    geometry::{Ray, Vec3},
+    object::{
+        bvh::{BvhTree, TraversalCounts},
+        Visible, VisibleHit,
+    },
+};
+
+use super::{LightList, Scene};
+
+/// A scene prepared for rendering, with its acceleration structure built.
+/// This is what integrators query while tracing rays.
+pub struct SceneView<'a> {
+    scene: &'a Scene,
+    bvh: BvhTree,
+}
+
+pub struct SceneHit {
+    pub hit: VisibleHit,
+    /// Whether the hit lies on a primitive registered in the scene's light list.
+    /// Only primitives in the BVH are registered, so other emitters are never light-sampled.
+    /// Neither are emissive surfaces of an instanced mesh, unless the instance itself is emissive.
+    pub on_light: bool,
+}
+
+impl<'a> SceneView<'a> {
+    pub fn new(scene: &'a Scene, bvh: BvhTree) -> Self {
+        Self { scene, bvh }
+    }
+
+    /// Returns the closest hit along `r` within `t_range`
+    pub fn hit(&self, r: Ray, t_range: &Range<f64>) -> Option<SceneHit> {
+        let closest_bvh = self.bvh.bounce(r, t_range);
+        let closest_object = self.scene.objects.bounce(r, t_range);
+
+        self.closest(closest_bvh, closest_object)
+    }
+
+    /// Like `hit`, but also counts the tests made to find it. Objects outside the BVH are tested
+    /// one by one, so each counts as a primitive test.
+    pub fn hit_counted(&self, r: Ray, t_range: &Range<f64>) -> (Option<SceneHit>, TraversalCounts) {
+        let (closest_bvh, mut counts) = self.bvh.bounce_counted(r, t_range);
+        let closest_object = self.scene.objects.bounce(r, t_range);
+        counts.primitive_tests += self.scene.objects.len() as u32;
+
+        (self.closest(closest_bvh, closest_object), counts)
+    }
+
+    /// Whether anything in the scene lies along `r` within `t_range`
+    pub fn occluded(&self, r: Ray, t_range: &Range<f64>) -> bool {
+        self.bvh.occluded(r, t_range) || self.scene.objects.occluded(r, t_range)
+    }
+
+    fn closest(
+        &self,
+        closest_bvh: Option<VisibleHit>,
+        closest_object: Option<VisibleHit>,
+    ) -> Option<SceneHit> {
+        // using comparison of options (shown in test below) to take the closest, non-None hit
+        if closest_object.as_ref().map(|hit| -hit.t) > closest_bvh.as_ref().map(|hit| -hit.t) {
+            closest_object.map(|hit| SceneHit {
+                hit,
+                on_light: false,
+            })
+        } else {
+            closest_bvh.map(|hit| SceneHit {
+                on_light: hit
+                    .primitive_id
+                    .is_some_and(|id| self.scene.light_ids.binary_search(&id).is_ok()),
+                hit,
+            })
+        }
+    }
+
+    pub fn sky(&self, unit_dir: Vec3<f64>) -> Color {
+        self.scene.sky.at(unit_dir)
+    }
+
+    pub fn lights(&self) -> &LightList {
+        &self.scene.lights
+    }
+
+    pub fn bvh(&self) -> &BvhTree {
+        &self.bvh
+    }
+
+    pub fn into_bvh(self) -> BvhTree {
+        self.bvh
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn visible_hit_comparison() {
+        assert!(Some(5) > None);
+        assert!(Some(-5) > None);
+        assert!(None < Some(32));
+        assert!(None::<f64> <= None);
+        assert!(Some(23.453) > Some(-123.45));
+    }
+}
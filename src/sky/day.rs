@@ -10,6 +10,12 @@ impl Day {
     }
 }
 
+impl Default for Day {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Sky for Day {
     fn at(&self, unit_dir: Vec3<f64>) -> Color {
         let t = 0.5 * (unit_dir.y() + 1.0);
@@ -14,6 +14,6 @@ impl Uniform {
 
 impl Sky for Uniform {
     fn at(&self, _unit_dir: Vec3<f64>) -> Color {
-        return self.color;
+        self.color
     }
 }
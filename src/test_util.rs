@@ -0,0 +1,36 @@
+//! Helpers shared by the unit tests
+
+use std::{
+    fs,
+    ops::Deref,
+    path::{Path, PathBuf},
+};
+
+/// An empty directory under the system's temp directory, removed again when dropped so a failing
+/// test doesn't leave it behind
+pub(crate) struct ScratchDir(PathBuf);
+
+impl ScratchDir {
+    /// `name` has to be unique among the tests, the process id keeps concurrent runs apart
+    pub(crate) fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("bounce-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        Self(dir)
+    }
+}
+
+impl Deref for ScratchDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
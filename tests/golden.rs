@@ -0,0 +1,152 @@
+//! Renders the small reference scenes in `tests/golden/` with a fixed seed and compares them
+//! against the checked-in `.pfm` images next to them.
+//!
+//! After a change that is meant to alter the output, regenerate the references with
+//! `BOUNCE_BLESS=1 cargo test --test golden` and check the new images in.
+
+use std::{env, fs, path::Path};
+
+use bounce::{color::Color, image::Image, integrator::PathIntegrator, scene::Scene};
+
+const SEED: u64 = 0;
+
+/// Lowest peak signal-to-noise ratio (in dB) accepted between a render and its reference.
+/// Renders are deterministic, so this only leaves room for floating point differences
+/// between platforms flipping the odd sample.
+const MIN_PSNR: f64 = 35.0;
+
+fn check_golden(name: &str) {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+
+    let (mut scene, settings) = Scene::load(dir.join(format!("{}.scene", name)))
+        .unwrap_or_else(|err| panic!("{}: {}", name, err));
+    scene.progress(false);
+    scene.seed(SEED);
+
+    let mut image = Image::new(settings.width, settings.height, Color::black());
+    let integrator = PathIntegrator::new(settings.max_depth);
+    scene.render(&mut image, settings.samples_per_pixel, &integrator);
+
+    let reference_path = dir.join(format!("{}.pfm", name));
+    if env::var_os("BOUNCE_BLESS").is_some() {
+        image.save(&reference_path).unwrap();
+        return;
+    }
+
+    let reference = Image::load(&reference_path).unwrap_or_else(|err| {
+        panic!(
+            "{}: {} (run with BOUNCE_BLESS=1 to create it)",
+            reference_path.display(),
+            err
+        )
+    });
+    assert_eq!(
+        (image.width(), image.height()),
+        (reference.width(), reference.height()),
+        "{}: render size differs from the reference",
+        name
+    );
+
+    let rmse = rmse(&image, &reference);
+    let psnr = -20.0 * rmse.log10();
+
+    if psnr < MIN_PSNR {
+        let out = Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden");
+        fs::create_dir_all(&out).unwrap();
+
+        let actual_path = out.join(format!("{}.png", name));
+        let expected_path = out.join(format!("{}.expected.png", name));
+        let diff_path = out.join(format!("{}.diff.png", name));
+        image.save(&actual_path).unwrap();
+        reference.save(&expected_path).unwrap();
+        diff(&image, &reference).save(&diff_path).unwrap();
+
+        panic!(
+            "{}: PSNR {:.2} dB is below {} dB (RMSE {:.5})\n  render:    {}\n  reference: {}\n  diff:      {}",
+            name,
+            psnr,
+            MIN_PSNR,
+            rmse,
+            actual_path.display(),
+            expected_path.display(),
+            diff_path.display()
+        );
+    }
+}
+
+/// Root mean squared error over every channel, with values clamped to the displayable range
+fn rmse(a: &Image, b: &Image) -> f64 {
+    let mut sum = 0.0;
+
+    for y in 0..a.height() {
+        for x in 0..a.width() {
+            let (p, q) = (a.get(x, y), b.get(x, y));
+
+            for (u, v) in [(p.r(), q.r()), (p.g(), q.g()), (p.b(), q.b())] {
+                sum += (u.clamp(0.0, 1.0) - v.clamp(0.0, 1.0)).powi(2);
+            }
+        }
+    }
+
+    (sum / (a.width() * a.height() * 3) as f64).sqrt()
+}
+
+/// Per-channel absolute difference, scaled so the largest difference is white
+fn diff(a: &Image, b: &Image) -> Image {
+    let mut pixels = Vec::new();
+
+    for y in (0..a.height()).rev() {
+        for x in 0..a.width() {
+            let (p, q) = (a.get(x, y), b.get(x, y));
+
+            pixels.push(Color::new(
+                (p.r() - q.r()).abs(),
+                (p.g() - q.g()).abs(),
+                (p.b() - q.b()).abs(),
+            ));
+        }
+    }
+
+    let max = pixels
+        .iter()
+        .map(|c| c.r().max(c.g()).max(c.b()))
+        .fold(0.0, f64::max);
+    let scale = if max > 0.0 { 1.0 / max } else { 0.0 };
+
+    Image::from_pixels(a.width(), a.height(), pixels).map(|c| c * scale)
+}
+
+#[test]
+fn golden_dielectric() {
+    check_golden("dielectric");
+}
+
+#[test]
+fn golden_camera() {
+    check_golden("camera");
+}
+
+#[test]
+fn golden_bvh() {
+    check_golden("bvh");
+}
+
+#[test]
+fn golden_cornell() {
+    check_golden("cornell");
+}
+
+#[test]
+fn golden_instances() {
+    check_golden("instances");
+}
+
+#[test]
+fn golden_motion() {
+    check_golden("motion");
+}
+
+#[test]
+fn golden_smooth() {
+    check_golden("smooth");
+}